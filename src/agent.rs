@@ -0,0 +1,341 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+use crate::{
+    cache::CacheState,
+    filter::IgnoreConfig,
+    provider::{ChatMessage, CompletionRequest, Provider, Role, ToolCall, ToolDefinition},
+    sandbox::{shadow_run_with_options, ShadowOptions},
+    storage::load_kill_switch,
+    usage::estimate_tokens,
+    vector::{LocalVectorStore, VectorStore},
+};
+
+const MAX_READ_BYTES: usize = 32_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentConfig {
+    pub max_steps: usize,
+    pub max_tokens: u64,
+    pub allow_exec: bool,
+    pub image: String,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            max_steps: 12,
+            max_tokens: 60_000,
+            allow_exec: false,
+            image: "ubuntu:22.04".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentStop {
+    Finished,
+    StepLimit,
+    TokenLimit,
+    KillSwitch,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStep {
+    pub step: usize,
+    pub tool: String,
+    pub arguments: serde_json::Value,
+    pub result: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProposedEdit {
+    pub path: String,
+    pub content: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentOutcome {
+    pub goal: String,
+    pub answer: Option<String>,
+    pub stop: AgentStop,
+    pub steps: Vec<AgentStep>,
+    pub proposed_edits: Vec<ProposedEdit>,
+    pub tokens_used: u64,
+}
+
+pub struct Agent {
+    pub cache: CacheState,
+    pub vectors: LocalVectorStore,
+    pub config: AgentConfig,
+    pub ignore: IgnoreConfig,
+    pub kill_switch: PathBuf,
+}
+
+impl Agent {
+    pub fn tools() -> Vec<ToolDefinition> {
+        vec![
+            tool(
+                "list_files",
+                "List repository files known to the cache, optionally under a path prefix.",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": {"prefix": {"type": "string"}}
+                }),
+            ),
+            tool(
+                "read_file",
+                "Read a repository file by its path relative to the root.",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": {"path": {"type": "string"}},
+                    "required": ["path"]
+                }),
+            ),
+            tool(
+                "search_vectors",
+                "Search the vector store for documents related to a query.",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "query": {"type": "string"},
+                        "top_k": {"type": "integer"}
+                    },
+                    "required": ["query"]
+                }),
+            ),
+            tool(
+                "propose_edit",
+                "Propose the full new content of a file. Edits are collected for review, not applied.",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {"type": "string"},
+                        "content": {"type": "string"},
+                        "reason": {"type": "string"}
+                    },
+                    "required": ["path", "content"]
+                }),
+            ),
+            tool(
+                "run_command",
+                "Shadow-run a shell command inside the sandbox against a copy of the repository.",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": {"command": {"type": "string"}},
+                    "required": ["command"]
+                }),
+            ),
+        ]
+    }
+
+    pub fn run(
+        &self,
+        provider: &dyn Provider,
+        goal: &str,
+        on_step: &mut dyn FnMut(&AgentStep),
+    ) -> anyhow::Result<AgentOutcome> {
+        let mut outcome = AgentOutcome {
+            goal: goal.to_string(),
+            answer: None,
+            stop: AgentStop::StepLimit,
+            steps: Vec::new(),
+            proposed_edits: Vec::new(),
+            tokens_used: 0,
+        };
+        let mut messages = vec![
+            ChatMessage::new(Role::System, &system_prompt(&self.cache)),
+            ChatMessage::user(goal),
+        ];
+        let tools = Self::tools();
+
+        for step in 1..=self.config.max_steps {
+            if self.halted() {
+                outcome.stop = AgentStop::KillSwitch;
+                return Ok(outcome);
+            }
+            if outcome.tokens_used >= self.config.max_tokens {
+                outcome.stop = AgentStop::TokenLimit;
+                return Ok(outcome);
+            }
+
+            let request = CompletionRequest::new(&messages).with_tools(tools.clone());
+            let completion = provider.complete_request(&request)?;
+            outcome.tokens_used += match completion.usage {
+                Some(usage) => usage.prompt_tokens + usage.completion_tokens,
+                None => {
+                    messages
+                        .iter()
+                        .map(|message| estimate_tokens(&message.content) as u64)
+                        .sum::<u64>()
+                        + estimate_tokens(&completion.text) as u64
+                }
+            };
+
+            if completion.tool_calls.is_empty() {
+                outcome.answer = Some(completion.text);
+                outcome.stop = AgentStop::Finished;
+                return Ok(outcome);
+            }
+
+            messages.push(ChatMessage::tool_use(
+                &completion.text,
+                completion.tool_calls.clone(),
+            ));
+            for call in &completion.tool_calls {
+                if self.halted() {
+                    outcome.stop = AgentStop::KillSwitch;
+                    return Ok(outcome);
+                }
+                let result = self
+                    .execute(call, &mut outcome.proposed_edits)
+                    .unwrap_or_else(|err| format!("Error: {}", err));
+                messages.push(ChatMessage::tool_result(call, &result));
+                let record = AgentStep {
+                    step,
+                    tool: call.name.clone(),
+                    arguments: call.arguments.clone(),
+                    result,
+                };
+                on_step(&record);
+                outcome.steps.push(record);
+            }
+        }
+        Ok(outcome)
+    }
+
+    fn halted(&self) -> bool {
+        load_kill_switch(&self.kill_switch).unwrap_or(true)
+    }
+
+    fn execute(&self, call: &ToolCall, edits: &mut Vec<ProposedEdit>) -> anyhow::Result<String> {
+        let args = &call.arguments;
+        match call.name.as_str() {
+            "list_files" => {
+                let prefix = args["prefix"].as_str().unwrap_or("");
+                let files: Vec<&str> = self
+                    .cache
+                    .files
+                    .keys()
+                    .filter(|path| path.starts_with(prefix))
+                    .map(String::as_str)
+                    .collect();
+                Ok(files.join("\n"))
+            }
+            "read_file" => {
+                let path = required(args, "path")?;
+                if !self.cache.files.contains_key(path) {
+                    return Err(anyhow::anyhow!("{} is not in the cached repository", path));
+                }
+                let mut contents = std::fs::read_to_string(self.cache.root.join(safe_path(path)?))?;
+                if contents.len() > MAX_READ_BYTES {
+                    let mut end = MAX_READ_BYTES;
+                    while !contents.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    contents.truncate(end);
+                    contents.push_str("\n[truncated]");
+                }
+                Ok(contents)
+            }
+            "search_vectors" => {
+                let query = required(args, "query")?;
+                let top_k = args["top_k"].as_u64().unwrap_or(3) as usize;
+                let matches = self.vectors.query(query, top_k)?;
+                let lines: Vec<String> = matches
+                    .iter()
+                    .map(|hit| {
+                        let content = self
+                            .vectors
+                            .documents
+                            .iter()
+                            .find(|doc| doc.id == hit.id)
+                            .map(|doc| doc.content.chars().take(400).collect::<String>())
+                            .unwrap_or_default();
+                        format!("{} ({:.3}): {}", hit.id, hit.score, content)
+                    })
+                    .collect();
+                if lines.is_empty() {
+                    Ok("No matches.".to_string())
+                } else {
+                    Ok(lines.join("\n"))
+                }
+            }
+            "propose_edit" => {
+                let path = required(args, "path")?;
+                safe_path(path)?;
+                edits.push(ProposedEdit {
+                    path: path.to_string(),
+                    content: required(args, "content")?.to_string(),
+                    reason: args["reason"].as_str().unwrap_or("").to_string(),
+                });
+                Ok(format!("Edit to {} recorded for review.", path))
+            }
+            "run_command" => {
+                let command = required(args, "command")?;
+                let result = shadow_run_with_options(
+                    command,
+                    ShadowOptions {
+                        root: self.cache.root.clone(),
+                        image: self.config.image.clone(),
+                        allow_exec: self.config.allow_exec,
+                        hydrate: false,
+                        ignore: self.ignore.clone(),
+                    },
+                )?;
+                Ok(format!(
+                    "exit status: {}\n{}",
+                    result
+                        .status
+                        .map(|code| code.to_string())
+                        .unwrap_or_else(|| "none".to_string()),
+                    result.output
+                ))
+            }
+            other => Err(anyhow::anyhow!("Unknown tool: {}", other)),
+        }
+    }
+}
+
+fn tool(name: &str, description: &str, parameters: serde_json::Value) -> ToolDefinition {
+    ToolDefinition {
+        name: name.to_string(),
+        description: description.to_string(),
+        parameters,
+    }
+}
+
+fn required<'a>(args: &'a serde_json::Value, key: &str) -> anyhow::Result<&'a str> {
+    args[key]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("Missing string argument `{}`", key))
+}
+
+fn safe_path(path: &str) -> anyhow::Result<&Path> {
+    let path = Path::new(path);
+    if path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        Ok(path)
+    } else {
+        Err(anyhow::anyhow!(
+            "{} escapes the repository root",
+            path.display()
+        ))
+    }
+}
+
+fn system_prompt(cache: &CacheState) -> String {
+    format!(
+        "You are Nexus, a coding agent working in the repository at {} ({} cached files). \
+         Use the tools to inspect files, search the vector store and run commands in the sandbox. \
+         Propose edits with propose_edit instead of describing them. \
+         Reply without tool calls once the goal is done.",
+        cache.root.display(),
+        cache.files.len()
+    )
+}
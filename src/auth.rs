@@ -0,0 +1,230 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
+
+use crate::{
+    provider::ProviderKind,
+    storage::{load_auth, save_auth},
+};
+
+const DEVICE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+const GOOGLE_DEVICE_URL: &str = "https://oauth2.googleapis.com/device/code";
+const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const GOOGLE_SCOPE: &str = "https://www.googleapis.com/auth/generative-language";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OAuthConfig {
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub device_authorization_url: Option<String>,
+    pub token_url: Option<String>,
+    pub scope: Option<String>,
+}
+
+impl OAuthConfig {
+    fn device_url(&self, provider: &str) -> anyhow::Result<String> {
+        self.device_authorization_url
+            .clone()
+            .or_else(|| (provider == "gemini").then(|| GOOGLE_DEVICE_URL.to_string()))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Set {}.oauth.device_authorization_url in nexus.toml",
+                    provider
+                )
+            })
+    }
+
+    fn token_endpoint(&self, provider: &str) -> anyhow::Result<String> {
+        self.token_url
+            .clone()
+            .or_else(|| (provider == "gemini").then(|| GOOGLE_TOKEN_URL.to_string()))
+            .ok_or_else(|| anyhow::anyhow!("Set {}.oauth.token_url in nexus.toml", provider))
+    }
+
+    fn scope_for(&self, provider: &str) -> Option<String> {
+        self.scope
+            .clone()
+            .or_else(|| (provider == "gemini").then(|| GOOGLE_SCOPE.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthToken {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_at: Option<u64>,
+}
+
+impl OAuthToken {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.map(|at| now + 30 >= at).unwrap_or(false)
+    }
+
+    fn from_response(response: &serde_json::Value) -> anyhow::Result<Self> {
+        let access_token = response["access_token"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Token response has no access_token"))?;
+        Ok(Self {
+            access_token: access_token.to_string(),
+            refresh_token: response["refresh_token"].as_str().map(str::to_string),
+            expires_at: response["expires_in"]
+                .as_u64()
+                .map(|seconds| now_ts() + seconds),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuthStore {
+    pub tokens: BTreeMap<String, OAuthToken>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceCode {
+    pub user_code: String,
+    pub verification_uri: String,
+    pub verification_uri_complete: Option<String>,
+    pub expires_in: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct OAuthSession {
+    pub provider: String,
+    pub config: OAuthConfig,
+    pub store: PathBuf,
+}
+
+impl OAuthSession {
+    pub fn access_token(&self) -> anyhow::Result<Option<String>> {
+        let mut store = load_auth(&self.store)?;
+        let Some(token) = store.tokens.get(&self.provider).cloned() else {
+            return Ok(None);
+        };
+        if !token.is_expired(now_ts()) {
+            return Ok(Some(token.access_token));
+        }
+        let Some(refresh_token) = token.refresh_token.as_deref() else {
+            return Err(anyhow::anyhow!(
+                "{} login expired; run `nexus auth login {}`",
+                self.provider,
+                self.provider
+            ));
+        };
+        let mut fresh = refresh(&self.provider, &self.config, refresh_token)?;
+        if fresh.refresh_token.is_none() {
+            fresh.refresh_token = token.refresh_token.clone();
+        }
+        let access_token = fresh.access_token.clone();
+        store.tokens.insert(self.provider.clone(), fresh);
+        save_auth(&store, &self.store)?;
+        Ok(Some(access_token))
+    }
+}
+
+pub fn provider_key(kind: &ProviderKind) -> String {
+    serde_json::to_value(kind)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+pub fn device_login(
+    provider: &str,
+    config: &OAuthConfig,
+    on_code: &mut dyn FnMut(&DeviceCode),
+) -> anyhow::Result<OAuthToken> {
+    let client = reqwest::blocking::Client::new();
+    let mut form = vec![("client_id", config.client_id.clone())];
+    if let Some(scope) = config.scope_for(provider) {
+        form.push(("scope", scope));
+    }
+    let response: serde_json::Value = client
+        .post(config.device_url(provider)?)
+        .form(&form)
+        .send()?
+        .json()?;
+    let device_code = response["device_code"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("Device authorization failed: {}", response))?
+        .to_string();
+    let code = DeviceCode {
+        user_code: response["user_code"].as_str().unwrap_or("").to_string(),
+        verification_uri: response["verification_uri"]
+            .as_str()
+            .or_else(|| response["verification_url"].as_str())
+            .unwrap_or("")
+            .to_string(),
+        verification_uri_complete: response["verification_uri_complete"]
+            .as_str()
+            .map(str::to_string),
+        expires_in: response["expires_in"].as_u64().unwrap_or(900),
+    };
+    on_code(&code);
+
+    let token_url = config.token_endpoint(provider)?;
+    let mut interval = response["interval"].as_u64().unwrap_or(5);
+    let deadline = now_ts() + code.expires_in;
+    while now_ts() <= deadline {
+        std::thread::sleep(Duration::from_secs(interval));
+        let mut form = vec![
+            ("client_id", config.client_id.clone()),
+            ("device_code", device_code.clone()),
+            ("grant_type", DEVICE_GRANT.to_string()),
+        ];
+        if let Some(secret) = &config.client_secret {
+            form.push(("client_secret", secret.clone()));
+        }
+        let response: serde_json::Value = client.post(&token_url).form(&form).send()?.json()?;
+        match response["error"].as_str() {
+            None => return OAuthToken::from_response(&response),
+            Some("authorization_pending") => {}
+            Some("slow_down") => interval += 5,
+            Some(error) => {
+                return Err(anyhow::anyhow!(
+                    "Login failed: {} {}",
+                    error,
+                    response["error_description"].as_str().unwrap_or("")
+                ))
+            }
+        }
+    }
+    Err(anyhow::anyhow!(
+        "Device code expired before login completed"
+    ))
+}
+
+pub fn refresh(
+    provider: &str,
+    config: &OAuthConfig,
+    refresh_token: &str,
+) -> anyhow::Result<OAuthToken> {
+    let mut form = vec![
+        ("client_id", config.client_id.clone()),
+        ("refresh_token", refresh_token.to_string()),
+        ("grant_type", "refresh_token".to_string()),
+    ];
+    if let Some(secret) = &config.client_secret {
+        form.push(("client_secret", secret.clone()));
+    }
+    let response: serde_json::Value = reqwest::blocking::Client::new()
+        .post(config.token_endpoint(provider)?)
+        .form(&form)
+        .send()?
+        .json()?;
+    if let Some(error) = response["error"].as_str() {
+        return Err(anyhow::anyhow!(
+            "Refreshing {} login failed: {}; run `nexus auth login {}`",
+            provider,
+            error,
+            provider
+        ));
+    }
+    OAuthToken::from_response(&response)
+}
+
+fn now_ts() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
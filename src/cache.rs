@@ -1,17 +1,45 @@
 use blake3::Hasher;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use similar::TextDiff;
 use std::{
     collections::BTreeMap,
     fs,
     path::{Path, PathBuf},
+    time::{Instant, UNIX_EPOCH},
+};
+
+use crate::{
+    classify::{classify_file, FileClass},
+    context::MAX_BLOB_BYTES,
+    filter::{IgnoreConfig, RepoFilter},
 };
-use walkdir::WalkDir;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileMeta {
     pub modified: Option<u64>,
     pub size: u64,
     pub hash: String,
+    #[serde(default)]
+    pub modified_ns: Option<u64>,
+    #[serde(default)]
+    pub inode: Option<u64>,
+    #[serde(default)]
+    pub class: Option<FileClass>,
+}
+
+impl FileMeta {
+    fn unchanged(&self, other: &FileMeta) -> bool {
+        self.size == other.size
+            && self.modified_ns.is_some()
+            && self.modified_ns == other.modified_ns
+            && self.inode == other.inode
+            && self.class.is_some()
+    }
+
+    pub fn class(&self) -> FileClass {
+        self.class.unwrap_or_default()
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -28,26 +56,92 @@ impl CacheState {
         }
     }
 
-    pub fn warm(&mut self) -> anyhow::Result<()> {
-        self.files.clear();
-        for entry in WalkDir::new(&self.root).into_iter().filter_map(Result::ok) {
-            if entry.file_type().is_file() {
-                let path = entry.path();
+    pub fn warm(&mut self) -> anyhow::Result<WarmStats> {
+        self.warm_with(&IgnoreConfig::default())
+    }
+
+    pub fn warm_with(&mut self, rules: &IgnoreConfig) -> anyhow::Result<WarmStats> {
+        let previous = std::mem::take(&mut self.files);
+        self.rescan(&previous, rules)
+    }
+
+    pub fn warm_from(
+        &mut self,
+        previous: &CacheState,
+        rules: &IgnoreConfig,
+    ) -> anyhow::Result<WarmStats> {
+        if previous.root == self.root {
+            self.rescan(&previous.files, rules)
+        } else {
+            self.rescan(&BTreeMap::new(), rules)
+        }
+    }
+
+    fn rescan(
+        &mut self,
+        previous: &BTreeMap<String, FileMeta>,
+        rules: &IgnoreConfig,
+    ) -> anyhow::Result<WarmStats> {
+        let start = Instant::now();
+        let filter = RepoFilter::new(&self.root, rules)?;
+        let paths: Vec<PathBuf> = filter.files().collect();
+        let scanned = paths
+            .par_iter()
+            .map(|path| {
                 let rel = path
                     .strip_prefix(&self.root)
                     .unwrap_or(path)
                     .to_string_lossy()
                     .to_string();
-                let meta = metadata_for(path)?;
-                self.files.insert(rel, meta);
+                let mut meta = stat_file(path)?;
+                let reused = match previous.get(&rel) {
+                    Some(known) if known.unchanged(&meta) => {
+                        meta.hash = known.hash.clone();
+                        meta.class = known.class;
+                        true
+                    }
+                    _ => {
+                        meta.hash = hash_file(path)?;
+                        meta.class = Some(classify_file(&self.root, &rel, meta.size)?);
+                        false
+                    }
+                };
+                Ok((rel, meta, reused))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut stats = WarmStats::default();
+        self.files.clear();
+        for (rel, meta, reused) in scanned {
+            stats.files += 1;
+            if reused {
+                stats.reused += 1;
+            } else {
+                stats.hashed += 1;
+                stats.bytes_hashed += meta.size;
             }
+            self.files.insert(rel, meta);
+        }
+        stats.elapsed_ms = start.elapsed().as_millis() as u64;
+        Ok(stats)
+    }
+
+    pub fn class_counts(&self) -> BTreeMap<FileClass, usize> {
+        let mut counts = BTreeMap::new();
+        for meta in self.files.values() {
+            *counts.entry(meta.class()).or_insert(0) += 1;
         }
-        Ok(())
+        counts
     }
 
     pub fn diff(&self, other: &CacheState) -> CacheDiff {
+        self.diff_with(other, None)
+    }
+
+    pub fn diff_with(&self, other: &CacheState, similarity: Option<(f64, &Path)>) -> CacheDiff {
         let mut changed = Vec::new();
         let mut removed = Vec::new();
+        let mut added = Vec::new();
 
         for (path, meta) in &self.files {
             match other.files.get(path) {
@@ -62,11 +156,79 @@ impl CacheState {
 
         for path in other.files.keys() {
             if !self.files.contains_key(path) {
-                changed.push(path.clone());
+                added.push(path.clone());
+            }
+        }
+
+        let mut renamed: Vec<(String, String)> = Vec::new();
+        let mut by_hash: BTreeMap<&str, Vec<&String>> = BTreeMap::new();
+        for path in removed.iter().rev() {
+            let meta = &self.files[path];
+            if meta.size > 0 {
+                by_hash.entry(&meta.hash).or_default().push(path);
+            }
+        }
+        added.retain(|path| {
+            let candidates = by_hash.get_mut(other.files[path].hash.as_str());
+            match candidates.and_then(|paths| paths.pop()) {
+                Some(from) => {
+                    renamed.push((from.clone(), path.clone()));
+                    false
+                }
+                None => true,
+            }
+        });
+        removed.retain(|path| !renamed.iter().any(|(from, _)| from == path));
+
+        if let Some((threshold, blobs)) = similarity {
+            let mut previous: BTreeMap<String, Option<String>> = BTreeMap::new();
+            for path in &added {
+                let meta = &other.files[path];
+                if meta.size > MAX_BLOB_BYTES {
+                    continue;
+                }
+                let Ok(current) = fs::read_to_string(other.root.join(path)) else {
+                    continue;
+                };
+                let mut best: Option<(f64, bool, String)> = None;
+                for from in &removed {
+                    let old = &self.files[from];
+                    let ratio = size_ratio(old.size, meta.size);
+                    if old.size > MAX_BLOB_BYTES || 2.0 * ratio / (1.0 + ratio) < threshold {
+                        continue;
+                    }
+                    let Some(content) = previous
+                        .entry(old.hash.clone())
+                        .or_insert_with(|| fs::read_to_string(blobs.join(&old.hash)).ok())
+                    else {
+                        continue;
+                    };
+                    let score = TextDiff::from_lines(content.as_str(), current.as_str()).ratio();
+                    let candidate = (
+                        f64::from(score),
+                        file_name(from) == file_name(path),
+                        from.clone(),
+                    );
+                    let better = best.as_ref().is_none_or(|(score, same_name, _)| {
+                        candidate.0.total_cmp(score).then(candidate.1.cmp(same_name)).is_gt()
+                    });
+                    if candidate.0 >= threshold && better {
+                        best = Some(candidate);
+                    }
+                }
+                if let Some((_, _, from)) = best {
+                    removed.retain(|path| *path != from);
+                    renamed.push((from, path.clone()));
+                }
             }
         }
 
-        CacheDiff { changed, removed }
+        changed.extend(added);
+        CacheDiff {
+            changed,
+            removed,
+            renamed,
+        }
     }
 }
 
@@ -74,25 +236,78 @@ impl CacheState {
 pub struct CacheDiff {
     pub changed: Vec<String>,
     pub removed: Vec<String>,
+    #[serde(default)]
+    pub renamed: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoCacheEntry {
+    pub key: String,
+    pub root: PathBuf,
+    pub files: usize,
+    pub disk_bytes: u64,
+    pub updated_at: u64,
 }
 
-fn metadata_for(path: &Path) -> anyhow::Result<FileMeta> {
+impl RepoCacheEntry {
+    pub fn is_orphaned(&self) -> bool {
+        !self.root.as_os_str().is_empty() && !self.root.is_dir()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WarmStats {
+    pub files: usize,
+    pub hashed: usize,
+    pub reused: usize,
+    pub bytes_hashed: u64,
+    pub elapsed_ms: u64,
+}
+
+impl WarmStats {
+    pub fn summary(&self) -> String {
+        format!(
+            "{} files ({} rehashed, {} reused, {} bytes hashed) in {} ms",
+            self.files, self.hashed, self.reused, self.bytes_hashed, self.elapsed_ms
+        )
+    }
+}
+
+fn stat_file(path: &Path) -> anyhow::Result<FileMeta> {
     let meta = fs::metadata(path)?;
-    let modified = meta
+    let since_epoch = meta
         .modified()
         .ok()
-        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
-        .map(|dur| dur.as_secs());
-    let size = meta.len();
-    let hash = hash_file(path)?;
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+    #[cfg(unix)]
+    let inode = {
+        use std::os::unix::fs::MetadataExt;
+        Some(meta.ino())
+    };
+    #[cfg(not(unix))]
+    let inode = None;
 
     Ok(FileMeta {
-        modified,
-        size,
-        hash,
+        modified: since_epoch.map(|dur| dur.as_secs()),
+        size: meta.len(),
+        hash: String::new(),
+        modified_ns: since_epoch.and_then(|dur| u64::try_from(dur.as_nanos()).ok()),
+        inode,
+        class: None,
     })
 }
 
+fn file_name(path: &str) -> Option<&std::ffi::OsStr> {
+    Path::new(path).file_name()
+}
+
+fn size_ratio(left: u64, right: u64) -> f64 {
+    if left == right {
+        return 1.0;
+    }
+    left.min(right) as f64 / left.max(right) as f64
+}
+
 fn hash_file(path: &Path) -> anyhow::Result<String> {
     let mut hasher = Hasher::new();
     let mut file = fs::File::open(path)?;
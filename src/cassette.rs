@@ -0,0 +1,214 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::provider::{
+    ChatMessage, Completion, CompletionRequest, Provider, ProviderKind, ToolCall, ToolDefinition,
+    Usage,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CassetteMode {
+    Record,
+    Replay,
+    Auto,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CassetteConfig {
+    pub path: Option<String>,
+    pub mode: CassetteMode,
+}
+
+impl Default for CassetteConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            mode: CassetteMode::Auto,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub key: String,
+    pub provider: ProviderKind,
+    #[serde(default)]
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub response: String,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    pub recorded_at: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let raw = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&raw)?)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let data = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+}
+
+pub struct CassetteProvider {
+    pub inner: Box<dyn Provider>,
+    pub path: PathBuf,
+    pub mode: CassetteMode,
+    cassette: RefCell<Cassette>,
+    played: RefCell<BTreeMap<String, usize>>,
+}
+
+impl CassetteProvider {
+    pub fn new(
+        inner: Box<dyn Provider>,
+        path: PathBuf,
+        mode: CassetteMode,
+        cassette: Cassette,
+    ) -> Self {
+        Self {
+            inner,
+            path,
+            mode,
+            cassette: RefCell::new(cassette),
+            played: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    fn replay(&self, key: &str) -> Option<Completion> {
+        let cassette = self.cassette.borrow();
+        let matches: Vec<&Interaction> = cassette
+            .interactions
+            .iter()
+            .filter(|interaction| interaction.key == key)
+            .collect();
+        let mut played = self.played.borrow_mut();
+        let count = played.entry(key.to_string()).or_insert(0);
+        let hit = matches.get(*count).or_else(|| matches.last())?;
+        *count += 1;
+        Some(Completion::new(
+            hit.provider.clone(),
+            &hit.model,
+            hit.response.clone(),
+            hit.usage,
+        )
+        .with_tool_calls(hit.tool_calls.clone()))
+    }
+
+    fn play(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        on_chunk: &mut dyn FnMut(&str),
+        call: impl FnOnce(&dyn Provider, &mut dyn FnMut(&str)) -> anyhow::Result<Completion>,
+    ) -> anyhow::Result<Completion> {
+        let key = interaction_key(messages, tools)?;
+        if self.mode != CassetteMode::Record {
+            if let Some(completion) = self.replay(&key) {
+                on_chunk(&completion.text);
+                return Ok(completion);
+            }
+            if self.mode == CassetteMode::Replay {
+                return Err(anyhow::anyhow!(
+                    "No recorded interaction in {} for this request",
+                    self.path.display()
+                ));
+            }
+        }
+        let completion = call(self.inner.as_ref(), on_chunk)?;
+        self.record(key, messages, &completion)?;
+        Ok(completion)
+    }
+
+    fn record(
+        &self,
+        key: String,
+        messages: &[ChatMessage],
+        completion: &Completion,
+    ) -> anyhow::Result<()> {
+        let mut cassette = self.cassette.borrow_mut();
+        cassette.interactions.push(Interaction {
+            key,
+            provider: completion.provider.clone(),
+            model: completion.model.clone(),
+            messages: messages.to_vec(),
+            response: completion.text.clone(),
+            usage: completion.usage,
+            tool_calls: completion.tool_calls.clone(),
+            recorded_at: now_ts(),
+        });
+        cassette.save(&self.path)
+    }
+}
+
+impl Provider for CassetteProvider {
+    fn kind(&self) -> ProviderKind {
+        self.inner.kind()
+    }
+
+    fn display_name(&self) -> &'static str {
+        self.inner.display_name()
+    }
+
+    fn dry_run_prompt(&self, input: &str) -> String {
+        self.inner.dry_run_prompt(input)
+    }
+
+    fn complete_request(&self, request: &CompletionRequest) -> anyhow::Result<Completion> {
+        self.play(&request.messages, &request.tools, &mut |_| {}, |inner, _| {
+            inner.complete_request(request)
+        })
+    }
+
+    fn complete_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<Completion> {
+        self.play(messages, &[], on_chunk, |inner, on_chunk| {
+            inner.complete_stream(messages, on_chunk)
+        })
+    }
+
+    fn list_models(&self) -> anyhow::Result<Vec<String>> {
+        self.inner.list_models()
+    }
+}
+
+fn interaction_key(messages: &[ChatMessage], tools: &[ToolDefinition]) -> anyhow::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&serde_json::to_vec(messages)?);
+    if !tools.is_empty() {
+        hasher.update(&serde_json::to_vec(tools)?);
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+fn now_ts() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+use std::{io::Read, path::Path};
+
+use crate::context::MAX_BLOB_BYTES;
+
+const HEAD_BYTES: usize = 8 * 1024;
+const MINIFIED_MIN_BYTES: usize = 1024;
+const MINIFIED_AVG_LINE: usize = 200;
+const GENERATED_HEADER_LINES: usize = 5;
+
+const LOCKFILES: [&str; 13] = [
+    "Cargo.lock",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "bun.lockb",
+    "poetry.lock",
+    "Pipfile.lock",
+    "uv.lock",
+    "Gemfile.lock",
+    "composer.lock",
+    "flake.lock",
+    "go.sum",
+];
+const VENDOR_DIRS: [&str; 6] = [
+    "vendor",
+    "vendored",
+    "third_party",
+    "third-party",
+    "node_modules",
+    "bower_components",
+];
+const GENERATED_SUFFIXES: [&str; 6] = [
+    ".pb.go",
+    "_pb2.py",
+    "_pb2_grpc.py",
+    ".g.dart",
+    ".freezed.dart",
+    ".js.map",
+];
+const GENERATED_MARKERS: [&str; 5] = [
+    "@generated",
+    "do not edit",
+    "code generated",
+    "auto-generated",
+    "autogenerated",
+];
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum FileClass {
+    #[default]
+    Text,
+    Binary,
+    Generated,
+    Lockfile,
+    Minified,
+    Vendored,
+    TooLarge,
+}
+
+impl FileClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Binary => "binary",
+            Self::Generated => "generated",
+            Self::Lockfile => "lockfile",
+            Self::Minified => "minified",
+            Self::Vendored => "vendored",
+            Self::TooLarge => "too_large",
+        }
+    }
+}
+
+impl std::str::FromStr for FileClass {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(value.to_string())).map_err(|_| {
+            anyhow::anyhow!(
+                "Unknown file class: {} (text, binary, generated, lockfile, minified, vendored, too_large)",
+                value
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClassPolicy {
+    Include,
+    #[default]
+    Placeholder,
+    Skip,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClassPolicies {
+    pub binary: ClassPolicy,
+    pub generated: ClassPolicy,
+    pub lockfile: ClassPolicy,
+    pub minified: ClassPolicy,
+    pub vendored: ClassPolicy,
+    pub too_large: ClassPolicy,
+}
+
+impl Default for ClassPolicies {
+    fn default() -> Self {
+        Self {
+            binary: ClassPolicy::Placeholder,
+            generated: ClassPolicy::Placeholder,
+            lockfile: ClassPolicy::Placeholder,
+            minified: ClassPolicy::Placeholder,
+            vendored: ClassPolicy::Skip,
+            too_large: ClassPolicy::Placeholder,
+        }
+    }
+}
+
+impl ClassPolicies {
+    pub fn policy(&self, class: FileClass) -> ClassPolicy {
+        match class {
+            FileClass::Text => ClassPolicy::Include,
+            FileClass::Binary => self.binary,
+            FileClass::Generated => self.generated,
+            FileClass::Lockfile => self.lockfile,
+            FileClass::Minified => self.minified,
+            FileClass::Vendored => self.vendored,
+            FileClass::TooLarge => self.too_large,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Placeholder {
+    pub path: String,
+    pub class: FileClass,
+    pub size: u64,
+    pub hash: String,
+}
+
+impl Placeholder {
+    pub fn describe(&self) -> String {
+        format!(
+            "{} ({}, {} bytes, blake3 {})",
+            self.path,
+            self.class.as_str(),
+            self.size,
+            &self.hash[..self.hash.len().min(12)]
+        )
+    }
+}
+
+pub fn classify_file(root: &Path, rel: &str, size: u64) -> anyhow::Result<FileClass> {
+    let mut head = Vec::with_capacity(HEAD_BYTES);
+    std::fs::File::open(root.join(rel))?
+        .take(HEAD_BYTES as u64)
+        .read_to_end(&mut head)?;
+    Ok(classify(rel, size, &head))
+}
+
+pub fn classify(path: &str, size: u64, head: &[u8]) -> FileClass {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    if LOCKFILES.contains(&name) || name.ends_with(".lock") {
+        return FileClass::Lockfile;
+    }
+    if path
+        .split('/')
+        .rev()
+        .skip(1)
+        .any(|dir| VENDOR_DIRS.contains(&dir))
+    {
+        return FileClass::Vendored;
+    }
+    if is_binary(head) {
+        return FileClass::Binary;
+    }
+    if size > MAX_BLOB_BYTES {
+        return FileClass::TooLarge;
+    }
+    if name.contains(".min.") || is_minified(head) {
+        return FileClass::Minified;
+    }
+    if name.contains(".generated.")
+        || GENERATED_SUFFIXES
+            .iter()
+            .any(|suffix| name.ends_with(suffix))
+        || has_generated_header(head)
+    {
+        return FileClass::Generated;
+    }
+    FileClass::Text
+}
+
+fn is_binary(head: &[u8]) -> bool {
+    if head.contains(&0) {
+        return true;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => false,
+        // A multi-byte character cut off at the end of the head is still text.
+        Err(err) => err.error_len().is_some(),
+    }
+}
+
+fn is_minified(head: &[u8]) -> bool {
+    if head.len() < MINIFIED_MIN_BYTES {
+        return false;
+    }
+    let lines = head.split(|byte| *byte == b'\n').count();
+    head.len() / lines > MINIFIED_AVG_LINE
+}
+
+fn has_generated_header(head: &[u8]) -> bool {
+    String::from_utf8_lossy(head)
+        .lines()
+        .take(GENERATED_HEADER_LINES)
+        .any(|line| {
+            let line = line.to_lowercase();
+            GENERATED_MARKERS.iter().any(|marker| line.contains(marker))
+        })
+}
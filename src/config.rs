@@ -1,7 +1,25 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use crate::provider::{ProviderConfig, ProviderKind, ProviderSettings};
+use crate::agent::AgentConfig;
+use crate::cassette::{Cassette, CassetteConfig, CassetteMode, CassetteProvider};
+use crate::classify::ClassPolicies;
+use crate::fallback::{FallbackConfig, FallbackProvider};
+use crate::filter::IgnoreConfig;
+use crate::provider::{build_provider, Provider, ProviderConfig, ProviderKind, ProviderSettings};
+use crate::auth::{provider_key, OAuthSession};
+use crate::secrets::{expand_home, is_reference, is_sensitive_key, SecretResolver, SecretsConfig};
+use crate::storage::{auth_path, usage_path};
+use crate::structured::validate as validate_schema;
+use crate::usage::{BudgetConfig, MeteredProvider, ModelPrice};
+
+const RESERVED_ENV: &[&str] = &[
+    "NEXUS_CONFIG",
+    "NEXUS_VAULT_PASSPHRASE",
+    "NEXUS_VAULT_KEY_FILE",
+];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
@@ -15,6 +33,19 @@ pub struct Config {
     pub openrouter: ProviderConfig,
     pub opencode: ProviderConfig,
     pub claude: ProviderConfig,
+    pub openai_compatible: ProviderConfig,
+    pub ollama: ProviderConfig,
+    pub mock: ProviderConfig,
+    pub fallback: FallbackConfig,
+    pub cassette: CassetteConfig,
+    pub pricing: BTreeMap<String, ModelPrice>,
+    pub budget: BudgetConfig,
+    pub agent: AgentConfig,
+    pub secrets: SecretsConfig,
+    pub ignore: IgnoreConfig,
+    pub classes: ClassPolicies,
+    pub profile: Option<String>,
+    pub profiles: BTreeMap<String, toml::Table>,
 }
 
 impl Default for Config {
@@ -29,18 +60,46 @@ impl Default for Config {
             openrouter: ProviderConfig::default(),
             opencode: ProviderConfig::default(),
             claude: ProviderConfig::default(),
+            openai_compatible: ProviderConfig::default(),
+            ollama: ProviderConfig::default(),
+            mock: ProviderConfig::default(),
+            fallback: FallbackConfig::default(),
+            cassette: CassetteConfig::default(),
+            pricing: BTreeMap::new(),
+            budget: BudgetConfig::default(),
+            agent: AgentConfig::default(),
+            secrets: SecretsConfig::default(),
+            ignore: IgnoreConfig::default(),
+            classes: ClassPolicies::default(),
+            profile: None,
+            profiles: BTreeMap::new(),
         }
     }
 }
 
 impl Config {
-    pub fn load() -> Self {
-        let path = config_path().unwrap_or_else(|| PathBuf::from("nexus.toml"));
-        if let Ok(contents) = std::fs::read_to_string(&path) {
-            toml::from_str(&contents).unwrap_or_default()
-        } else {
-            Self::default()
+    pub fn load() -> anyhow::Result<Self> {
+        Ok(Self::load_layered(&[])?.config)
+    }
+
+    pub fn load_layered(overrides: &[String]) -> anyhow::Result<LayeredConfig> {
+        let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let user = config_path().filter(|path| path.exists());
+        let project = project_config_path(&cwd);
+        LayeredConfig::resolve(
+            user.as_deref(),
+            project.as_deref(),
+            std::env::vars(),
+            overrides,
+        )
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
         }
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))
     }
 
     pub fn path() -> Option<PathBuf> {
@@ -55,23 +114,802 @@ impl Config {
         std::fs::write(path, data)
     }
 
-    pub fn provider_settings(&self) -> ProviderSettings {
-        let fallback_key = self.api_key.clone();
-        let provider_config = match self.provider {
+    pub fn provider_settings(&self) -> anyhow::Result<ProviderSettings> {
+        self.settings_for(&self.provider, &self.secret_resolver())
+    }
+
+    pub fn secret_resolver(&self) -> Arc<SecretResolver> {
+        SecretResolver::shared(&self.secrets)
+    }
+
+    pub fn settings_for(
+        &self,
+        kind: &ProviderKind,
+        resolver: &SecretResolver,
+    ) -> anyhow::Result<ProviderSettings> {
+        let fallback_key = match kind {
+            ProviderKind::OpenAiCompatible | ProviderKind::Ollama | ProviderKind::Mock => None,
+            _ => self.api_key.clone(),
+        };
+        let provider_config = match kind {
             ProviderKind::Gemini => &self.gemini,
             ProviderKind::OpenRouter => &self.openrouter,
             ProviderKind::OpenCode => &self.opencode,
             ProviderKind::Claude => &self.claude,
+            ProviderKind::OpenAiCompatible => &self.openai_compatible,
+            ProviderKind::Ollama => &self.ollama,
+            ProviderKind::Mock => &self.mock,
+        };
+
+        let resolve = |value: Option<String>, field: &str| {
+            value
+                .map(|value| {
+                    resolver.resolve(&value).map_err(|err| {
+                        anyhow::anyhow!("{}.{}: {}", provider_key(kind), field, err)
+                    })
+                })
+                .transpose()
         };
 
-        ProviderSettings {
-            api_key: provider_config.api_key.clone().or(fallback_key),
+        let oauth = match provider_config.oauth.clone() {
+            Some(mut config) => {
+                config.client_secret =
+                    resolve(config.client_secret.take(), "oauth.client_secret")?;
+                auth_path().ok().map(|store| OAuthSession {
+                    provider: provider_key(kind),
+                    config,
+                    store,
+                })
+            }
+            None => None,
+        };
+        Ok(ProviderSettings {
+            api_key: resolve(provider_config.api_key.clone().or(fallback_key), "api_key")?,
             model: provider_config.model.clone(),
             base_url: provider_config.base_url.clone(),
+            fixture: provider_config.fixture.clone(),
+            oauth,
+        })
+    }
+
+    pub fn project_path() -> PathBuf {
+        let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        project_config_path(&cwd).unwrap_or_else(|| cwd.join(".nexus").join("config.toml"))
+    }
+
+    pub fn validate(&self) -> Vec<ConfigIssue> {
+        let mut issues = Vec::new();
+        if let Some(url) = &self.chroma_url {
+            check_url("chroma_url", url, &mut issues);
+        }
+        if let Some(key) = &self.api_key {
+            check_reference("api_key", key, &mut issues);
+        }
+        for (section, provider) in self.provider_configs() {
+            if let Some(model) = &provider.model {
+                check_model(section, model, &mut issues);
+            }
+            if let Some(url) = &provider.base_url {
+                check_url(&format!("{}.base_url", section), url, &mut issues);
+            }
+            if let Some(key) = &provider.api_key {
+                check_reference(&format!("{}.api_key", section), key, &mut issues);
+            }
+            if let Some(oauth) = &provider.oauth {
+                if oauth.client_id.trim().is_empty() {
+                    issues.push(ConfigIssue::error(
+                        &format!("{}.oauth.client_id", section),
+                        "must not be empty",
+                    ));
+                }
+                for (field, url) in [
+                    ("device_authorization_url", &oauth.device_authorization_url),
+                    ("token_url", &oauth.token_url),
+                ] {
+                    if let Some(url) = url {
+                        check_url(&format!("{}.oauth.{}", section, field), url, &mut issues);
+                    }
+                }
+            }
+        }
+        if self.fallback.base_delay_ms > self.fallback.max_delay_ms {
+            issues.push(ConfigIssue::error(
+                "fallback.base_delay_ms",
+                "must not exceed fallback.max_delay_ms",
+            ));
+        }
+        issues
+    }
+
+    pub fn provider_configs(&self) -> [(&'static str, &ProviderConfig); 7] {
+        [
+            ("gemini", &self.gemini),
+            ("openrouter", &self.openrouter),
+            ("opencode", &self.opencode),
+            ("claude", &self.claude),
+            ("openai_compatible", &self.openai_compatible),
+            ("ollama", &self.ollama),
+            ("mock", &self.mock),
+        ]
+    }
+
+    pub fn provider_configs_mut(&mut self) -> [(&'static str, &mut ProviderConfig); 7] {
+        [
+            ("gemini", &mut self.gemini),
+            ("openrouter", &mut self.openrouter),
+            ("opencode", &mut self.opencode),
+            ("claude", &mut self.claude),
+            ("openai_compatible", &mut self.openai_compatible),
+            ("ollama", &mut self.ollama),
+            ("mock", &mut self.mock),
+        ]
+    }
+
+    pub fn build_provider(&self, command: &str) -> anyhow::Result<Box<dyn Provider>> {
+        let mut provider: Box<dyn Provider> = if self.fallback.chain.is_empty() {
+            build_provider(&self.provider, self.provider_settings()?)
+        } else {
+            Box::new(FallbackProvider::from_config(self)?)
+        };
+        if let Ok(ledger_path) = usage_path() {
+            provider = Box::new(MeteredProvider {
+                inner: provider,
+                command: command.to_string(),
+                ledger_path,
+                pricing: self.pricing.clone(),
+                budget: self.budget.clone(),
+            });
+        }
+        let Some(path) = &self.cassette.path else {
+            return Ok(provider);
+        };
+        let cassette = match Cassette::load(Path::new(path)) {
+            Ok(cassette) => cassette,
+            Err(err) if self.cassette.mode == CassetteMode::Record => {
+                eprintln!("Cassette {} unreadable, recording a new one: {}", path, err);
+                Cassette::default()
+            }
+            Err(err) => {
+                return Err(anyhow::anyhow!("Cannot load cassette {}: {}", path, err));
+            }
+        };
+        Ok(Box::new(CassetteProvider::new(
+            provider,
+            path.into(),
+            self.cassette.mode,
+            cassette,
+        )))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigLayer {
+    Defaults,
+    User(PathBuf),
+    Project(PathBuf),
+    Profile(String),
+    Env,
+    Cli,
+}
+
+impl std::fmt::Display for ConfigLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Defaults => write!(f, "defaults"),
+            Self::User(path) => write!(f, "user {}", path.display()),
+            Self::Project(path) => write!(f, "project {}", path.display()),
+            Self::Profile(name) => write!(f, "profile {}", name),
+            Self::Env => write!(f, "env"),
+            Self::Cli => write!(f, "cli"),
         }
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct LayeredConfig {
+    pub config: Config,
+    pub layers: Vec<ConfigLayer>,
+    pub origins: BTreeMap<String, ConfigLayer>,
+    pub merged: toml::Table,
+}
+
+impl LayeredConfig {
+    pub fn resolve(
+        user: Option<&Path>,
+        project: Option<&Path>,
+        env: impl IntoIterator<Item = (String, String)>,
+        overrides: &[String],
+    ) -> anyhow::Result<Self> {
+        let defaults = match toml::Value::try_from(Config::default())? {
+            toml::Value::Table(table) => table,
+            _ => toml::Table::new(),
+        };
+        let mut layered = Self {
+            config: Config::default(),
+            layers: Vec::new(),
+            origins: BTreeMap::new(),
+            merged: toml::Table::new(),
+        };
+        layered.apply(ConfigLayer::Defaults, defaults);
+        if let Some(path) = user {
+            layered.apply(ConfigLayer::User(path.to_path_buf()), read_layer(path)?);
+        }
+        if let Some(path) = project {
+            let table = read_layer(path)?;
+            let mut leaves = Vec::new();
+            flatten("", &table, &mut leaves);
+            if let Some((key, _)) = leaves.iter().find(|(key, _)| is_user_only(key)) {
+                return Err(anyhow::anyhow!(
+                    "{}: `{}` cannot be set in a project config; move it to the user nexus.toml",
+                    path.display(),
+                    key
+                ));
+            }
+            layered.apply(ConfigLayer::Project(path.to_path_buf()), table);
+        }
+        let env = env_layer(env);
+        let mut cli = toml::Table::new();
+        for entry in overrides {
+            let (key, value) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Expected KEY=VALUE in --set {}", entry))?;
+            insert_path(&mut cli, key.trim(), parse_value(value.trim()));
+        }
+        let profile = [&cli, &env, &layered.merged]
+            .into_iter()
+            .find_map(|table| table.get("profile").and_then(toml::Value::as_str))
+            .filter(|name| !name.is_empty())
+            .map(str::to_string);
+        if let Some(name) = profile {
+            let table = profile_table(&layered.merged, &name)?;
+            layered.apply(ConfigLayer::Profile(name), table);
+        }
+        layered.apply(ConfigLayer::Env, env);
+        layered.apply(ConfigLayer::Cli, cli);
+        layered.config = toml::Value::Table(layered.merged.clone())
+            .try_into()
+            .map_err(|err| anyhow::anyhow!("Invalid configuration: {}", err))?;
+        Ok(layered)
+    }
+
+    pub fn explain(&self) -> Vec<(String, String, &ConfigLayer)> {
+        let mut leaves = Vec::new();
+        flatten("", &self.merged, &mut leaves);
+        leaves
+            .into_iter()
+            .map(|(path, value)| {
+                let shown = match &value {
+                    toml::Value::String(text)
+                        if is_sensitive_key(path.rsplit('.').next().unwrap_or(""))
+                            && !is_reference(text) =>
+                    {
+                        "\"****\"".to_string()
+                    }
+                    other => other.to_string(),
+                };
+                let layer = self.origins.get(&path).unwrap_or(&ConfigLayer::Defaults);
+                (path, shown, layer)
+            })
+            .collect()
+    }
+
+    fn apply(&mut self, layer: ConfigLayer, table: toml::Table) {
+        if table.is_empty() && layer != ConfigLayer::Defaults {
+            return;
+        }
+        let mut leaves = Vec::new();
+        flatten("", &table, &mut leaves);
+        for (path, _) in leaves {
+            self.origins.insert(path, layer.clone());
+        }
+        merge(&mut self.merged, table);
+        self.layers.push(layer);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigIssue {
+    pub severity: Severity,
+    pub path: String,
+    pub message: String,
+}
+
+impl ConfigIssue {
+    fn error(path: &str, message: &str) -> Self {
+        Self {
+            severity: Severity::Error,
+            path: path.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    fn warning(path: &str, message: &str) -> Self {
+        Self {
+            severity: Severity::Warning,
+            path: path.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}: {}", level, self.path, self.message)
+    }
+}
+
+pub fn config_schema() -> serde_json::Value {
+    let kinds = serde_json::json!([
+        "gemini",
+        "open_router",
+        "open_code",
+        "claude",
+        "openai_compatible",
+        "ollama",
+        "mock"
+    ]);
+    let string = serde_json::json!({"type": "string"});
+    let boolean = serde_json::json!({"type": "boolean"});
+    let count = serde_json::json!({"type": "integer", "minimum": 0});
+    let amount = serde_json::json!({"type": "number", "minimum": 0});
+    let oauth = serde_json::json!({
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {
+            "client_id": string,
+            "client_secret": string,
+            "device_authorization_url": string,
+            "token_url": string,
+            "scope": string
+        }
+    });
+    let provider = serde_json::json!({
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {
+            "api_key": string,
+            "model": string,
+            "base_url": string,
+            "fixture": string,
+            "oauth": oauth
+        }
+    });
+    let policy = serde_json::json!({"enum": ["include", "placeholder", "skip"]});
+    let mut schema = serde_json::json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "nexus.toml",
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {
+            "provider": {"enum": kinds},
+            "api_key": string,
+            "dry_run": boolean,
+            "chroma_url": string,
+            "vector_collection": string,
+            "gemini": provider,
+            "openrouter": provider,
+            "opencode": provider,
+            "claude": provider,
+            "openai_compatible": provider,
+            "ollama": provider,
+            "mock": provider,
+            "fallback": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "chain": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "additionalProperties": false,
+                            "required": ["provider"],
+                            "properties": {"provider": {"enum": kinds}, "retries": count}
+                        }
+                    },
+                    "base_delay_ms": count,
+                    "max_delay_ms": count
+                }
+            },
+            "cassette": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "path": string,
+                    "mode": {"enum": ["record", "replay", "auto"]}
+                }
+            },
+            "pricing": {
+                "type": "object",
+                "additionalProperties": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {"prompt_per_mtok": amount, "completion_per_mtok": amount}
+                }
+            },
+            "budget": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "daily_usd": amount,
+                    "monthly_usd": amount,
+                    "daily_tokens": count
+                }
+            },
+            "agent": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "max_steps": {"type": "integer", "minimum": 1},
+                    "max_tokens": {"type": "integer", "minimum": 1},
+                    "allow_exec": boolean,
+                    "image": string
+                }
+            },
+            "secrets": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {"vault": string, "key_file": string}
+            },
+            "ignore": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "gitignore": boolean,
+                    "include": {"type": "array", "items": string},
+                    "exclude": {"type": "array", "items": string}
+                }
+            },
+            "classes": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "binary": policy,
+                    "generated": policy,
+                    "lockfile": policy,
+                    "minified": policy,
+                    "vendored": policy,
+                    "too_large": policy
+                }
+            }
+        }
+    });
+    let mut profile = schema.clone();
+    if let Some(object) = profile.as_object_mut() {
+        object.remove("$schema");
+        object.remove("title");
+    }
+    schema["properties"]["profile"] = string;
+    schema["properties"]["profiles"] = serde_json::json!({
+        "type": "object",
+        "additionalProperties": profile
+    });
+    schema
+}
+
+pub fn validate_table(table: &toml::Table) -> Vec<ConfigIssue> {
+    let value = serde_json::to_value(table).unwrap_or_default();
+    if let Err(err) = validate_schema(&config_schema(), &value) {
+        let err = err.strip_prefix("$.").unwrap_or(&err);
+        let (path, message) = err.split_once(' ').unwrap_or((err, "is invalid"));
+        return vec![ConfigIssue::error(path, message)];
+    }
+    let config = match toml::Value::Table(table.clone()).try_into::<Config>() {
+        Ok(config) => config,
+        Err(err) => return vec![ConfigIssue::error("$", &err.to_string())],
+    };
+    let mut issues = config.validate();
+    if let Some(name) = &config.profile {
+        if !config.profiles.contains_key(name) {
+            issues.push(ConfigIssue::error(
+                "profile",
+                &format!("unknown profile `{}`", name),
+            ));
+        }
+    }
+    for (name, overlay) in &config.profiles {
+        let mut merged = table.clone();
+        merge(&mut merged, overlay.clone());
+        let mut leaves = Vec::new();
+        flatten("", overlay, &mut leaves);
+        let profiled = match toml::Value::Table(merged).try_into::<Config>() {
+            Ok(profiled) => profiled,
+            Err(err) => {
+                issues.push(ConfigIssue::error(
+                    &format!("profiles.{}", name),
+                    &err.to_string(),
+                ));
+                continue;
+            }
+        };
+        for mut issue in profiled.validate() {
+            if leaves.iter().any(|(path, _)| *path == issue.path) {
+                issue.path = format!("profiles.{}.{}", name, issue.path);
+                issues.push(issue);
+            }
+        }
+    }
+    issues
+}
+
+pub fn get_value<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    let mut segments = key.split('.');
+    let mut current = table.get(segments.next()?)?;
+    for segment in segments {
+        current = current.as_table()?.get(segment)?;
+    }
+    Some(current)
+}
+
+pub fn read_document(path: &Path) -> anyhow::Result<toml_edit::DocumentMut> {
+    let contents = if path.exists() {
+        std::fs::read_to_string(path)?
+    } else {
+        String::new()
+    };
+    contents
+        .parse()
+        .map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))
+}
+
+pub fn set_file_value(path: &Path, key: &str, raw: &str) -> anyhow::Result<Vec<ConfigIssue>> {
+    let mut document = read_document(path)?;
+    let segments: Vec<&str> = key.split('.').collect();
+    let (last, parents) = segments
+        .split_last()
+        .filter(|(last, _)| !last.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Empty config key"))?;
+    let mut table = document.as_table_mut();
+    for segment in parents {
+        let entry = table
+            .entry(segment)
+            .or_insert_with(|| toml_edit::Item::Table(toml_edit::Table::new()));
+        table = entry
+            .as_table_mut()
+            .ok_or_else(|| anyhow::anyhow!("{} is not a table in {}", segment, path.display()))?;
+    }
+    let value = raw
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| toml_edit::Value::from(raw));
+    table.insert(last, toml_edit::value(value));
+
+    let updated = document.to_string();
+    let parsed: toml::Table = toml::from_str(&updated)?;
+    let issues = validate_table(&parsed);
+    if let Some(issue) = issues
+        .iter()
+        .find(|issue| issue.severity == Severity::Error)
+    {
+        return Err(anyhow::anyhow!("Not saved, {}", issue));
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, updated)?;
+    Ok(issues)
+}
+
+fn check_url(path: &str, url: &str, issues: &mut Vec<ConfigIssue>) {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => {}
+        Ok(parsed) => issues.push(ConfigIssue::error(
+            path,
+            &format!("unsupported URL scheme `{}`", parsed.scheme()),
+        )),
+        Err(err) => issues.push(ConfigIssue::error(
+            path,
+            &format!("invalid URL `{}` ({})", url, err),
+        )),
+    }
+}
+
+fn check_model(section: &str, model: &str, issues: &mut Vec<ConfigIssue>) {
+    let path = format!("{}.model", section);
+    let allowed = |c: char| c.is_ascii_alphanumeric() || "._:/@+-".contains(c);
+    if model.is_empty() || !model.chars().all(allowed) {
+        issues.push(ConfigIssue::error(
+            &path,
+            &format!("invalid model name `{}`", model),
+        ));
+        return;
+    }
+    let family = match section {
+        "gemini" => model.starts_with("gemini") || model.starts_with("models/"),
+        "claude" => model.starts_with("claude"),
+        _ => true,
+    };
+    if !family {
+        issues.push(ConfigIssue::warning(
+            &path,
+            &format!("`{}` does not look like a {} model", model, section),
+        ));
+    }
+}
+
+fn check_reference(path: &str, value: &str, issues: &mut Vec<ConfigIssue>) {
+    if let Some(name) = value.strip_prefix("env:") {
+        if std::env::var(name).is_err() {
+            issues.push(ConfigIssue::warning(
+                path,
+                &format!("environment variable {} is not set", name),
+            ));
+        }
+    } else if let Some(file) = value.strip_prefix("file:") {
+        if !expand_home(file).exists() {
+            issues.push(ConfigIssue::warning(path, &format!("{} does not exist", file)));
+        }
+    } else if !is_reference(value) {
+        issues.push(ConfigIssue::warning(
+            path,
+            "stored in plain text; use a vault:, env: or file: reference",
+        ));
+    }
+}
+
+fn profile_table(merged: &toml::Table, name: &str) -> anyhow::Result<toml::Table> {
+    let profiles = merged.get("profiles").and_then(toml::Value::as_table);
+    match profiles.and_then(|profiles| profiles.get(name)) {
+        Some(toml::Value::Table(table)) => {
+            let mut table = table.clone();
+            table.remove("profile");
+            table.remove("profiles");
+            Ok(table)
+        }
+        _ => Err(anyhow::anyhow!(
+            "Unknown profile `{}`; defined profiles: {}",
+            name,
+            profiles
+                .map(|profiles| profiles.keys().cloned().collect::<Vec<_>>().join(", "))
+                .filter(|names| !names.is_empty())
+                .unwrap_or_else(|| "none".to_string())
+        )),
+    }
+}
+
+pub fn is_user_only(key: &str) -> bool {
+    let mut segments: Vec<&str> = key.split('.').collect();
+    if segments.first() == Some(&"profiles") {
+        segments.drain(..segments.len().min(2));
+    }
+    matches!(
+        segments.as_slice(),
+        ["api_key" | "chroma_url" | "dry_run"]
+            | ["secrets" | "cassette" | "budget" | "pricing" | "fallback", ..]
+            | ["agent", "allow_exec" | "image"]
+            | [_, "api_key" | "base_url" | "oauth", ..]
+    )
+}
+
+fn read_layer(path: &Path) -> anyhow::Result<toml::Table> {
+    let contents = std::fs::read_to_string(path)?;
+    toml::from_str::<Config>(&contents)
+        .map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))?;
+    toml::from_str(&contents).map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))
+}
+
+fn env_layer(env: impl IntoIterator<Item = (String, String)>) -> toml::Table {
+    let schema = config_schema();
+    let mut table = toml::Table::new();
+    for (name, value) in env {
+        if RESERVED_ENV.contains(&name.as_str()) {
+            continue;
+        }
+        let Some(key) = name.strip_prefix("NEXUS_") else {
+            continue;
+        };
+        let path = key.to_lowercase().replace("__", ".");
+        let Some(value) = schema_leaf(&schema, &path).and_then(|leaf| typed_value(leaf, &value))
+        else {
+            continue;
+        };
+        insert_path(&mut table, &path, value);
+    }
+    table
+}
+
+fn schema_leaf<'a>(schema: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    let mut current = schema;
+    for segment in path.split('.') {
+        current = current
+            .get("properties")
+            .and_then(|properties| properties.get(segment))
+            .or_else(|| current.get("additionalProperties").filter(|next| next.is_object()))?;
+    }
+    Some(current)
+}
+
+fn typed_value(schema: &serde_json::Value, raw: &str) -> Option<toml::Value> {
+    match schema.get("type").and_then(serde_json::Value::as_str) {
+        Some("object") => None,
+        Some("boolean") => Some(
+            raw.parse()
+                .map(toml::Value::Boolean)
+                .unwrap_or_else(|_| toml::Value::String(raw.to_string())),
+        ),
+        Some("integer") => Some(
+            raw.parse()
+                .map(toml::Value::Integer)
+                .unwrap_or_else(|_| toml::Value::String(raw.to_string())),
+        ),
+        Some("number") => Some(
+            raw.parse()
+                .map(toml::Value::Float)
+                .unwrap_or_else(|_| toml::Value::String(raw.to_string())),
+        ),
+        Some("array") => Some(parse_value(raw)),
+        _ => Some(toml::Value::String(raw.to_string())),
+    }
+}
+
+fn parse_value(raw: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+fn insert_path(table: &mut toml::Table, path: &str, value: toml::Value) {
+    match path.split_once('.') {
+        None => {
+            table.insert(path.to_string(), value);
+        }
+        Some((head, rest)) => {
+            let child = table
+                .entry(head.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if !child.is_table() {
+                *child = toml::Value::Table(toml::Table::new());
+            }
+            if let toml::Value::Table(child) = child {
+                insert_path(child, rest, value);
+            }
+        }
+    }
+}
+
+fn merge(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(incoming)) => {
+                merge(existing, incoming)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn flatten(prefix: &str, table: &toml::Table, out: &mut Vec<(String, toml::Value)>) {
+    for (key, value) in table {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match value {
+            toml::Value::Table(child) => flatten(&path, child, out),
+            other => out.push((path, other.clone())),
+        }
+    }
+}
+
+fn project_config_path(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(".nexus").join("config.toml"))
+        .find(|path| path.is_file())
+}
+
 fn config_path() -> Option<PathBuf> {
     let explicit = std::env::var("NEXUS_CONFIG").ok();
     if let Some(path) = explicit {
@@ -1,13 +1,56 @@
-use crate::cache::CacheState;
+use crate::cache::{CacheDiff, CacheState};
+use crate::classify::{ClassPolicy, FileClass, Placeholder};
+use crate::packer::{
+    can_truncate, fits, rank, truncate_diff_to_budget, truncate_to_budget, Candidate, DroppedFile,
+    PackOptions,
+};
+use crate::usage::estimate_tokens;
 use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+pub const MAX_BLOB_BYTES: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Handshake {
     pub root: String,
     pub generated_at: u64,
     pub file_count: usize,
     pub total_bytes: u64,
     pub digest: String,
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub map: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadMode {
+    #[default]
+    Auto,
+    Full,
+    Diff,
+}
+
+impl std::str::FromStr for PayloadMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(value.to_string()))
+            .map_err(|_| anyhow::anyhow!("Unknown payload mode: {} (auto, full, diff)", value))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentFormat {
+    #[default]
+    Full,
+    Diff,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,16 +58,58 @@ pub struct ContextFile {
     pub path: String,
     pub bytes: usize,
     pub truncated: bool,
+    #[serde(default)]
+    pub format: ContentFormat,
+    #[serde(default)]
+    pub reason: String,
     pub content: String,
 }
 
+pub struct Baseline {
+    files: BTreeMap<String, String>,
+    blobs: PathBuf,
+}
+
+impl Baseline {
+    pub fn new(handshake: &Handshake, blobs: PathBuf) -> Self {
+        Self::from_files(handshake.files.clone(), blobs)
+    }
+
+    pub fn from_files(files: BTreeMap<String, String>, blobs: PathBuf) -> Self {
+        Self { files, blobs }
+    }
+
+    pub fn content(&self, path: &str) -> Option<String> {
+        let hash = self.files.get(path)?;
+        std::fs::read_to_string(self.blobs.join(hash)).ok()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContextPayload {
     pub changed: Vec<String>,
     pub removed: Vec<String>,
+    #[serde(default)]
+    pub renamed: Vec<(String, String)>,
     pub files: Vec<ContextFile>,
+    #[serde(default)]
+    pub placeholders: Vec<Placeholder>,
+    #[serde(default)]
+    pub dropped: Vec<DroppedFile>,
     pub total_bytes: usize,
+    #[serde(default)]
+    pub estimated_tokens: usize,
     pub truncated: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    #[serde(default)]
+    pub sequence: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_digest: Option<String>,
+    #[serde(default)]
+    pub digest: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resync: Option<String>,
 }
 
 pub fn build_handshake(cache: &CacheState) -> Handshake {
@@ -43,7 +128,50 @@ pub fn build_handshake(cache: &CacheState) -> Handshake {
         file_count: cache.files.len(),
         total_bytes,
         digest: hasher.finalize().to_hex().to_string(),
+        files: cache
+            .files
+            .iter()
+            .map(|(path, meta)| (path.clone(), meta.hash.clone()))
+            .collect(),
+        map: None,
+    }
+}
+
+pub fn store_blobs(cache: &CacheState, dir: &Path) -> anyhow::Result<usize> {
+    std::fs::create_dir_all(dir)?;
+    let mut stored = 0;
+    for (path, meta) in &cache.files {
+        let blob = dir.join(&meta.hash);
+        if meta.size > MAX_BLOB_BYTES || blob.exists() {
+            continue;
+        }
+        let Ok(bytes) = std::fs::read(cache.root.join(path)) else {
+            continue;
+        };
+        if std::str::from_utf8(&bytes).is_err()
+            || blake3::hash(&bytes).to_hex().as_str() != meta.hash
+        {
+            continue;
+        }
+        std::fs::write(blob, bytes)?;
+        stored += 1;
     }
+    Ok(stored)
+}
+
+pub fn prune_blobs(dir: &Path, keep: &BTreeSet<String>) -> anyhow::Result<usize> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let mut removed = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !keep.contains(entry.file_name().to_string_lossy().as_ref()) {
+            std::fs::remove_file(entry.path())?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
 }
 
 pub fn build_payload(
@@ -51,48 +179,176 @@ pub fn build_payload(
     current: &CacheState,
     max_bytes: usize,
 ) -> anyhow::Result<ContextPayload> {
-    let diff = previous.diff(current);
-    let mut total_bytes = 0usize;
-    let mut truncated = false;
-    let mut files = Vec::new();
+    let options = PackOptions {
+        max_tokens: usize::MAX,
+        max_bytes: Some(max_bytes),
+        ..PackOptions::default()
+    };
+    build_payload_with(previous.diff(current), current, &options, None)
+}
 
+pub fn build_payload_with(
+    diff: CacheDiff,
+    current: &CacheState,
+    options: &PackOptions,
+    baseline: Option<&Baseline>,
+) -> anyhow::Result<ContextPayload> {
+    let mut candidates = Vec::new();
+    let mut placeholders = Vec::new();
+    let mut dropped = Vec::new();
+    let placeholder = |path: &str, class: FileClass| {
+        let meta = current.files.get(path);
+        Placeholder {
+            path: path.to_string(),
+            class,
+            size: meta.map(|meta| meta.size).unwrap_or_default(),
+            hash: meta.map(|meta| meta.hash.clone()).unwrap_or_default(),
+        }
+    };
     for path in &diff.changed {
-        if total_bytes >= max_bytes {
-            truncated = true;
-            break;
+        let class = current
+            .files
+            .get(path)
+            .map(|meta| meta.class())
+            .unwrap_or_default();
+        match options.policies.policy(class) {
+            ClassPolicy::Skip => {
+                dropped.push(DroppedFile {
+                    path: path.clone(),
+                    reason: format!("{} file skipped by policy", class.as_str()),
+                });
+                continue;
+            }
+            ClassPolicy::Placeholder => {
+                placeholders.push(placeholder(path, class));
+                continue;
+            }
+            ClassPolicy::Include => {}
         }
-
-        let full_path = current.root.join(path);
-        let contents = match std::fs::read_to_string(&full_path) {
+        let contents = match std::fs::read_to_string(current.root.join(path)) {
             Ok(value) => value,
-            Err(_) => continue,
+            Err(err) if err.kind() == std::io::ErrorKind::InvalidData => {
+                placeholders.push(placeholder(path, FileClass::Binary));
+                continue;
+            }
+            Err(_) => {
+                dropped.push(DroppedFile {
+                    path: path.clone(),
+                    reason: "unreadable".to_string(),
+                });
+                continue;
+            }
+        };
+        let base_path = diff
+            .renamed
+            .iter()
+            .find(|(_, to)| to == path)
+            .map(|(from, _)| from)
+            .unwrap_or(path);
+        let previous = match options.mode {
+            PayloadMode::Full => None,
+            _ => baseline.and_then(|baseline| baseline.content(base_path)),
         };
-        let remaining = max_bytes.saturating_sub(total_bytes);
-        let mut file_content = contents;
-        let mut was_truncated = false;
-        if file_content.len() > remaining {
-            file_content.truncate(remaining);
-            was_truncated = true;
+        let (content, format) = match previous {
+            Some(previous) => {
+                let patch = unified_diff(base_path, path, &previous, &contents);
+                if options.mode == PayloadMode::Diff || patch.len() < contents.len() {
+                    (patch, ContentFormat::Diff)
+                } else {
+                    (contents, ContentFormat::Full)
+                }
+            }
+            None => (contents, ContentFormat::Full),
+        };
+        candidates.push(Candidate {
+            path: path.clone(),
+            content,
+            format,
+            modified_ns: current.files.get(path).and_then(|meta| meta.modified_ns),
+            pinned: false,
+            relevance: None,
+            recency: 0.0,
+        });
+    }
+    rank(&mut candidates, options);
+
+    let max_bytes = options.max_bytes.unwrap_or(usize::MAX);
+    let mut total_bytes = 0usize;
+    let mut total_tokens = 0usize;
+    let mut truncated = false;
+    let mut files = Vec::new();
+    for candidate in candidates {
+        let tokens_left = options.max_tokens.saturating_sub(total_tokens);
+        let bytes_left = max_bytes.saturating_sub(total_bytes);
+        let mut reason = candidate.reason();
+        let content = if fits(&candidate.content, tokens_left, bytes_left) {
+            candidate.content.clone()
+        } else {
             truncated = true;
-        }
-        total_bytes += file_content.len();
+            let partial = match candidate.format {
+                _ if !can_truncate(tokens_left) || bytes_left == 0 => None,
+                ContentFormat::Diff => {
+                    truncate_diff_to_budget(&candidate.content, tokens_left, bytes_left)
+                }
+                _ => Some(truncate_to_budget(&candidate.content, tokens_left, bytes_left)),
+            };
+            match partial {
+                Some(partial) => {
+                    reason.push_str("; truncated to fit budget");
+                    partial
+                }
+                None => {
+                    dropped.push(DroppedFile {
+                        reason: format!(
+                            "over budget: needs ~{} tokens, {} left; {}",
+                            estimate_tokens(&candidate.content),
+                            tokens_left,
+                            reason
+                        ),
+                        path: candidate.path,
+                    });
+                    continue;
+                }
+            }
+        };
+        total_bytes += content.len();
+        total_tokens += estimate_tokens(&content);
         files.push(ContextFile {
-            path: path.clone(),
-            bytes: file_content.len(),
-            truncated: was_truncated,
-            content: file_content,
+            path: candidate.path,
+            bytes: content.len(),
+            truncated: content.len() < candidate.content.len(),
+            format: candidate.format,
+            reason,
+            content,
         });
     }
 
     Ok(ContextPayload {
         changed: diff.changed,
         removed: diff.removed,
+        renamed: diff.renamed,
         files,
+        placeholders,
+        dropped,
         total_bytes,
+        estimated_tokens: total_tokens,
         truncated,
+        session: None,
+        sequence: 0,
+        base_digest: None,
+        digest: build_handshake(current).digest,
+        resync: None,
     })
 }
 
+fn unified_diff(from: &str, to: &str, old: &str, new: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", from), &format!("b/{}", to))
+        .to_string()
+}
+
 fn now_ts() -> u64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
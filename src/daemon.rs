@@ -20,12 +20,14 @@ pub fn run_daemon(
     poll_ms: u64,
     watch_root: Option<&str>,
 ) -> anyhow::Result<()> {
-    let cache = load_cache(cache_path()?.as_path()).unwrap_or_default();
+    let cache_root = Path::new(watch_root.unwrap_or("."));
+    let cache = load_cache(cache_path(cache_root)?.as_path()).unwrap_or_default();
     let memory = MemoryVault::load(memory_path()?).unwrap_or_default();
-    let kill_switch = load_kill_switch(&kill_switch_path()?).unwrap_or(false);
+    let kill_switch = load_kill_switch(&kill_switch_path()?).unwrap_or(true);
 
     let snapshot = StatusSnapshot {
         provider: config.provider.clone(),
+        profile: config.profile.clone(),
         dry_run: config.dry_run,
         cache_entries: cache.files.len(),
         memory_entries: memory.entries.len(),
@@ -73,9 +75,10 @@ pub fn run_daemon(
 
     if let Some(root) = watch_root {
         let root = Path::new(root).to_path_buf();
+        let rules = config.ignore.clone();
         thread::spawn(move || {
             let (tx, rx) = mpsc::channel();
-            let _watcher = watch_filesystem(&root, tx);
+            let _watcher = watch_filesystem(&root, &rules, tx);
             while let Ok(incident) = rx.recv() {
                 if let Ok(existing_path) = incidents_path() {
                     let mut existing = load_incidents(&existing_path).unwrap_or_default();
@@ -1,3 +1,4 @@
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::thread;
 
@@ -9,7 +10,7 @@ use crate::{
     mcp::IntegrationConfig,
     notifications::Notification,
     storage::{
-        audit_path, cache_path, incidents_path, integrations_path, kill_switch_path, load_audit,
+        audit_path, cache_path, canonical_root, incidents_path, integrations_path, kill_switch_path, load_audit,
         load_cache, load_incidents, load_integrations, load_kill_switch, load_memory,
         load_notifications, memory_path, notifications_path, save_audit, save_cache,
         save_incidents, save_integrations, save_kill_switch, save_memory,
@@ -44,13 +45,17 @@ pub struct DesktopApp {
 
 impl DesktopApp {
     pub fn new() -> Self {
-        let config = Config::load();
+        let config = Config::load().unwrap_or_else(|err| {
+            eprintln!("Config error, using defaults: {}", err);
+            Config::default()
+        });
         let kill_switch = kill_switch_path()
             .ok()
-            .and_then(|path| load_kill_switch(&path).ok())
+            .map(|path| load_kill_switch(&path).unwrap_or(true))
             .unwrap_or(false);
         let status = StatusSnapshot {
             provider: config.provider,
+            profile: config.profile,
             dry_run: config.dry_run,
             cache_entries: 0,
             memory_entries: 0,
@@ -79,7 +84,12 @@ impl DesktopApp {
     }
 
     fn refresh(&self) {
-        let cache = cache_path()
+        let root = self
+            .state
+            .lock()
+            .map(|state| state.cache_root.clone())
+            .unwrap_or_else(|_| ".".to_string());
+        let cache = cache_path(Path::new(&root))
             .ok()
             .and_then(|path| load_cache(&path).ok())
             .unwrap_or_default();
@@ -116,7 +126,7 @@ impl DesktopApp {
                 state.audit_docs = report.docs_complete;
             }
             if let Ok(path) = kill_switch_path() {
-                let enabled = load_kill_switch(&path).unwrap_or(false);
+                let enabled = load_kill_switch(&path).unwrap_or(true);
                 state.kill_switch = enabled;
                 state.status.kill_switch = enabled;
             }
@@ -131,16 +141,19 @@ impl DesktopApp {
     }
 
     fn warm_cache(&self, root: &str) {
-        let mut cache = CacheState::new(root.into());
-        let result = cache.warm().and_then(|_| {
-            let path = cache_path()?;
+        let root = canonical_root(Path::new(root));
+        let mut cache = CacheState::new(root.clone());
+        let rules = Config::load().map(|config| config.ignore).unwrap_or_default();
+        let result = cache_path(&root).and_then(|path| {
+            let previous = load_cache(&path).unwrap_or_default();
+            let stats = cache.warm_from(&previous, &rules)?;
             save_cache(&cache, &path)?;
-            Ok(())
+            Ok(stats)
         });
 
         if let Ok(mut state) = self.state.lock() {
             match result {
-                Ok(_) => state.log.push(format!("Cache warmed: {} files.", cache.files.len())),
+                Ok(stats) => state.log.push(format!("Cache warmed: {}.", stats.summary())),
                 Err(err) => state.log.push(format!("Cache warm failed: {}", err)),
             }
         }
@@ -276,6 +289,12 @@ impl DesktopApp {
     }
 }
 
+impl Default for DesktopApp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl eframe::App for DesktopApp {
     fn update(&mut self, ctx: &egui::Context, _: &mut eframe::Frame) {
         let mut start_server: Option<String> = None;
@@ -296,6 +315,10 @@ impl eframe::App for DesktopApp {
 
             ui.horizontal(|ui| {
                 ui.group(|ui| {
+                    ui.label(format!(
+                        "Profile: {}",
+                        state_snapshot.status.profile.as_deref().unwrap_or("default")
+                    ));
                     ui.label(format!("Provider: {:?}", state_snapshot.status.provider));
                     ui.label(format!("Dry run: {}", state_snapshot.status.dry_run));
                     ui.label(format!("Cache entries: {}", state_snapshot.status.cache_entries));
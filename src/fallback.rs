@@ -0,0 +1,173 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::{
+    notifications::new_notification,
+    provider::{
+        build_provider, ChatMessage, Completion, CompletionRequest, Provider, ProviderError,
+        ProviderKind,
+    },
+    storage::{load_notifications, notifications_path, save_notifications},
+    Config,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FallbackConfig {
+    pub chain: Vec<FallbackEntry>,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for FallbackConfig {
+    fn default() -> Self {
+        Self {
+            chain: Vec::new(),
+            base_delay_ms: 500,
+            max_delay_ms: 30_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FallbackEntry {
+    pub provider: ProviderKind,
+    #[serde(default)]
+    pub retries: u32,
+}
+
+pub struct FallbackProvider {
+    pub providers: Vec<(Box<dyn Provider>, u32)>,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl FallbackProvider {
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let resolver = config.secret_resolver();
+        let providers = config
+            .fallback
+            .chain
+            .iter()
+            .map(|entry| {
+                let settings = config.settings_for(&entry.provider, &resolver)?;
+                Ok((build_provider(&entry.provider, settings), entry.retries))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            providers,
+            base_delay: Duration::from_millis(config.fallback.base_delay_ms),
+            max_delay: Duration::from_millis(config.fallback.max_delay_ms),
+        })
+    }
+
+    fn backoff(&self, attempt: u32, hint: Option<Duration>) -> Duration {
+        let exponential = self.base_delay.saturating_mul(2u32.saturating_pow(attempt));
+        hint.unwrap_or(exponential).min(self.max_delay)
+    }
+
+    fn run(
+        &self,
+        mut call: impl FnMut(&dyn Provider) -> anyhow::Result<Completion>,
+        emitted: &dyn Fn() -> bool,
+    ) -> anyhow::Result<Completion> {
+        let mut failures = Vec::new();
+        for (index, (provider, retries)) in self.providers.iter().enumerate() {
+            let mut attempt = 0;
+            loop {
+                match call(provider.as_ref()) {
+                    Ok(completion) => {
+                        log_answer(provider.display_name(), index, attempt + 1);
+                        return Ok(completion);
+                    }
+                    Err(err) => {
+                        if emitted() {
+                            return Err(err);
+                        }
+                        let (retryable, hint) = classify(&err);
+                        failures.push(format!("{}: {}", provider.display_name(), err));
+                        if !retryable || attempt >= *retries {
+                            break;
+                        }
+                        std::thread::sleep(self.backoff(attempt, hint));
+                        attempt += 1;
+                    }
+                }
+            }
+        }
+        Err(anyhow::anyhow!(
+            "All providers in the fallback chain failed:\n{}",
+            failures.join("\n")
+        ))
+    }
+}
+
+impl Provider for FallbackProvider {
+    fn kind(&self) -> ProviderKind {
+        self.providers
+            .first()
+            .map(|(provider, _)| provider.kind())
+            .unwrap_or(ProviderKind::Gemini)
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Fallback"
+    }
+
+    fn dry_run_prompt(&self, input: &str) -> String {
+        let chain: Vec<String> = self
+            .providers
+            .iter()
+            .map(|(provider, retries)| format!("{} (retries {})", provider.display_name(), retries))
+            .collect();
+        format!(
+            "[Fallback dry-run] Would send prompt of {} chars via {}",
+            input.len(),
+            chain.join(" -> ")
+        )
+    }
+
+    fn complete_request(&self, request: &CompletionRequest) -> anyhow::Result<Completion> {
+        self.run(|provider| provider.complete_request(request), &|| false)
+    }
+
+    fn complete_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<Completion> {
+        let emitted = std::cell::Cell::new(false);
+        self.run(
+            |provider| {
+                provider.complete_stream(messages, &mut |chunk| {
+                    emitted.set(true);
+                    on_chunk(chunk);
+                })
+            },
+            &|| emitted.get(),
+        )
+    }
+}
+
+fn classify(err: &anyhow::Error) -> (bool, Option<Duration>) {
+    if let Some(provider_err) = err.downcast_ref::<ProviderError>() {
+        return (provider_err.is_retryable(), provider_err.retry_after());
+    }
+    if let Some(http_err) = err.downcast_ref::<reqwest::Error>() {
+        return (http_err.is_timeout() || http_err.is_connect(), None);
+    }
+    (false, None)
+}
+
+fn log_answer(provider: &str, index: usize, attempts: u32) {
+    let message = format!("Answered by {} after {} attempt(s).", provider, attempts);
+    eprintln!("[fallback] {}", message);
+    if index == 0 && attempts == 1 {
+        return;
+    }
+    if let Ok(path) = notifications_path() {
+        let mut notifications = load_notifications(&path).unwrap_or_default();
+        notifications.push(new_notification("warn", "fallback", &message));
+        let _ = save_notifications(&notifications, &path);
+    }
+}
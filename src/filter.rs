@@ -0,0 +1,149 @@
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    overrides::{Override, OverrideBuilder},
+    DirEntry, WalkBuilder,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+pub const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", ".nexusignore"];
+pub const DEFAULT_EXCLUDES: [&str; 3] = ["target", "node_modules", ".venv"];
+const ALWAYS_EXCLUDED: &str = ".git";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IgnoreConfig {
+    pub gitignore: bool,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Default for IgnoreConfig {
+    fn default() -> Self {
+        Self {
+            gitignore: true,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+pub struct RepoFilter {
+    root: PathBuf,
+    config: IgnoreConfig,
+    overrides: Override,
+    matchers: Mutex<HashMap<PathBuf, Option<Gitignore>>>,
+}
+
+impl RepoFilter {
+    pub fn new(root: &Path, config: &IgnoreConfig) -> anyhow::Result<Self> {
+        let mut builder = OverrideBuilder::new(root);
+        for glob in &config.include {
+            builder.add(glob)?;
+        }
+        let excludes = DEFAULT_EXCLUDES
+            .iter()
+            .copied()
+            .chain(config.exclude.iter().map(String::as_str))
+            .chain([ALWAYS_EXCLUDED]);
+        for glob in excludes {
+            builder.add(&format!("!{}", glob))?;
+        }
+        Ok(Self {
+            root: root.to_path_buf(),
+            config: config.clone(),
+            overrides: builder.build()?,
+            matchers: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = DirEntry> {
+        let mut builder = WalkBuilder::new(&self.root);
+        builder
+            .hidden(false)
+            .parents(false)
+            .git_global(false)
+            .git_exclude(false)
+            .require_git(false)
+            .git_ignore(self.config.gitignore)
+            .ignore(self.config.gitignore)
+            .overrides(self.overrides.clone());
+        if self.config.gitignore {
+            builder.add_custom_ignore_filename(".nexusignore");
+        }
+        builder
+            .build()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.depth() > 0)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = PathBuf> {
+        self.entries()
+            .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()))
+            .map(DirEntry::into_path)
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let Ok(rel) = path.strip_prefix(&self.root) else {
+            return true;
+        };
+        let components: Vec<_> = rel.components().collect();
+        let mut current = self.root.clone();
+        for (index, component) in components.iter().enumerate() {
+            current.push(component);
+            let dir = index + 1 < components.len() || is_dir;
+            let overridden = self.overrides.matched(&current, dir);
+            if overridden.is_ignore() {
+                return true;
+            }
+            if overridden.is_whitelist() || !self.config.gitignore {
+                continue;
+            }
+            let parent = current.parent().unwrap_or(&self.root);
+            for dir_path in parent.ancestors() {
+                if !dir_path.starts_with(&self.root) {
+                    break;
+                }
+                let decision = self.with_matcher(dir_path, |matcher| {
+                    let matched = matcher.matched(&current, dir);
+                    (matched.is_ignore(), matched.is_whitelist())
+                });
+                match decision {
+                    Some((true, _)) => return true,
+                    Some((_, true)) => break,
+                    _ => {}
+                }
+            }
+        }
+        false
+    }
+
+    fn with_matcher<T>(&self, dir: &Path, check: impl FnOnce(&Gitignore) -> T) -> Option<T> {
+        let mut matchers = self.matchers.lock().ok()?;
+        let matcher = matchers.entry(dir.to_path_buf()).or_insert_with(|| {
+            let mut builder = GitignoreBuilder::new(dir);
+            let mut found = false;
+            for name in IGNORE_FILES {
+                let file = dir.join(name);
+                if file.is_file() {
+                    found = true;
+                    builder.add(file);
+                }
+            }
+            if found {
+                builder.build().ok()
+            } else {
+                None
+            }
+        });
+        matcher.as_ref().map(check)
+    }
+}
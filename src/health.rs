@@ -1,36 +1,28 @@
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::filter::{IgnoreConfig, RepoFilter};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AuditReport {
     pub performance_benchmark: bool,
     pub security_audit: bool,
     pub docs_complete: bool,
 }
 
-impl Default for AuditReport {
-    fn default() -> Self {
-        Self {
-            performance_benchmark: false,
-            security_audit: false,
-            docs_complete: false,
-        }
-    }
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SecurityFinding {
     pub path: String,
     pub issue: String,
 }
 
-pub fn run_security_audit(root: &Path) -> anyhow::Result<Vec<SecurityFinding>> {
+pub fn run_security_audit(
+    root: &Path,
+    rules: &IgnoreConfig,
+) -> anyhow::Result<Vec<SecurityFinding>> {
     let mut findings = Vec::new();
-    for entry in walkdir::WalkDir::new(root).into_iter().filter_map(Result::ok) {
-        if !entry.file_type().is_file() {
-            continue;
-        }
-        let path = entry.path();
+    for path in RepoFilter::new(root, rules)?.files() {
+        let path = path.as_path();
         let name = path.file_name().and_then(|v| v.to_str()).unwrap_or("");
         if matches!(name, ".env" | ".env.local" | "id_rsa" | "id_ed25519") || name.ends_with(".pem")
         {
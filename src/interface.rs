@@ -1,22 +1,27 @@
 use serde::Serialize;
-use std::sync::{Arc, Mutex};
-use tiny_http::{Header, Method, Response, Server};
+use std::io::Read;
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
 
 use crate::{
     cache::CacheState,
     memory::MemoryVault,
     provider::ProviderKind,
     storage::{
-        audit_path, cache_path, incidents_path, integrations_path, kill_switch_path, load_audit,
+        audit_path, cache_path, canonical_root, incidents_path, integrations_path, kill_switch_path, load_audit,
         load_cache, load_incidents, load_integrations, load_kill_switch, load_notifications,
         load_swarm_events, notifications_path, save_integrations, save_kill_switch,
-        swarm_events_path,
+        swarm_events_path, load_sync_state, save_sync_state, sync_dir, sync_state_path,
     },
+    Config,
 };
 
 #[derive(Debug, Clone, Serialize)]
 pub struct StatusSnapshot {
     pub provider: ProviderKind,
+    pub profile: Option<String>,
     pub dry_run: bool,
     pub cache_entries: usize,
     pub memory_entries: usize,
@@ -47,11 +52,26 @@ pub fn serve(state: SharedState, addr: &str) -> anyhow::Result<()> {
     let server = Server::http(addr).map_err(|err| anyhow::anyhow!(err.to_string()))?;
     println!("Nexus interface listening on http://{}", addr);
 
-    for request in server.incoming_requests() {
-        let method = request.method();
-        let url = request.url();
+    for mut request in server.incoming_requests() {
+        let method = request.method().clone();
+        let url = request.url().to_string();
 
-        let response = match (method, url) {
+        if method == Method::Post && !same_origin(&request) {
+            let _ = request.respond(
+                Response::from_string("cross-origin request rejected").with_status_code(403),
+            );
+            continue;
+        }
+
+        if method == Method::Post && url.starts_with("/prompt") {
+            let mut prompt = String::new();
+            let _ = request.as_reader().read_to_string(&mut prompt);
+            let profile = state.status.lock().ok().and_then(|status| status.profile.clone());
+            stream_prompt_response(request, prompt, profile);
+            continue;
+        }
+
+        let response = match (&method, url.as_str()) {
             (&Method::Get, "/") => html_response(dashboard_html()),
             (&Method::Get, "/app.js") => js_response(app_js()),
             (&Method::Get, "/style.css") => css_response(app_css()),
@@ -60,7 +80,7 @@ pub fn serve(state: SharedState, addr: &str) -> anyhow::Result<()> {
             (&Method::Get, "/health") => Response::from_string("ok"),
             (&Method::Get, "/status") => {
                 let mut snapshot = state.status.lock().unwrap().clone();
-                snapshot.kill_switch = load_kill_switch(&kill_switch_path()?).unwrap_or(false);
+                snapshot.kill_switch = load_kill_switch(&kill_switch_path()?).unwrap_or(true);
                 let body = serde_json::to_string_pretty(&snapshot)?;
                 json_response(body)?
             }
@@ -74,15 +94,14 @@ pub fn serve(state: SharedState, addr: &str) -> anyhow::Result<()> {
                 let body = serde_json::to_string_pretty(&audit)?;
                 json_response(body)?
             }
-            (&Method::Get, "/diff") => {
-                let cached = load_cache(&cache_path()?)?;
-                let root = if cached.root.as_os_str().is_empty() {
-                    std::path::PathBuf::from(".")
-                } else {
-                    cached.root.clone()
-                };
+            (&Method::Get, path) if path == "/diff" || path.starts_with("/diff?") => {
+                let root = canonical_root(Path::new(
+                    &query_param(path, "root").unwrap_or_else(|| ".".to_string()),
+                ));
+                let cached = load_cache(&cache_path(&root)?)?;
                 let mut current = CacheState::new(root);
-                let _ = current.warm();
+                let rules = Config::load().map(|config| config.ignore).unwrap_or_default();
+                let _ = current.warm_from(&cached, &rules);
                 let diff = cached.diff(&current);
                 let body = serde_json::to_string_pretty(&diff)?;
                 json_response(body)?
@@ -148,6 +167,30 @@ pub fn serve(state: SharedState, addr: &str) -> anyhow::Result<()> {
                 }
                 Response::from_string("ok")
             }
+            (&Method::Post, path) if path.starts_with("/context/ack") => {
+                let root = canonical_root(Path::new(
+                    &query_param(path, "root").unwrap_or_else(|| ".".to_string()),
+                ));
+                match (query_param(path, "session"), query_param(path, "digest")) {
+                    (Some(session), Some(digest)) => {
+                        let sync_path = sync_state_path(&sync_dir(&root)?, &session)?;
+                        match load_sync_state(&sync_path)? {
+                            Some(mut sync) => {
+                                let acked = sync.ack(&digest);
+                                save_sync_state(&sync, &sync_path)?;
+                                match acked {
+                                    Ok(()) => Response::from_string("ok"),
+                                    Err(err) => {
+                                        Response::from_string(err.to_string()).with_status_code(409)
+                                    }
+                                }
+                            }
+                            None => Response::from_string("unknown session").with_status_code(404),
+                        }
+                    }
+                    _ => Response::from_string("missing session or digest").with_status_code(400),
+                }
+            }
             (&Method::Get, "/kill-switch") => {
                 let enabled = load_kill_switch(&kill_switch_path()?)?;
                 let body = serde_json::to_string_pretty(&enabled)?;
@@ -162,8 +205,101 @@ pub fn serve(state: SharedState, addr: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn stream_prompt_response(request: Request, prompt: String, profile: Option<String>) {
+    let (tx, rx) = mpsc::channel::<Vec<u8>>();
+    thread::spawn(move || {
+        let send_event = |value: serde_json::Value| {
+            let _ = tx.send(format!("data: {}\n\n", value).into_bytes());
+        };
+        let overrides: Vec<String> = profile
+            .iter()
+            .map(|name| format!("profile={}", name))
+            .collect();
+        let config = match Config::load_layered(&overrides) {
+            Ok(layered) => layered.config,
+            Err(err) => {
+                send_event(serde_json::json!({ "error": err.to_string() }));
+                let _ = tx.send(b"data: [DONE]\n\n".to_vec());
+                return;
+            }
+        };
+        let provider = match config.build_provider("dashboard") {
+            Ok(provider) => provider,
+            Err(err) => {
+                send_event(serde_json::json!({ "error": err.to_string() }));
+                let _ = tx.send(b"data: [DONE]\n\n".to_vec());
+                return;
+            }
+        };
+        if config.dry_run {
+            send_event(serde_json::json!({ "text": provider.dry_run_prompt(&prompt) }));
+        } else if let Err(err) = provider.stream_prompt(&prompt, &mut |chunk| {
+            send_event(serde_json::json!({ "text": chunk }));
+        }) {
+            send_event(serde_json::json!({ "error": err.to_string() }));
+        }
+        let _ = tx.send(b"data: [DONE]\n\n".to_vec());
+    });
+
+    thread::spawn(move || {
+        let header = Header::from_bytes(&b"Content-Type"[..], &b"text/event-stream"[..]).unwrap();
+        let response = Response::new(
+            StatusCode(200),
+            vec![header],
+            ChannelReader {
+                rx,
+                pending: Vec::new(),
+                offset: 0,
+            },
+            None,
+            None,
+        );
+        let _ = request.respond(response);
+    });
+}
+
+struct ChannelReader {
+    rx: mpsc::Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+    offset: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.offset >= self.pending.len() {
+            match self.rx.recv() {
+                Ok(bytes) => {
+                    self.pending = bytes;
+                    self.offset = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+        let remaining = &self.pending[self.offset..];
+        let count = remaining.len().min(buf.len());
+        buf[..count].copy_from_slice(&remaining[..count]);
+        self.offset += count;
+        Ok(count)
+    }
+}
+
+fn same_origin(request: &Request) -> bool {
+    let header = |name: &'static str| {
+        request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv(name))
+            .map(|header| header.value.as_str().to_string())
+    };
+    match (header("Origin"), header("Host")) {
+        (None, _) => true,
+        (Some(origin), Some(host)) => origin == format!("http://{}", host),
+        (Some(_), None) => false,
+    }
+}
+
 fn query_param(url: &str, key: &str) -> Option<String> {
-    let query = url.splitn(2, '?').nth(1)?;
+    let (_, query) = url.split_once('?')?;
     for pair in query.split('&') {
         let mut iter = pair.splitn(2, '=');
         if let (Some(k), Some(v)) = (iter.next(), iter.next()) {
@@ -263,6 +399,16 @@ fn dashboard_html() -> &'static str {
         </article>
       </section>
 
+      <section class="panel">
+        <h2>Prompt</h2>
+        <p>Send a prompt through the configured provider and watch the reply stream in.</p>
+        <textarea id="prompt-input" rows="3" placeholder="Ask Nexus..."></textarea>
+        <div class="pill-row">
+          <button id="prompt-send">Send</button>
+        </div>
+        <pre id="prompt-output" class="muted"></pre>
+      </section>
+
       <section class="panel">
         <h2>MCP Marketplace</h2>
         <p>Configure integrations and external tools from this hub.</p>
@@ -358,6 +504,7 @@ async function refresh() {
     ]);
 
     statusEl.innerHTML = `
+      <div><strong>Profile:</strong> ${status.profile ?? "default"}</div>
       <div><strong>Provider:</strong> ${status.provider}</div>
       <div><strong>Dry run:</strong> ${status.dry_run}</div>
       <div><strong>Cache entries:</strong> ${status.cache_entries}</div>
@@ -384,7 +531,10 @@ async function refresh() {
 
     renderList(
       diffEl,
-      diff.changed.map((item) => `Changed: ${item}`).concat(diff.removed.map((item) => `Removed: ${item}`)),
+      diff.changed
+        .map((item) => `Changed: ${item}`)
+        .concat(diff.removed.map((item) => `Removed: ${item}`))
+        .concat((diff.renamed || []).map(([from, to]) => `Renamed: ${from} -> ${to}`)),
       "No pending diffs."
     );
 
@@ -402,6 +552,35 @@ async function refresh() {
   }
 }
 
+async function sendPrompt() {
+  const input = document.getElementById("prompt-input");
+  const output = document.getElementById("prompt-output");
+  output.textContent = "";
+  const res = await fetch("/prompt", { method: "POST", body: input.value });
+  const reader = res.body.getReader();
+  const decoder = new TextDecoder();
+  let buffer = "";
+  while (true) {
+    const { done, value } = await reader.read();
+    if (done) {
+      break;
+    }
+    buffer += decoder.decode(value, { stream: true });
+    const events = buffer.split("\n\n");
+    buffer = events.pop();
+    for (const event of events) {
+      const data = event.replace(/^data: /, "");
+      if (data === "[DONE]") {
+        return;
+      }
+      const payload = JSON.parse(data);
+      output.textContent += payload.error ? `\n[error] ${payload.error}` : payload.text;
+    }
+  }
+}
+
+document.getElementById("prompt-send").addEventListener("click", sendPrompt);
+
 killSwitch.addEventListener("click", async () => {
   const armed = killSwitch.classList.contains("armed");
   await fetch(armed ? "/kill-switch/off" : "/kill-switch/on", { method: "POST" });
@@ -491,6 +670,16 @@ fn app_css() -> &'static str {
     }
     .muted { color: var(--muted); }
     ul { padding-left: 16px; margin: 8px 0 0; }
+    textarea {
+      width: 100%;
+      background: var(--bg);
+      color: var(--text);
+      border: 1px solid rgba(108, 193, 255, 0.3);
+      border-radius: 10px;
+      padding: 8px;
+      margin-bottom: 8px;
+    }
+    pre { white-space: pre-wrap; }
     li { margin-bottom: 4px; }
     "#
 }
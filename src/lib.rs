@@ -1,39 +1,74 @@
+pub mod agent;
+pub mod auth;
 pub mod cache;
+pub mod cassette;
+pub mod classify;
 pub mod config;
 pub mod context;
 pub mod daemon;
 pub mod desktop;
+pub mod fallback;
+pub mod filter;
 pub mod health;
 pub mod interface;
 pub mod mcp;
 pub mod memory;
+pub mod mock;
 pub mod notifications;
+pub mod packer;
 pub mod provider;
+pub mod repomap;
 pub mod sandbox;
+pub mod secrets;
+pub mod session;
 pub mod storage;
+pub mod structured;
 pub mod swarm;
+pub mod sync;
+pub mod template;
 pub mod tui;
+pub mod usage;
 pub mod vector;
 pub mod watcher;
 
-pub use cache::{CacheDiff, CacheState};
-pub use config::Config;
+pub use agent::{Agent, AgentConfig, AgentOutcome, AgentStep, AgentStop, ProposedEdit};
+pub use auth::{AuthStore, OAuthConfig, OAuthSession, OAuthToken};
+pub use cache::{CacheDiff, CacheState, RepoCacheEntry, WarmStats};
+pub use cassette::{Cassette, CassetteConfig, CassetteMode, CassetteProvider};
+pub use classify::{ClassPolicies, ClassPolicy, FileClass, Placeholder};
+pub use config::{Config, ConfigIssue, ConfigLayer, LayeredConfig};
 pub use daemon::run_daemon;
+pub use fallback::{FallbackConfig, FallbackEntry, FallbackProvider};
+pub use filter::{IgnoreConfig, RepoFilter};
 pub use interface::{serve as serve_interface, SharedState, StatusSnapshot};
 pub use health::AuditReport;
 pub use memory::MemoryVault;
+pub use mock::{MockFixture, MockProvider, MockReply, MockRule};
 pub use notifications::{new_notification, Notification};
 pub use mcp::{default_integrations, set_detail, set_enabled, IntegrationConfig, IntegrationKind};
-pub use provider::{build_provider, Provider, ProviderConfig, ProviderKind, ProviderSettings};
+pub use provider::{
+    build_provider, ChatMessage, Completion, CompletionRequest, Provider, ProviderConfig,
+    ProviderError, ProviderKind, ProviderSettings, Role, ToolCall, ToolDefinition, Usage,
+};
 pub use sandbox::{shadow_run, shadow_run_with_options, ShadowOptions, ShadowResult};
+pub use secrets::{SecretResolver, SecretVault, SecretsConfig, VaultKey};
+pub use session::Session;
 pub use storage::{
-    audit_path, cache_path, context_payload_path, handshake_path, incidents_path,
-    integrations_path, kill_switch_path, load_audit, load_cache, load_handshake,
-    load_incidents, load_integrations, load_kill_switch, load_memory, load_notifications,
-    load_swarm_events, load_vector_store, memory_path, notifications_path, save_audit,
-    save_cache, save_context_payload, save_handshake, save_incidents, save_integrations,
-    save_kill_switch, save_memory, save_notifications, save_swarm_events, save_vector_store,
-    swarm_events_path, vector_store_path,
+    agent_run_path, save_agent_run, audit_path, cache_path, context_payload_path, delete_session, handshake_path,
+    incidents_path, integrations_path, kill_switch_path, list_sessions, load_audit, load_cache,
+    load_handshake, load_incidents, load_integrations, load_kill_switch, load_memory,
+    load_notifications, load_session, load_swarm_events, load_vector_store, memory_path,
+    notifications_path, save_audit, save_cache, save_context_payload, save_handshake,
+    save_incidents, save_integrations, save_kill_switch, save_memory, save_notifications,
+    save_session, save_swarm_events, save_vector_store, session_path, sessions_dir,
+    swarm_events_path, usage_path, load_usage, save_usage, vector_store_path,
+    load_context_payload, templates_dir, template_path, load_template, save_template,
+    list_templates, auth_path, load_auth, save_auth, secrets_path, canonical_root, repo_key,
+    repo_dir, repos_dir, list_repo_caches, prune_repo_caches, blobs_dir,
+    repo_map_path, load_repo_map, save_repo_map, sync_dir, sync_state_path, load_sync_state,
+    save_sync_state, list_sync_states, encode_state, decode_state, STATE_VERSION,
 };
 pub use swarm::{architect_plan, plan_events, result_events, run_workers, SwarmEvent, Task, TaskResult};
+pub use usage::{BudgetConfig, MeteredProvider, ModelPrice, UsageLedger, UsageRecord};
+pub use template::{render as render_template, RenderedPrompt, TemplateContext};
 pub use watcher::{analyze_log, watch_filesystem, Incident};
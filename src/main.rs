@@ -1,5 +1,8 @@
 use clap::{Parser, Subcommand};
-use std::io::Read;
+use std::io::{IsTerminal, Read, Write};
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::time::Instant;
 
 use nexus::{
     analyze_log, architect_plan, build_provider, cache::CacheState, memory::MemoryVault,
@@ -11,14 +14,39 @@ use nexus::{
     result_events, save_audit, save_cache, save_context_payload, save_handshake,
     save_incidents, save_integrations, save_kill_switch, save_notifications,
     save_swarm_events, save_vector_store, run_daemon, set_detail, set_enabled,
-    swarm_events_path, vector_store_path,
-    context::build_handshake,
-    vector::{embed, ChromaStore, LocalVectorStore, VectorDocument, VectorStore},
+    swarm_events_path, vector_store_path, delete_session, list_sessions, load_session,
+    save_session, sessions_dir, load_usage, usage_path, ProviderKind, Session,
+    usage::{day_of, UsageTotals},
+    agent_run_path, save_agent_run, Agent,
+    list_templates, load_template, render_template, save_template, templates_dir,
+    TemplateContext, auth_path, load_auth, save_auth, canonical_root, list_repo_caches,
+    prune_repo_caches, repos_dir, blobs_dir, load_handshake, load_repo_map, repo_map_path,
+    save_repo_map, repomap::{RepoMap, DEFAULT_MAP_TOKENS}, list_sync_states, load_sync_state, save_sync_state,
+    sync_dir, sync_state_path, FileClass, encode_state, decode_state, STATE_VERSION, SwarmEvent,
+    auth::{device_login, provider_key},
+    secrets::{generate_key_file, is_reference, is_sensitive_key, migrate_plaintext},
+    SecretVault, VaultKey,
+    config::{
+        config_schema, get_value as get_config_value, is_user_only, read_document,
+        set_file_value as set_config_value, validate_table as validate_config_table, Severity,
+    },
+    context::{
+        build_handshake, build_payload_with, prune_blobs, store_blobs, Baseline, PayloadMode,
+    },
+    sync::{build_session_payload, SessionSync},
+    packer::{PackOptions, DEFAULT_MAX_TOKENS},
+    vector::{embed, ChromaStore, LocalVectorStore, VectorDocument, VectorStore, VectorStoreSnapshot},
 };
 
 #[derive(Parser, Debug)]
 #[command(name = "nexus", version, about = "Nexus CLI - Phase 1-5 Shell")]
 struct Cli {
+    /// Apply a named profile from [profiles.<name>] in nexus.toml
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    /// Override a config value for this run (repeatable), e.g. --set claude.model=claude-3-5-sonnet
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -27,6 +55,8 @@ struct Cli {
 enum Commands {
     /// Show or write configuration
     Config {
+        #[command(subcommand)]
+        command: Option<ConfigCommand>,
         #[arg(long)]
         show_path: bool,
         #[arg(long)]
@@ -43,32 +73,107 @@ enum Commands {
     },
     /// Dry-run a prompt through the configured provider
     Prompt {
+        #[arg(long)]
+        input: Option<String>,
+        #[arg(long)]
+        template: Option<String>,
+        #[arg(long, default_value_t = false)]
+        live: bool,
+    },
+    /// Manage and preview prompt templates
+    Template {
+        #[command(subcommand)]
+        command: TemplateCommand,
+    },
+    /// Log in to a provider with the OAuth device-code flow
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommand,
+    },
+    /// Manage the encrypted secrets vault
+    Secret {
+        #[command(subcommand)]
+        command: SecretCommand,
+    },
+    /// List models available from a provider
+    Models {
+        #[arg(long)]
+        provider: Option<ProviderKind>,
+    },
+    /// Hold a multi-turn conversation with the configured provider
+    Chat {
+        #[arg(long)]
+        session: Option<String>,
         #[arg(long)]
         input: Option<String>,
         #[arg(long, default_value_t = false)]
         live: bool,
     },
+    /// List, inspect, resume or delete chat sessions
+    Session {
+        #[command(subcommand)]
+        command: SessionCommand,
+    },
     /// Warm the cache by scanning a repository
     CacheWarm {
         #[arg(long, default_value = ".")]
         root: String,
+        /// Rehash every file instead of reusing unchanged entries
+        #[arg(long)]
+        full: bool,
     },
     /// Diff the cache against current disk state
     CacheDiff {
         #[arg(long, default_value = ".")]
         root: String,
+        /// Also pair moved-and-edited files whose line similarity to the handshake copy is above this
+        #[arg(long)]
+        similarity: Option<f64>,
     },
     /// Build a cache handshake snapshot
     CacheHandshake {
         #[arg(long, default_value = ".")]
         root: String,
+        /// Include the repository map (tree, languages, top-level symbols)
+        #[arg(long)]
+        map: bool,
+        /// Token budget for the map; over it, only public symbols and shallow directories stay
+        #[arg(long, default_value_t = DEFAULT_MAP_TOKENS)]
+        map_tokens: usize,
     },
     /// Build a cache payload from the last snapshot
     CachePayload {
         #[arg(long, default_value = ".")]
         root: String,
-        #[arg(long, default_value_t = 12000)]
-        max_bytes: usize,
+        /// Token budget for file contents (estimated)
+        #[arg(long, default_value_t = DEFAULT_MAX_TOKENS)]
+        max_tokens: usize,
+        /// Optional hard cap on content bytes
+        #[arg(long)]
+        max_bytes: Option<usize>,
+        /// Rank changed files by relevance to this prompt
+        #[arg(long)]
+        query: Option<String>,
+        /// Always rank this file or directory first (repeatable)
+        #[arg(long = "pin")]
+        pins: Vec<String>,
+        /// Also pair moved-and-edited files whose line similarity to the handshake copy is above this
+        #[arg(long)]
+        similarity: Option<f64>,
+        /// auto (diff when smaller), full or diff against the last handshake
+        #[arg(long, default_value = "auto")]
+        mode: PayloadMode,
+        /// Diff against this session's last acknowledged handshake instead of the cache
+        #[arg(long)]
+        session: Option<String>,
+        /// Digest the client last saw; a mismatch forces a full resend
+        #[arg(long, requires = "session")]
+        seen: Option<String>,
+    },
+    /// List or prune per-repository caches
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
     },
     /// Manage long-term memory entries
     Memory {
@@ -157,18 +262,144 @@ enum Commands {
         #[command(subcommand)]
         command: NotifyCommand,
     },
+    /// Let the model work toward a goal with file, vector and sandbox tools
+    Agent {
+        goal: String,
+        #[arg(long, default_value = ".")]
+        root: String,
+        #[arg(long)]
+        max_steps: Option<usize>,
+        #[arg(long)]
+        max_tokens: Option<u64>,
+        #[arg(long, default_value_t = false)]
+        allow_exec: bool,
+        #[arg(long, default_value_t = false)]
+        live: bool,
+    },
+    /// Report token usage and estimated cost
+    Usage {
+        #[command(subcommand)]
+        command: UsageCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Show every resolved value and the layer that set it
+    Explain,
+    /// Print one resolved value by dotted path, e.g. claude.model
+    Get { key: String },
+    /// Set a dotted path in the user config (or .nexus/config.toml with --project)
+    Set {
+        key: String,
+        value: String,
+        #[arg(long, default_value_t = false)]
+        project: bool,
+    },
+    /// Check the resolved configuration for schema, URL and model errors
+    Validate,
+    /// Print the JSON schema of nexus.toml
+    Schema,
+    /// List the profiles defined in nexus.toml
+    Profiles,
 }
 
 #[derive(Subcommand, Debug)]
 enum MemoryCommand {
-    Set { key: String, value: String },
+    Set {
+        key: String,
+        value: String,
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
     Get { key: String },
     List,
 }
 
+#[derive(Subcommand, Debug)]
+enum SessionCommand {
+    List,
+    Show { id: String },
+    Resume {
+        id: String,
+        #[arg(long, default_value_t = false)]
+        live: bool,
+    },
+    Delete { id: String },
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    List,
+    /// Print the repository map, refreshing outlines of changed files
+    Map {
+        #[arg(long, default_value = ".")]
+        root: String,
+    },
+    /// Acknowledge that a session received the payload with this digest
+    Ack {
+        #[arg(long, default_value = ".")]
+        root: String,
+        #[arg(long)]
+        session: String,
+        #[arg(long)]
+        digest: String,
+    },
+    /// Remove caches whose repository is gone or that are older than the given age
+    Prune {
+        #[arg(long)]
+        older_than_days: Option<u64>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AuthCommand {
+    Login { provider: ProviderKind },
+    Logout { provider: ProviderKind },
+    Status,
+}
+
+#[derive(Subcommand, Debug)]
+enum SecretCommand {
+    Set {
+        name: String,
+        #[arg(long)]
+        value: Option<String>,
+    },
+    Get { name: String },
+    List,
+    Remove { name: String },
+    /// Generate a random key file for unlocking the vault
+    Keygen { path: String },
+    /// Move plaintext keys from nexus.toml and integrations.json into the vault
+    Migrate,
+}
+
+#[derive(Subcommand, Debug)]
+enum TemplateCommand {
+    List,
+    Show { name: String },
+    Save {
+        name: String,
+        #[arg(long)]
+        body: Option<String>,
+        #[arg(long)]
+        file: Option<String>,
+    },
+    Preview {
+        name: String,
+        #[arg(long, default_value = "")]
+        input: String,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 enum SwarmCommand {
-    Plan { input: String },
+    Plan {
+        input: String,
+        #[arg(long, default_value_t = false)]
+        live: bool,
+    },
     Run { input: String },
     Merge { branch: String },
 }
@@ -177,6 +408,10 @@ enum SwarmCommand {
 enum HealCommand {
     Scan { log_path: String },
     List,
+    Suggest {
+        #[arg(long, default_value_t = false)]
+        live: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -203,6 +438,15 @@ enum BenchCommand {
         #[arg(long, default_value_t = 500)]
         docs: usize,
     },
+    /// Compare JSON and binary load/save times for cache, vector and swarm state
+    Storage {
+        #[arg(long, default_value = ".")]
+        root: String,
+        #[arg(long, default_value_t = 5000)]
+        docs: usize,
+        #[arg(long, default_value_t = 20000)]
+        events: usize,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -210,7 +454,13 @@ enum McpCommand {
     List,
     Enable { name: String },
     Disable { name: String },
-    SetDetail { name: String, key: String, value: String },
+    SetDetail {
+        name: String,
+        key: String,
+        value: String,
+        #[arg(long, default_value_t = false)]
+        secret: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -219,6 +469,14 @@ enum NotifyCommand {
     Clear,
 }
 
+#[derive(Subcommand, Debug)]
+enum UsageCommand {
+    Report {
+        #[arg(long)]
+        days: Option<u64>,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 enum VectorCommand {
     Add { id: String, content: String },
@@ -229,12 +487,162 @@ enum VectorCommand {
     },
 }
 
+fn print_usage(title: &str, totals: &std::collections::BTreeMap<String, UsageTotals>) {
+    println!("{}:", title);
+    for (key, total) in totals {
+        println!(
+            "  {:<32} {:>5} call(s) {:>9} in {:>9} out  ${:.4}",
+            key, total.calls, total.prompt_tokens, total.completion_tokens, total.cost_usd
+        );
+    }
+}
+
+fn vault_key(config: &Config) -> anyhow::Result<VaultKey> {
+    match config.secrets.key() {
+        Some(key) => Ok(key),
+        None => VaultKey::prompt(),
+    }
+}
+
+fn refresh_repo_map(cache: &CacheState) -> anyhow::Result<RepoMap> {
+    let path = repo_map_path(&cache.root)?;
+    let mut map = load_repo_map(&path)?;
+    map.refresh(cache);
+    save_repo_map(&map, &path)?;
+    Ok(map)
+}
+
+fn bench_storage<T>(name: &str, label: &str, value: &T, dir: &Path) -> anyhow::Result<()>
+where
+    T: serde::Serialize + serde::de::DeserializeOwned + Default,
+{
+    let json_path = dir.join(format!("{}.json", name));
+    let start = Instant::now();
+    std::fs::write(&json_path, serde_json::to_string_pretty(value)?)?;
+    let json_save = start.elapsed();
+    let start = Instant::now();
+    let _: T = serde_json::from_str(&std::fs::read_to_string(&json_path)?)?;
+    let json_load = start.elapsed();
+
+    let bin_path = dir.join(format!("{}.bin", name));
+    let start = Instant::now();
+    std::fs::write(&bin_path, encode_state(value)?)?;
+    let bin_save = start.elapsed();
+    let start = Instant::now();
+    let _: T = decode_state(&std::fs::read(&bin_path)?, &bin_path)?;
+    let bin_load = start.elapsed();
+
+    println!(
+        "{}: json {} bytes (save {:.2?}, load {:.2?}); binary v{} {} bytes (save {:.2?}, load {:.2?})",
+        label,
+        std::fs::metadata(&json_path)?.len(),
+        json_save,
+        json_load,
+        STATE_VERSION,
+        std::fs::metadata(&bin_path)?.len(),
+        bin_save,
+        bin_load
+    );
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
-    let config = Config::load();
+    let mut overrides = cli.overrides.clone();
+    if let Some(profile) = &cli.profile {
+        overrides.push(format!("profile={}", profile));
+    }
+    let layered = match Config::load_layered(&overrides) {
+        Err(err) if !matches!(cli.command, Commands::Config { .. }) => return Err(err.into()),
+        loaded => loaded,
+    };
+    let config = layered
+        .as_ref()
+        .map(|layered| layered.config.clone())
+        .unwrap_or_default();
 
     match cli.command {
         Commands::Config {
+            command: Some(command),
+            ..
+        } => match command {
+            ConfigCommand::Explain => {
+                let layered = layered?;
+                println!("Layers (lowest first):");
+                for layer in &layered.layers {
+                    println!("  {}", layer);
+                }
+                for (path, value, layer) in layered.explain() {
+                    println!("{} = {}  [{}]", path, value, layer);
+                }
+            }
+            ConfigCommand::Get { key } => {
+                let layered = layered?;
+                match get_config_value(&layered.merged, &key) {
+                    Some(toml::Value::String(text)) => println!("{}", text),
+                    Some(toml::Value::Table(table)) => print!("{}", toml::to_string_pretty(table)?),
+                    Some(value) => println!("{}", value),
+                    None => println!("{} is not set.", key),
+                }
+            }
+            ConfigCommand::Set {
+                key,
+                value,
+                project,
+            } => {
+                let path = if project {
+                    if is_user_only(&key) {
+                        return Err(format!("{} can only be set in the user config", key).into());
+                    }
+                    Config::project_path()
+                } else {
+                    Config::path().ok_or("No config path")?
+                };
+                for issue in set_config_value(&path, &key, &value)? {
+                    println!("{}", issue);
+                }
+                println!("Set {} in {}", key, path.display());
+            }
+            ConfigCommand::Validate => {
+                let layered = match layered {
+                    Ok(layered) => layered,
+                    Err(err) => {
+                        println!("error: {}", err);
+                        std::process::exit(1);
+                    }
+                };
+                let issues = validate_config_table(&layered.merged);
+                for issue in &issues {
+                    let origin = layered
+                        .origins
+                        .get(&issue.path)
+                        .map(|layer| format!("  [{}]", layer))
+                        .unwrap_or_default();
+                    println!("{}{}", issue, origin);
+                }
+                if issues.iter().any(|issue| issue.severity == Severity::Error) {
+                    std::process::exit(1);
+                }
+                println!("Configuration is valid ({} warning(s)).", issues.len());
+            }
+            ConfigCommand::Profiles => {
+                let layered = layered?;
+                let active = layered.config.profile.as_deref();
+                if layered.config.profiles.is_empty() {
+                    println!("No profiles defined.");
+                }
+                for (name, overlay) in &layered.config.profiles {
+                    let marker = if Some(name.as_str()) == active { "*" } else { " " };
+                    let keys: Vec<&str> = overlay.keys().map(String::as_str).collect();
+                    println!("{} {} ({})", marker, name, keys.join(", "));
+                }
+            }
+            ConfigCommand::Schema => {
+                println!("{}", serde_json::to_string_pretty(&config_schema())?);
+            }
+        },
+        Commands::Config {
+            command: None,
             show_path,
             write_default,
         } => {
@@ -265,30 +673,268 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             nexus::tui::run(&config)?;
         }
         Commands::DiffView { root } => {
-            nexus::tui::run_diff(&root)?;
+            nexus::tui::run_diff(&root, &config.ignore)?;
         }
-        Commands::Prompt { input, live } => {
-            let settings = config.provider_settings();
-            let provider = build_provider(&config.provider, settings);
-            let prompt = input.unwrap_or_else(|| "Hello Nexus".to_string());
+        Commands::Prompt {
+            input,
+            template,
+            live,
+        } => {
+            let provider = config.build_provider("prompt")?;
+            let mut prompt = input.unwrap_or_else(|| "Hello Nexus".to_string());
+            if let Some(name) = template {
+                let body = load_template(&templates_dir()?, &name)?;
+                prompt = render_template(&body, &TemplateContext::load(&prompt, Path::new("."))?)?.text;
+            }
             if config.dry_run && !live {
                 println!("{}", provider.dry_run_prompt(&prompt));
             } else {
-                println!("{}", provider.send_prompt(&prompt)?);
+                let mut stdout = std::io::stdout();
+                provider.stream_prompt(&prompt, &mut |chunk| {
+                    let _ = stdout.write_all(chunk.as_bytes());
+                    let _ = stdout.flush();
+                })?;
+                println!();
             }
         }
-        Commands::CacheWarm { root } => {
-            let mut cache = CacheState::new(root.into());
-            cache.warm()?;
-            let path = cache_path()?;
+        Commands::Auth { command } => {
+            let path = auth_path()?;
+            let mut store = load_auth(&path)?;
+            match command {
+                AuthCommand::Login { provider } => {
+                    let key = provider_key(&provider);
+                    let settings = config.settings_for(&provider, &config.secret_resolver())?;
+                    let session = settings.oauth.ok_or_else(|| {
+                        format!("Add a [{}.oauth] section with client_id to nexus.toml", key)
+                    })?;
+                    let token = device_login(&key, &session.config, &mut |code| {
+                        match &code.verification_uri_complete {
+                            Some(uri) => println!("Open {} to approve Nexus.", uri),
+                            None => println!(
+                                "Open {} and enter code {}.",
+                                code.verification_uri, code.user_code
+                            ),
+                        }
+                        println!("Waiting for approval...");
+                    })?;
+                    store.tokens.insert(key.clone(), token);
+                    save_auth(&store, &path)?;
+                    println!("Logged in to {}.", key);
+                }
+                AuthCommand::Logout { provider } => {
+                    let key = provider_key(&provider);
+                    if store.tokens.remove(&key).is_some() {
+                        save_auth(&store, &path)?;
+                        println!("Logged out of {}.", key);
+                    } else {
+                        println!("Not logged in to {}.", key);
+                    }
+                }
+                AuthCommand::Status => {
+                    if store.tokens.is_empty() {
+                        println!("No OAuth logins.");
+                    }
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)?
+                        .as_secs();
+                    for (key, token) in &store.tokens {
+                        let state = if !token.is_expired(now) {
+                            "active"
+                        } else if token.refresh_token.is_some() {
+                            "expired (will refresh)"
+                        } else {
+                            "expired"
+                        };
+                        println!("{}: {}", key, state);
+                    }
+                }
+            }
+        }
+        Commands::Secret { command } => {
+            let vault_path = config.secrets.vault_path()?;
+            match command {
+                SecretCommand::Set { name, value } => {
+                    let value = match value {
+                        Some(value) => value,
+                        None if std::io::stdin().is_terminal() => {
+                            rpassword::prompt_password("Secret value: ")?
+                        }
+                        None => {
+                            let mut buffer = String::new();
+                            std::io::stdin().read_to_string(&mut buffer)?;
+                            buffer.trim_end_matches(['\r', '\n']).to_string()
+                        }
+                    };
+                    let unlock = vault_key(&config)?;
+                    let mut vault = SecretVault::open(&vault_path, &unlock)?;
+                    vault.entries.insert(name.clone(), value);
+                    vault.save(&vault_path, &unlock)?;
+                    println!("Stored {}. Reference it as vault:{}", name, name);
+                }
+                SecretCommand::Get { name } => {
+                    let vault = SecretVault::open(&vault_path, &vault_key(&config)?)?;
+                    match vault.entries.get(&name) {
+                        Some(value) => println!("{}", value),
+                        None => println!("Unknown secret."),
+                    }
+                }
+                SecretCommand::List => {
+                    let vault = SecretVault::open(&vault_path, &vault_key(&config)?)?;
+                    if vault.entries.is_empty() {
+                        println!("Vault is empty.");
+                    }
+                    for name in vault.entries.keys() {
+                        println!("{}", name);
+                    }
+                }
+                SecretCommand::Remove { name } => {
+                    let unlock = vault_key(&config)?;
+                    let mut vault = SecretVault::open(&vault_path, &unlock)?;
+                    if vault.entries.remove(&name).is_some() {
+                        vault.save(&vault_path, &unlock)?;
+                        println!("Removed {}.", name);
+                    } else {
+                        println!("Unknown secret.");
+                    }
+                }
+                SecretCommand::Keygen { path } => {
+                    generate_key_file(Path::new(&path))?;
+                    println!(
+                        "Wrote {}. Point secrets.key_file or NEXUS_VAULT_KEY_FILE at it.",
+                        path
+                    );
+                }
+                SecretCommand::Migrate => {
+                    let unlock = vault_key(&config)?;
+                    let mut vault = SecretVault::open(&vault_path, &unlock)?;
+                    let integrations_file = integrations_path()?;
+                    let mut integrations = load_integrations(&integrations_file)?;
+                    let config_file = Config::path().ok_or("No config path")?;
+                    let mut document = read_document(&config_file)?;
+                    let moved = migrate_plaintext(&mut document, &mut integrations, &mut vault);
+                    if moved.is_empty() {
+                        println!("No plaintext secrets found.");
+                        return Ok(());
+                    }
+                    vault.save(&vault_path, &unlock)?;
+                    if config_file.exists() {
+                        std::fs::write(&config_file, document.to_string())?;
+                    }
+                    save_integrations(&integrations, &integrations_file)?;
+                    for name in moved {
+                        println!("Moved {} -> vault:{}", name, name);
+                    }
+                }
+            }
+        }
+        Commands::Template { command } => {
+            let dir = templates_dir()?;
+            match command {
+                TemplateCommand::List => {
+                    for name in list_templates(&dir)? {
+                        println!("{}", name);
+                    }
+                }
+                TemplateCommand::Show { name } => {
+                    println!("{}", load_template(&dir, &name)?);
+                }
+                TemplateCommand::Save { name, body, file } => {
+                    let body = match (body, file) {
+                        (Some(body), _) => body,
+                        (None, Some(file)) => std::fs::read_to_string(file)?,
+                        (None, None) => {
+                            let mut buffer = String::new();
+                            std::io::stdin().read_to_string(&mut buffer)?;
+                            buffer
+                        }
+                    };
+                    save_template(&dir, &name, &body)?;
+                    println!("Template {} saved.", name);
+                }
+                TemplateCommand::Preview { name, input } => {
+                    let body = load_template(&dir, &name)?;
+                    let rendered = render_template(&body, &TemplateContext::load(&input, Path::new("."))?)?;
+                    println!("{}", rendered.text);
+                    println!("---");
+                    println!("{}", rendered.summary());
+                }
+            }
+        }
+        Commands::Models { provider } => {
+            let kind = provider.unwrap_or_else(|| config.provider.clone());
+            let settings = config.settings_for(&kind, &config.secret_resolver())?;
+            let provider = build_provider(&kind, settings);
+            for model in provider.list_models()? {
+                println!("{}", model);
+            }
+        }
+        Commands::Chat {
+            session,
+            input,
+            live,
+        } => {
+            run_chat(&config, session, input, live)?;
+        }
+        Commands::Session { command } => {
+            let dir = sessions_dir()?;
+            match command {
+                SessionCommand::List => {
+                    for session in list_sessions(&dir)? {
+                        println!(
+                            "{} [{:?}] {} message(s) - {}",
+                            session.id,
+                            session.provider,
+                            session.messages.len(),
+                            session.title
+                        );
+                    }
+                }
+                SessionCommand::Show { id } => {
+                    let session = load_session(&dir, &id)?;
+                    println!("{}", session.transcript());
+                }
+                SessionCommand::Resume { id, live } => {
+                    run_chat(&config, Some(id), None, live)?;
+                }
+                SessionCommand::Delete { id } => {
+                    if delete_session(&dir, &id)? {
+                        println!("Session deleted.");
+                    } else {
+                        println!("Unknown session.");
+                    }
+                }
+            }
+        }
+        Commands::CacheWarm { root, full } => {
+            let root = canonical_root(Path::new(&root));
+            let path = cache_path(&root)?;
+            let previous = if full {
+                CacheState::default()
+            } else {
+                load_cache(&path)?
+            };
+            let mut cache = CacheState::new(root);
+            let stats = cache.warm_from(&previous, &config.ignore)?;
             save_cache(&cache, &path)?;
-            println!("Cache warmed with {} files.", cache.files.len());
+            println!("Cache warmed with {}.", stats.summary());
+            let classes: Vec<String> = cache
+                .class_counts()
+                .iter()
+                .filter(|(class, _)| **class != FileClass::Text)
+                .map(|(class, count)| format!("{} {}", class.as_str(), count))
+                .collect();
+            if !classes.is_empty() {
+                println!("Classified: {}", classes.join(", "));
+            }
         }
-        Commands::CacheDiff { root } => {
-            let mut current = CacheState::new(root.into());
-            current.warm()?;
-            let previous = load_cache(cache_path()?.as_path())?;
-            let diff = previous.diff(&current);
+        Commands::CacheDiff { root, similarity } => {
+            let root = canonical_root(Path::new(&root));
+            let previous = load_cache(cache_path(&root)?.as_path())?;
+            let blobs = blobs_dir(&root)?;
+            let mut current = CacheState::new(root);
+            current.warm_from(&previous, &config.ignore)?;
+            let diff =
+                previous.diff_with(&current, similarity.map(|ratio| (ratio, blobs.as_path())));
             println!("Changed: {}", diff.changed.len());
             for item in diff.changed {
                 println!("+ {}", item);
@@ -297,36 +943,178 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             for item in diff.removed {
                 println!("- {}", item);
             }
+            println!("Renamed: {}", diff.renamed.len());
+            for (from, to) in diff.renamed {
+                println!("~ {} -> {}", from, to);
+            }
         }
-        Commands::CacheHandshake { root } => {
-            let mut cache = CacheState::new(root.into());
-            cache.warm()?;
-            let handshake = build_handshake(&cache);
-            save_handshake(&handshake, &handshake_path()?)?;
+        Commands::CacheHandshake {
+            root,
+            map,
+            map_tokens,
+        } => {
+            let root = canonical_root(Path::new(&root));
+            let previous = load_cache(cache_path(&root)?.as_path())?;
+            let mut cache = CacheState::new(root.clone());
+            cache.warm_from(&previous, &config.ignore)?;
+            let mut handshake = build_handshake(&cache);
+            if map {
+                handshake.map = Some(refresh_repo_map(&cache)?.render_within(map_tokens));
+            }
+            let blobs = blobs_dir(&root)?;
+            let stored = store_blobs(&cache, &blobs)?;
+            let mut keep: BTreeSet<String> = handshake.files.values().cloned().collect();
+            for sync in list_sync_states(&sync_dir(&root)?)? {
+                keep.extend(sync.blob_hashes());
+            }
+            prune_blobs(&blobs, &keep)?;
+            save_handshake(&handshake, &handshake_path(&root)?)?;
             println!(
-                "Handshake created: {} files, {} bytes.",
-                handshake.file_count, handshake.total_bytes
+                "Handshake created: {} files, {} bytes ({} new file versions stored).",
+                handshake.file_count, handshake.total_bytes, stored
             );
         }
-        Commands::CachePayload { root, max_bytes } => {
-            let previous = load_cache(cache_path()?.as_path())?;
-            let mut current = CacheState::new(root.into());
-            current.warm()?;
-            let payload = previous.diff_payload(&current, max_bytes)?;
-            save_context_payload(&payload, &context_payload_path()?)?;
+        Commands::CachePayload {
+            root,
+            max_tokens,
+            max_bytes,
+            query,
+            pins,
+            similarity,
+            mode,
+            session,
+            seen,
+        } => {
+            let root = canonical_root(Path::new(&root));
+            let previous = load_cache(cache_path(&root)?.as_path())?;
+            let mut current = CacheState::new(root.clone());
+            current.warm_from(&previous, &config.ignore)?;
+            let vectors =
+                LocalVectorStore::from_snapshot(load_vector_store(&vector_store_path()?)?);
+            let options = PackOptions {
+                max_tokens,
+                max_bytes,
+                mode,
+                query,
+                pins,
+                vectors: Some(&vectors),
+                policies: config.classes.clone(),
+            };
+            let payload = match session {
+                Some(session) => {
+                    let path = sync_state_path(&sync_dir(&root)?, &session)?;
+                    let mut sync = load_sync_state(&path)?
+                        .unwrap_or_else(|| SessionSync::new(&session, &root));
+                    let payload = build_session_payload(
+                        &mut sync,
+                        &current,
+                        &options,
+                        &blobs_dir(&root)?,
+                        seen.as_deref(),
+                    )?;
+                    save_sync_state(&sync, &path)?;
+                    payload
+                }
+                None => {
+                    let blobs = blobs_dir(&root)?;
+                    let diff = previous
+                        .diff_with(&current, similarity.map(|ratio| (ratio, blobs.as_path())));
+                    let handshake = load_handshake(&handshake_path(&root)?)?;
+                    let baseline = Baseline::new(&handshake, blobs);
+                    build_payload_with(diff, &current, &options, Some(&baseline))?
+                }
+            };
+            save_context_payload(&payload, &context_payload_path(&root)?)?;
             println!(
-                "Payload built: {} changed, {} removed, {} bytes.",
+                "Payload built: {} changed, {} removed, {} renamed, {} bytes (~{} tokens).",
                 payload.changed.len(),
                 payload.removed.len(),
-                payload.total_bytes
+                payload.renamed.len(),
+                payload.total_bytes,
+                payload.estimated_tokens
             );
+            if let Some(session) = &payload.session {
+                match (&payload.resync, &payload.base_digest) {
+                    (Some(reason), _) => println!("Full resend for {}: {}", session, reason),
+                    (None, Some(base)) => println!("Diffed against acknowledged {}", base),
+                    (None, None) => {}
+                }
+                println!(
+                    "Digest {} (#{}); acknowledge with `nexus cache ack --session {} --digest {}`",
+                    payload.digest, payload.sequence, session, payload.digest
+                );
+            }
+            for file in &payload.files {
+                println!("+ {} - {}", file.path, file.reason);
+            }
+            for placeholder in &payload.placeholders {
+                println!("~ {}", placeholder.describe());
+            }
+            for file in &payload.dropped {
+                println!("- {} - {}", file.path, file.reason);
+            }
+        }
+        Commands::Cache { command } => {
+            let dir = repos_dir()?;
+            match command {
+                CacheCommand::List => {
+                    for entry in list_repo_caches(&dir)? {
+                        println!(
+                            "{} {} file(s), {} bytes{} - {}",
+                            entry.key,
+                            entry.files,
+                            entry.disk_bytes,
+                            if entry.root.as_os_str().is_empty() {
+                                " [unknown root]"
+                            } else if entry.is_orphaned() {
+                                " [missing]"
+                            } else {
+                                ""
+                            },
+                            entry.root.display()
+                        );
+                    }
+                }
+                CacheCommand::Map { root } => {
+                    let root = canonical_root(Path::new(&root));
+                    let previous = load_cache(&cache_path(&root)?)?;
+                    let mut cache = CacheState::new(root);
+                    cache.warm_from(&previous, &config.ignore)?;
+                    println!("{}", refresh_repo_map(&cache)?.render());
+                }
+                CacheCommand::Ack {
+                    root,
+                    session,
+                    digest,
+                } => {
+                    let root = canonical_root(Path::new(&root));
+                    let path = sync_state_path(&sync_dir(&root)?, &session)?;
+                    let mut sync = load_sync_state(&path)?
+                        .ok_or_else(|| format!("No payload was sent for session {}", session))?;
+                    let acked = sync.ack(&digest);
+                    save_sync_state(&sync, &path)?;
+                    acked?;
+                    println!("Session {} acknowledged {}.", session, digest);
+                }
+                CacheCommand::Prune { older_than_days } => {
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)?
+                        .as_secs();
+                    let pruned =
+                        prune_repo_caches(&dir, older_than_days.map(|days| days * 86_400), now)?;
+                    for entry in &pruned {
+                        println!("Pruned {} ({})", entry.key, entry.root.display());
+                    }
+                    println!("Pruned {} cache(s).", pruned.len());
+                }
+            }
         }
         Commands::Memory { command } => {
             let path = memory_path()?;
             let mut vault = MemoryVault::load(path.clone())?;
             match command {
-                MemoryCommand::Set { key, value } => {
-                    vault.set(key, value);
+                MemoryCommand::Set { key, value, tags } => {
+                    vault.set_with_tags(key, value, tags);
                     vault.save(path)?;
                     println!("Memory updated.");
                 }
@@ -362,6 +1150,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     image,
                     allow_exec,
                     hydrate,
+                    ignore: config.ignore.clone(),
                 },
             )
             .or_else(|_| shadow_run(&command, allow_exec))?;
@@ -383,6 +1172,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     image,
                     allow_exec: true,
                     hydrate,
+                    ignore: config.ignore.clone(),
                 },
             )
             .or_else(|_| shadow_run(&command, true))?;
@@ -392,8 +1182,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
         Commands::Swarm { command } => match command {
-            SwarmCommand::Plan { input } => {
-                let tasks = architect_plan(&input);
+            SwarmCommand::Plan { input, live } => {
+                let tasks = if config.dry_run && !live {
+                    architect_plan(&input)
+                } else {
+                    nexus::swarm::architect_plan_with_provider(
+                        config.build_provider("swarm")?.as_ref(),
+                        &input,
+                    )?
+                };
                 if let Ok(path) = swarm_events_path() {
                     let mut events = load_swarm_events(&path).unwrap_or_default();
                     events.extend(plan_events(&tasks));
@@ -401,7 +1198,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
                 println!("Planned {} task(s).", tasks.len());
                 for task in tasks {
-                    println!("[{}] {}", task.id, task.description);
+                    if task.dependencies.is_empty() {
+                        println!("[{}] {}", task.id, task.description);
+                    } else {
+                        println!(
+                            "[{}] {} (after {:?})",
+                            task.id, task.description, task.dependencies
+                        );
+                    }
                 }
             }
             SwarmCommand::Run { input } => {
@@ -422,11 +1226,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         },
         Commands::Serve { addr } => {
-            let cache = load_cache(cache_path()?.as_path()).unwrap_or_default();
+            let cache = load_cache(cache_path(Path::new("."))?.as_path()).unwrap_or_default();
             let memory = MemoryVault::load(memory_path()?).unwrap_or_default();
-            let kill_switch = load_kill_switch(&kill_switch_path()?).unwrap_or(false);
+            let kill_switch = load_kill_switch(&kill_switch_path()?).unwrap_or(true);
             let snapshot = StatusSnapshot {
                 provider: config.provider.clone(),
+                profile: config.profile.clone(),
                 dry_run: config.dry_run,
                 cache_entries: cache.files.len(),
                 memory_entries: memory.entries.len(),
@@ -469,6 +1274,28 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     println!("{}", line);
                 }
             }
+            HealCommand::Suggest { live } => {
+                let path = incidents_path()?;
+                let mut incidents = load_incidents(&path)?;
+                let provider = config.build_provider("heal")?;
+                if config.dry_run && !live {
+                    println!(
+                        "{}",
+                        provider.dry_run_prompt(&format!("{} incident(s)", incidents.len()))
+                    );
+                    return Ok(());
+                }
+                for incident in &mut incidents {
+                    match nexus::watcher::suggest_fix(provider.as_ref(), incident) {
+                        Ok(suggestion) => {
+                            println!("{} -> {}", incident.summary, suggestion);
+                            incident.suggestion = Some(suggestion);
+                        }
+                        Err(err) => eprintln!("{}: {}", incident.summary, err),
+                    }
+                }
+                save_incidents(&incidents, &path)?;
+            }
         },
         Commands::Audit { command } => match command {
             AuditCommand::Report => {
@@ -476,7 +1303,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 println!("{:#?}", report);
             }
             AuditCommand::Scan { root } => {
-                let findings = nexus::health::run_security_audit(root.as_ref())?;
+                let findings = nexus::health::run_security_audit(root.as_ref(), &config.ignore)?;
                 if findings.is_empty() {
                     println!("Security audit clean.");
                 } else {
@@ -507,15 +1334,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         },
         Commands::Bench { command } => match command {
             BenchCommand::Cache { root } => {
-                let start = std::time::Instant::now();
-                let mut cache = CacheState::new(root.into());
-                cache.warm()?;
-                let elapsed = start.elapsed();
-                println!(
-                    "Cache warmed: {} files in {:.2?}",
-                    cache.files.len(),
-                    elapsed
-                );
+                let mut cold = CacheState::new(root.clone().into());
+                let stats = cold.warm_with(&config.ignore)?;
+                println!("Cold warm: {}", stats.summary());
+                let mut warm = CacheState::new(root.into());
+                let stats = warm.warm_from(&cold, &config.ignore)?;
+                println!("Incremental warm: {}", stats.summary());
             }
             BenchCommand::Vector { docs } => {
                 let start = std::time::Instant::now();
@@ -533,9 +1357,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 let _ = store.query("Document", 5)?;
                 println!("Vector benchmark: {} docs in {:.2?}", docs, start.elapsed());
             }
+            BenchCommand::Storage { root, docs, events } => {
+                let mut cache = CacheState::new(canonical_root(Path::new(&root)));
+                cache.warm_with(&config.ignore)?;
+                let snapshot = VectorStoreSnapshot {
+                    documents: (0..docs)
+                        .map(|idx| VectorDocument {
+                            id: format!("doc-{}", idx),
+                            content: format!("Document {}", idx),
+                            embedding: embed(&format!("Document {}", idx)),
+                            metadata: Default::default(),
+                        })
+                        .collect(),
+                };
+                let swarm: Vec<SwarmEvent> = (0..events)
+                    .map(|idx| SwarmEvent {
+                        timestamp: idx as u64,
+                        event: "worker".to_string(),
+                        detail: format!("Task {} finished", idx),
+                    })
+                    .collect();
+                let dir = std::env::temp_dir().join(format!("nexus-bench-{}", std::process::id()));
+                std::fs::create_dir_all(&dir)?;
+                bench_storage("cache", &format!("cache ({} files)", cache.files.len()), &cache, &dir)?;
+                bench_storage("vector", &format!("vector ({} docs)", docs), &snapshot, &dir)?;
+                bench_storage("swarm", &format!("swarm ({} events)", events), &swarm, &dir)?;
+                std::fs::remove_dir_all(&dir)?;
+            }
         },
         Commands::KillSwitch { on, off } => {
-            let enabled = if on { true } else if off { false } else { true };
+            let enabled = on || !off;
             save_kill_switch(enabled, &kill_switch_path()?)?;
             println!(
                 "Kill switch {}.",
@@ -575,9 +1426,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     println!("Unknown integration.");
                 }
             }
-            McpCommand::SetDetail { name, key, value } => {
+            McpCommand::SetDetail {
+                name,
+                key,
+                value,
+                secret,
+            } => {
                 let path = integrations_path()?;
                 let mut integrations = load_integrations(&path)?;
+                let mut value = value;
+                if !integrations
+                    .iter()
+                    .any(|integration| integration.name.eq_ignore_ascii_case(&name))
+                {
+                    println!("Unknown integration.");
+                    return Ok(());
+                }
+                if secret {
+                    let vault_path = config.secrets.vault_path()?;
+                    let unlock = vault_key(&config)?;
+                    let mut vault = SecretVault::open(&vault_path, &unlock)?;
+                    let entry = format!("integrations.{}.{}", name.to_lowercase(), key);
+                    vault.entries.insert(entry.clone(), value);
+                    vault.save(&vault_path, &unlock)?;
+                    value = format!("vault:{}", entry);
+                } else if is_sensitive_key(&key) && !is_reference(&value) {
+                    eprintln!(
+                        "Warning: {} is stored in plain text; pass --secret or use an env:/file: reference.",
+                        key
+                    );
+                }
                 if set_detail(&mut integrations, &name, &key, &value) {
                     save_integrations(&integrations, &path)?;
                     println!("Integration detail updated.");
@@ -603,6 +1481,88 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 println!("Notifications cleared.");
             }
         },
+        Commands::Agent {
+            goal,
+            root,
+            max_steps,
+            max_tokens,
+            allow_exec,
+            live,
+        } => {
+            let provider = config.build_provider("agent")?;
+            if config.dry_run && !live {
+                println!("{}", provider.dry_run_prompt(&goal));
+                let tools: Vec<String> = Agent::tools().into_iter().map(|tool| tool.name).collect();
+                println!("Tools: {}", tools.join(", "));
+                return Ok(());
+            }
+            let mut agent_config = config.agent.clone();
+            agent_config.max_steps = max_steps.unwrap_or(agent_config.max_steps);
+            agent_config.max_tokens = max_tokens.unwrap_or(agent_config.max_tokens);
+            agent_config.allow_exec |= allow_exec;
+
+            let root = canonical_root(Path::new(&root));
+            let mut cache = load_cache(cache_path(&root)?.as_path())?;
+            if cache.root != root || cache.files.is_empty() {
+                cache = CacheState::new(root.clone());
+                cache.warm_with(&config.ignore)?;
+            }
+            let agent = Agent {
+                cache,
+                vectors: LocalVectorStore::from_snapshot(load_vector_store(
+                    vector_store_path()?.as_path(),
+                )?),
+                config: agent_config,
+                ignore: config.ignore.clone(),
+                kill_switch: kill_switch_path()?,
+            };
+            let outcome = agent.run(provider.as_ref(), &goal, &mut |step| {
+                println!("[step {}] {} {}", step.step, step.tool, step.arguments);
+            })?;
+            for edit in &outcome.proposed_edits {
+                println!(
+                    "Proposed edit: {} ({} bytes) {}",
+                    edit.path,
+                    edit.content.len(),
+                    edit.reason
+                );
+            }
+            if let Some(answer) = &outcome.answer {
+                println!("{}", answer);
+            }
+            println!(
+                "Agent stopped ({:?}) after {} tool call(s), ~{} tokens.",
+                outcome.stop,
+                outcome.steps.len(),
+                outcome.tokens_used
+            );
+            save_agent_run(&outcome, &agent_run_path()?)?;
+        }
+        Commands::Usage { command } => match command {
+            UsageCommand::Report { days } => {
+                let mut ledger = load_usage(&usage_path()?)?;
+                if let Some(days) = days {
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)?
+                        .as_secs();
+                    ledger = ledger.since(now.saturating_sub(days * 86_400));
+                }
+                if ledger.records.is_empty() {
+                    println!("No usage recorded.");
+                    return Ok(());
+                }
+                print_usage("Daily", &ledger.breakdown(|record| day_of(record.timestamp)));
+                print_usage(
+                    "By provider",
+                    &ledger.breakdown(|record| format!("{:?}/{}", record.provider, record.model)),
+                );
+                print_usage("By command", &ledger.breakdown(|record| record.command.clone()));
+                let estimated = ledger.records.iter().filter(|record| record.estimated).count();
+                if estimated > 0 {
+                    println!("{} call(s) had no usage block; tokens were estimated.", estimated);
+                }
+            }
+        },
         Commands::Vector { command } => {
             let vector_path = vector_store_path()?;
             let mut local_store =
@@ -650,8 +1610,63 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+fn run_chat(
+    config: &Config,
+    session_id: Option<String>,
+    input: Option<String>,
+    live: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = sessions_dir()?;
+    let mut session = match session_id {
+        Some(id) => load_session(&dir, &id)?,
+        None => Session::new(config.provider.clone()),
+    };
+    let provider = config.build_provider("chat")?;
+    let dry_run = config.dry_run && !live;
+    println!("Session {} ({} message(s)).", session.id, session.messages.len());
+
+    let turn = |session: &mut Session, line: &str| -> Result<(), Box<dyn std::error::Error>> {
+        if dry_run {
+            let preview = format!("{}\n{}", session.transcript(), line);
+            println!("{}", provider.dry_run_prompt(preview.trim_start()));
+            return Ok(());
+        }
+        let mut stdout = std::io::stdout();
+        session.send(provider.as_ref(), line, &mut |chunk| {
+            let _ = stdout.write_all(chunk.as_bytes());
+            let _ = stdout.flush();
+        })?;
+        println!();
+        save_session(session, &dir)?;
+        Ok(())
+    };
+
+    if let Some(line) = input {
+        return turn(&mut session, &line);
+    }
+
+    let stdin = std::io::stdin();
+    loop {
+        print!("> ");
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if matches!(line, "exit" | "quit") {
+            break;
+        }
+        turn(&mut session, line)?;
+    }
+    Ok(())
+}
+
 fn run_interceptor(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
-    if load_kill_switch(&kill_switch_path()?).unwrap_or(false) {
+    if load_kill_switch(&kill_switch_path()?).unwrap_or(true) {
         println!("Kill switch armed: commands blocked.");
         return Ok(());
     }
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
+use crate::secrets::SecretResolver;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum IntegrationKind {
     Github,
@@ -27,6 +29,13 @@ impl IntegrationConfig {
             details: BTreeMap::new(),
         }
     }
+
+    pub fn detail(&self, key: &str, resolver: &SecretResolver) -> anyhow::Result<Option<String>> {
+        self.details
+            .get(key)
+            .map(|value| resolver.resolve(value))
+            .transpose()
+    }
 }
 
 pub fn default_integrations() -> Vec<IntegrationConfig> {
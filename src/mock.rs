@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+
+use crate::provider::{
+    ChatMessage, Completion, CompletionRequest, Provider, ProviderKind, Role, ToolCall,
+};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MockFixture {
+    pub rules: Vec<MockRule>,
+    pub responses: Vec<MockReply>,
+    pub fallback: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MockReply {
+    Text(String),
+    ToolCalls {
+        #[serde(default)]
+        text: String,
+        tool_calls: Vec<ToolCall>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockRule {
+    pub contains: String,
+    pub response: String,
+}
+
+pub struct MockProvider {
+    pub fixture: Option<MockFixture>,
+    pub fixture_error: Option<String>,
+    next: Cell<usize>,
+}
+
+impl MockProvider {
+    pub fn new(fixture: Option<MockFixture>) -> Self {
+        Self {
+            fixture,
+            fixture_error: None,
+            next: Cell::new(0),
+        }
+    }
+
+    pub fn from_fixture(path: Option<&str>) -> Self {
+        let Some(path) = path else {
+            return Self::new(None);
+        };
+        let loaded = std::fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|raw| Ok(serde_json::from_str::<MockFixture>(&raw)?));
+        match loaded {
+            Ok(fixture) => Self::new(Some(fixture)),
+            Err(err) => Self {
+                fixture_error: Some(format!("Mock fixture {} unreadable: {}", path, err)),
+                ..Self::new(None)
+            },
+        }
+    }
+}
+
+impl Provider for MockProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Mock
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Mock"
+    }
+
+    fn dry_run_prompt(&self, input: &str) -> String {
+        format!("[Mock dry-run] Would send prompt of {} chars.", input.len())
+    }
+
+    fn complete_request(&self, request: &CompletionRequest) -> anyhow::Result<Completion> {
+        let (text, tool_calls) = match self.respond(&request.messages)? {
+            MockReply::Text(text) => (text, Vec::new()),
+            MockReply::ToolCalls { text, tool_calls } => (text, tool_calls),
+        };
+        Ok(Completion::new(ProviderKind::Mock, "mock", text, None).with_tool_calls(tool_calls))
+    }
+}
+
+impl MockProvider {
+    fn respond(&self, messages: &[ChatMessage]) -> anyhow::Result<MockReply> {
+        if let Some(err) = &self.fixture_error {
+            return Err(anyhow::anyhow!(err.clone()));
+        }
+        let input = messages
+            .iter()
+            .rev()
+            .find(|message| message.role == Role::User)
+            .map(|message| message.content.as_str())
+            .unwrap_or("");
+        let Some(fixture) = &self.fixture else {
+            return Ok(MockReply::Text(format!("[mock] {}", input)));
+        };
+
+        if let Some(rule) = fixture
+            .rules
+            .iter()
+            .find(|rule| input.contains(&rule.contains))
+        {
+            return Ok(MockReply::Text(rule.response.clone()));
+        }
+        let index = self.next.get();
+        if let Some(response) = fixture.responses.get(index) {
+            self.next.set(index + 1);
+            return Ok(response.clone());
+        }
+        fixture
+            .fallback
+            .clone()
+            .map(MockReply::Text)
+            .ok_or_else(|| anyhow::anyhow!("Mock fixture exhausted after {} response(s)", index))
+    }
+}
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    classify::ClassPolicies,
+    context::{ContentFormat, PayloadMode},
+    usage::estimate_tokens,
+    vector::{LocalVectorStore, VectorStore},
+};
+
+pub const DEFAULT_MAX_TOKENS: usize = 3000;
+const MIN_PARTIAL_TOKENS: usize = 32;
+const RELEVANCE_WEIGHT: f32 = 0.7;
+
+pub struct PackOptions<'a> {
+    pub max_tokens: usize,
+    pub max_bytes: Option<usize>,
+    pub mode: PayloadMode,
+    pub query: Option<String>,
+    pub pins: Vec<String>,
+    pub vectors: Option<&'a LocalVectorStore>,
+    pub policies: ClassPolicies,
+}
+
+impl Default for PackOptions<'_> {
+    fn default() -> Self {
+        Self {
+            max_tokens: DEFAULT_MAX_TOKENS,
+            max_bytes: None,
+            mode: PayloadMode::Auto,
+            query: None,
+            pins: Vec::new(),
+            vectors: None,
+            policies: ClassPolicies::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DroppedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub path: String,
+    pub content: String,
+    pub format: ContentFormat,
+    pub modified_ns: Option<u64>,
+    pub pinned: bool,
+    pub relevance: Option<f32>,
+    pub recency: f32,
+}
+
+impl Candidate {
+    pub fn score(&self) -> f32 {
+        match self.relevance {
+            Some(relevance) => {
+                RELEVANCE_WEIGHT * relevance + (1.0 - RELEVANCE_WEIGHT) * self.recency
+            }
+            None => self.recency,
+        }
+    }
+
+    pub fn reason(&self) -> String {
+        let mut factors = Vec::new();
+        if self.pinned {
+            factors.push("pinned".to_string());
+        }
+        if let Some(relevance) = self.relevance {
+            factors.push(format!("prompt relevance {:.2}", relevance));
+        }
+        factors.push(format!("recency {:.2}", self.recency));
+        format!("score {:.2} ({})", self.score(), factors.join(", "))
+    }
+}
+
+pub fn is_pinned(path: &str, pins: &[String]) -> bool {
+    pins.iter().any(|pin| {
+        let pin = pin.trim_end_matches('/');
+        path == pin
+            || path
+                .strip_prefix(pin)
+                .is_some_and(|rest| rest.starts_with('/'))
+    })
+}
+
+pub fn semantic_scores(query: &str, vectors: Option<&LocalVectorStore>) -> BTreeMap<String, f32> {
+    let mut scores = BTreeMap::new();
+    let hits = vectors
+        .and_then(|store| store.query(query, store.documents.len()).ok())
+        .unwrap_or_default();
+    for hit in hits {
+        let score = hit.score.clamp(0.0, 1.0);
+        for key in std::iter::once(hit.id).chain(hit.metadata.get("path").cloned()) {
+            let entry = scores.entry(key).or_insert(0.0);
+            *entry = f32::max(*entry, score);
+        }
+    }
+    scores
+}
+
+pub fn relevance(query: &str, path: &str, content: &str, semantic: &BTreeMap<String, f32>) -> f32 {
+    let terms: BTreeSet<String> = query
+        .split(|ch: char| !ch.is_alphanumeric() && ch != '_')
+        .filter(|term| term.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect();
+    let lexical = if terms.is_empty() {
+        0.0
+    } else {
+        let haystack = format!("{}\n{}", path, content).to_lowercase();
+        let hits = terms
+            .iter()
+            .filter(|term| haystack.contains(term.as_str()))
+            .count();
+        hits as f32 / terms.len() as f32
+    };
+    lexical.max(semantic.get(path).copied().unwrap_or_default())
+}
+
+pub fn rank(candidates: &mut [Candidate], options: &PackOptions) {
+    let mut by_age: Vec<Option<u64>> = candidates.iter().map(|c| c.modified_ns).collect();
+    by_age.sort();
+    by_age.dedup();
+    let span = by_age.len().saturating_sub(1).max(1) as f32;
+    let query = options
+        .query
+        .as_deref()
+        .filter(|query| !query.trim().is_empty());
+    let semantic = query
+        .map(|query| semantic_scores(query, options.vectors))
+        .unwrap_or_default();
+    for candidate in candidates.iter_mut() {
+        candidate.pinned = is_pinned(&candidate.path, &options.pins);
+        candidate.recency = match candidate.modified_ns {
+            Some(_) if by_age.len() == 1 => 1.0,
+            Some(_) => {
+                let position = by_age
+                    .iter()
+                    .position(|age| *age == candidate.modified_ns)
+                    .unwrap_or_default();
+                position as f32 / span
+            }
+            None => 0.0,
+        };
+        candidate.relevance =
+            query.map(|query| relevance(query, &candidate.path, &candidate.content, &semantic));
+    }
+    candidates.sort_by(|left, right| {
+        right
+            .pinned
+            .cmp(&left.pinned)
+            .then(right.score().total_cmp(&left.score()))
+            .then(left.path.cmp(&right.path))
+    });
+}
+
+pub fn fits(text: &str, max_tokens: usize, max_bytes: usize) -> bool {
+    text.len() <= max_bytes && estimate_tokens(text) <= max_tokens
+}
+
+pub fn can_truncate(max_tokens: usize) -> bool {
+    max_tokens >= MIN_PARTIAL_TOKENS
+}
+
+pub fn truncate_diff_to_budget(diff: &str, max_tokens: usize, max_bytes: usize) -> Option<String> {
+    let mut sections = vec![String::new()];
+    for line in diff.split_inclusive('\n') {
+        if line.starts_with("@@") {
+            sections.push(String::new());
+        }
+        if let Some(section) = sections.last_mut() {
+            section.push_str(line);
+        }
+    }
+    let mut hunks = sections.into_iter();
+    let mut out = hunks.next().unwrap_or_default();
+    let mut kept = 0;
+    for hunk in hunks {
+        if !fits(&format!("{}{}", out, hunk), max_tokens, max_bytes) {
+            break;
+        }
+        out.push_str(&hunk);
+        kept += 1;
+    }
+    (kept > 0).then_some(out)
+}
+
+pub fn truncate_to_budget(text: &str, max_tokens: usize, max_bytes: usize) -> String {
+    let mut out = String::new();
+    for line in text.split_inclusive('\n') {
+        let next = out.len() + line.len();
+        if next > max_bytes || estimate_tokens(&out) + estimate_tokens(line) > max_tokens {
+            break;
+        }
+        out.push_str(line);
+    }
+    if !out.is_empty() {
+        return out;
+    }
+    let max_chars = max_tokens.saturating_mul(4);
+    for (count, (index, ch)) in text.char_indices().enumerate() {
+        if count >= max_chars || index + ch.len_utf8() > max_bytes {
+            break;
+        }
+        out.push(ch);
+    }
+    out
+}
@@ -1,28 +1,30 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::time::Duration;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::auth::{OAuthConfig, OAuthSession};
+use crate::mock::MockProvider;
+
+const CLAUDE_RESPONSE_TOOL: &str = "respond";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(default)]
 pub struct ProviderConfig {
     pub api_key: Option<String>,
     pub model: Option<String>,
     pub base_url: Option<String>,
+    pub fixture: Option<String>,
+    pub oauth: Option<OAuthConfig>,
 }
 
-impl Default for ProviderConfig {
-    fn default() -> Self {
-        Self {
-            api_key: None,
-            model: None,
-            base_url: None,
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ProviderSettings {
     pub api_key: Option<String>,
     pub model: Option<String>,
     pub base_url: Option<String>,
+    pub fixture: Option<String>,
+    pub oauth: Option<OAuthSession>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,17 +34,239 @@ pub enum ProviderKind {
     OpenRouter,
     OpenCode,
     Claude,
+    #[serde(rename = "openai_compatible")]
+    OpenAiCompatible,
+    Ollama,
+    Mock,
+}
+
+impl std::str::FromStr for ProviderKind {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(value.to_string()))
+            .map_err(|_| anyhow::anyhow!("Unknown provider kind: {}", value))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    pub fn new(role: Role, content: &str) -> Self {
+        Self {
+            role,
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    pub fn user(content: &str) -> Self {
+        Self::new(Role::User, content)
+    }
+
+    pub fn assistant(content: &str) -> Self {
+        Self::new(Role::Assistant, content)
+    }
+
+    pub fn tool_use(content: &str, tool_calls: Vec<ToolCall>) -> Self {
+        Self {
+            tool_calls,
+            ..Self::new(Role::Assistant, content)
+        }
+    }
+
+    pub fn tool_result(call: &ToolCall, content: &str) -> Self {
+        Self {
+            tool_call_id: Some(call.id.clone()),
+            ..Self::new(Role::Tool, content)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompletionRequest {
+    pub messages: Vec<ChatMessage>,
+    pub tools: Vec<ToolDefinition>,
+    #[serde(default)]
+    pub response_schema: Option<serde_json::Value>,
+}
+
+impl CompletionRequest {
+    pub fn new(messages: &[ChatMessage]) -> Self {
+        Self {
+            messages: messages.to_vec(),
+            tools: Vec::new(),
+            response_schema: None,
+        }
+    }
+
+    pub fn with_tools(mut self, tools: Vec<ToolDefinition>) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    pub fn with_schema(mut self, schema: serde_json::Value) -> Self {
+        self.response_schema = Some(schema);
+        self
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProviderError {
+    #[error("{provider} returned HTTP {status}: {body}")]
+    Http {
+        provider: String,
+        status: u16,
+        retry_after: Option<Duration>,
+        body: String,
+    },
+}
+
+impl ProviderError {
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ProviderError::Http { status, .. } => *status == 408 || *status == 429 || *status >= 500,
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ProviderError::Http { retry_after, .. } => *retry_after,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl Usage {
+    fn merge(&mut self, other: Usage) {
+        self.prompt_tokens = self.prompt_tokens.max(other.prompt_tokens);
+        self.completion_tokens = self.completion_tokens.max(other.completion_tokens);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Completion {
+    pub provider: ProviderKind,
+    pub model: String,
+    pub text: String,
+    pub usage: Option<Usage>,
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl Completion {
+    pub fn new(provider: ProviderKind, model: &str, text: String, usage: Option<Usage>) -> Self {
+        Self {
+            provider,
+            model: model.to_string(),
+            text,
+            usage,
+            tool_calls: Vec::new(),
+        }
+    }
+
+    pub fn with_tool_calls(mut self, tool_calls: Vec<ToolCall>) -> Self {
+        self.tool_calls = tool_calls;
+        self
+    }
 }
 
 pub trait Provider {
     fn kind(&self) -> ProviderKind;
     fn display_name(&self) -> &'static str;
     fn dry_run_prompt(&self, input: &str) -> String;
-    fn send_prompt(&self, input: &str) -> anyhow::Result<String>;
+    fn complete_request(&self, request: &CompletionRequest) -> anyhow::Result<Completion>;
+
+    fn complete(&self, messages: &[ChatMessage]) -> anyhow::Result<Completion> {
+        self.complete_request(&CompletionRequest::new(messages))
+    }
+
+    fn complete_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<Completion> {
+        let completion = self.complete(messages)?;
+        on_chunk(&completion.text);
+        Ok(completion)
+    }
+
+    fn send_messages(&self, messages: &[ChatMessage]) -> anyhow::Result<String> {
+        Ok(self.complete(messages)?.text)
+    }
+
+    fn stream_messages(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<String> {
+        Ok(self.complete_stream(messages, on_chunk)?.text)
+    }
+
+    fn send_prompt(&self, input: &str) -> anyhow::Result<String> {
+        self.send_messages(&[ChatMessage::user(input)])
+    }
+
+    fn stream_prompt(&self, input: &str, on_chunk: &mut dyn FnMut(&str)) -> anyhow::Result<String> {
+        self.stream_messages(&[ChatMessage::user(input)], on_chunk)
+    }
+
+    fn send_structured(
+        &self,
+        messages: &[ChatMessage],
+        schema: &serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value> {
+        crate::structured::send_structured(self, messages, schema)
+    }
+
+    fn list_models(&self) -> anyhow::Result<Vec<String>> {
+        Err(anyhow::anyhow!(
+            "Model listing is not supported for {}",
+            self.display_name()
+        ))
+    }
 }
 
 pub struct GeminiProvider {
     pub api_key: Option<String>,
+    pub oauth: Option<OAuthSession>,
     pub model: String,
     pub base_url: String,
 }
@@ -60,11 +284,38 @@ pub struct OpenCodeProvider {
 }
 
 pub struct ClaudeProvider {
+    pub api_key: Option<String>,
+    pub oauth: Option<OAuthSession>,
+    pub model: String,
+    pub base_url: String,
+}
+
+pub struct OpenAiCompatibleProvider {
+    pub api_key: Option<String>,
+    pub model: String,
+    pub base_url: String,
+}
+
+pub struct OllamaProvider {
     pub api_key: Option<String>,
     pub model: String,
     pub base_url: String,
 }
 
+impl GeminiProvider {
+    fn post(
+        &self,
+        client: &reqwest::blocking::Client,
+        method: &str,
+    ) -> anyhow::Result<reqwest::blocking::RequestBuilder> {
+        let url = format!("{}/v1beta/models/{}:{}", self.base_url, self.model, method);
+        Ok(match credential(self.display_name(), &self.api_key, &self.oauth)? {
+            Credential::ApiKey(key) => client.post(url).query(&[("key", key)]),
+            Credential::Bearer(token) => client.post(url).bearer_auth(token),
+        })
+    }
+}
+
 impl Provider for GeminiProvider {
     fn kind(&self) -> ProviderKind {
         ProviderKind::Gemini
@@ -76,40 +327,47 @@ impl Provider for GeminiProvider {
 
     fn dry_run_prompt(&self, input: &str) -> String {
         format!(
-            "[Gemini dry-run] Would send prompt of {} chars. API key {}",
+            "[Gemini dry-run] Would send prompt of {} chars. {}",
             input.len(),
-            if self.api_key.is_some() { "loaded" } else { "missing" }
+            credential_status(&self.api_key, &self.oauth)
         )
     }
 
-    fn send_prompt(&self, input: &str) -> anyhow::Result<String> {
-        let api_key = self
-            .api_key
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Gemini API key not configured"))?;
-        let url = format!(
-            "{}/v1beta/models/{}:generateContent?key={}",
-            self.base_url, self.model, api_key
-        );
-
-        let payload = serde_json::json!({
-            "contents": [{
-                "parts": [{"text": input}]
-            }]
-        });
-
-        let response: serde_json::Value = reqwest::blocking::Client::new()
-            .post(url)
-            .json(&payload)
+    fn complete_request(&self, request: &CompletionRequest) -> anyhow::Result<Completion> {
+        let response: serde_json::Value = self
+            .post(&reqwest::blocking::Client::new(), "generateContent")?
+            .json(&gemini_payload(request))
             .send()?
-            .error_for_status()?
+            .check_status(self.display_name())?
             .json()?;
 
-        let text = response["candidates"][0]["content"]["parts"][0]["text"]
-            .as_str()
-            .unwrap_or("")
-            .to_string();
-        Ok(text)
+        let (text, tool_calls) = gemini_parts(&response["candidates"][0]["content"]["parts"]);
+        Ok(Completion::new(self.kind(), &self.model, text, gemini_usage(&response))
+            .with_tool_calls(tool_calls))
+    }
+
+    fn complete_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<Completion> {
+        let response = self
+            .post(&stream_client()?, "streamGenerateContent?alt=sse")?
+            .json(&gemini_payload(&CompletionRequest::new(messages)))
+            .send()?
+            .check_status(self.display_name())?;
+
+        let (text, usage) = read_sse(
+            response,
+            |event| {
+                event["candidates"][0]["content"]["parts"][0]["text"]
+                    .as_str()
+                    .map(str::to_string)
+            },
+            gemini_usage,
+            on_chunk,
+        )?;
+        Ok(Completion::new(self.kind(), &self.model, text, usage))
     }
 }
 
@@ -130,29 +388,45 @@ impl Provider for OpenRouterProvider {
         )
     }
 
-    fn send_prompt(&self, input: &str) -> anyhow::Result<String> {
+    fn complete_request(&self, request: &CompletionRequest) -> anyhow::Result<Completion> {
         let api_key = self
             .api_key
             .as_ref()
             .ok_or_else(|| anyhow::anyhow!("OpenRouter API key not configured"))?;
-        let payload = serde_json::json!({
-            "model": self.model,
-            "messages": [{"role": "user", "content": input}],
-        });
         let response: serde_json::Value = reqwest::blocking::Client::new()
             .post(&self.base_url)
             .bearer_auth(api_key)
             .header("HTTP-Referer", "https://localhost")
             .header("X-Title", "Nexus")
-            .json(&payload)
+            .json(&openai_payload(&self.model, request, false))
             .send()?
-            .error_for_status()?
+            .check_status(self.display_name())?
             .json()?;
-        let text = response["choices"][0]["message"]["content"]
-            .as_str()
-            .unwrap_or("")
-            .to_string();
-        Ok(text)
+        let message = &response["choices"][0]["message"];
+        let text = message["content"].as_str().unwrap_or("").to_string();
+        Ok(Completion::new(self.kind(), &self.model, text, openai_usage(&response))
+            .with_tool_calls(openai_tool_calls(message)))
+    }
+
+    fn complete_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<Completion> {
+        let api_key = self
+            .api_key
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("OpenRouter API key not configured"))?;
+        let response = stream_client()?
+            .post(&self.base_url)
+            .bearer_auth(api_key)
+            .header("HTTP-Referer", "https://localhost")
+            .header("X-Title", "Nexus")
+            .json(&openai_payload(&self.model, &CompletionRequest::new(messages), true))
+            .send()?
+            .check_status(self.display_name())?;
+        let (text, usage) = read_sse(response, openai_delta, openai_usage, on_chunk)?;
+        Ok(Completion::new(self.kind(), &self.model, text, usage))
     }
 }
 
@@ -173,27 +447,58 @@ impl Provider for OpenCodeProvider {
         )
     }
 
-    fn send_prompt(&self, input: &str) -> anyhow::Result<String> {
+    fn complete_request(&self, request: &CompletionRequest) -> anyhow::Result<Completion> {
         let api_key = self
             .api_key
             .as_ref()
             .ok_or_else(|| anyhow::anyhow!("OpenCode API key not configured"))?;
-        let payload = serde_json::json!({
-            "model": self.model,
-            "messages": [{"role": "user", "content": input}],
-        });
         let response: serde_json::Value = reqwest::blocking::Client::new()
             .post(&self.base_url)
             .bearer_auth(api_key)
-            .json(&payload)
+            .json(&openai_payload(&self.model, request, false))
             .send()?
-            .error_for_status()?
+            .check_status(self.display_name())?
             .json()?;
-        let text = response["choices"][0]["message"]["content"]
-            .as_str()
-            .unwrap_or("")
-            .to_string();
-        Ok(text)
+        let message = &response["choices"][0]["message"];
+        let text = message["content"].as_str().unwrap_or("").to_string();
+        Ok(Completion::new(self.kind(), &self.model, text, openai_usage(&response))
+            .with_tool_calls(openai_tool_calls(message)))
+    }
+
+    fn complete_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<Completion> {
+        let api_key = self
+            .api_key
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("OpenCode API key not configured"))?;
+        let response = stream_client()?
+            .post(&self.base_url)
+            .bearer_auth(api_key)
+            .json(&openai_payload(&self.model, &CompletionRequest::new(messages), true))
+            .send()?
+            .check_status(self.display_name())?;
+        let (text, usage) = read_sse(response, openai_delta, openai_usage, on_chunk)?;
+        Ok(Completion::new(self.kind(), &self.model, text, usage))
+    }
+}
+
+impl ClaudeProvider {
+    fn post(
+        &self,
+        client: &reqwest::blocking::Client,
+    ) -> anyhow::Result<reqwest::blocking::RequestBuilder> {
+        let request = client
+            .post(&self.base_url)
+            .header("anthropic-version", "2023-06-01");
+        Ok(match credential(self.display_name(), &self.api_key, &self.oauth)? {
+            Credential::ApiKey(key) => request.header("x-api-key", key),
+            Credential::Bearer(token) => request
+                .bearer_auth(token)
+                .header("anthropic-beta", "oauth-2025-04-20"),
+        })
     }
 }
 
@@ -208,42 +513,700 @@ impl Provider for ClaudeProvider {
 
     fn dry_run_prompt(&self, input: &str) -> String {
         format!(
-            "[Claude dry-run] Would send prompt of {} chars. API key {}",
+            "[Claude dry-run] Would send prompt of {} chars. {}",
             input.len(),
-            if self.api_key.is_some() { "loaded" } else { "missing" }
+            credential_status(&self.api_key, &self.oauth)
         )
     }
 
-    fn send_prompt(&self, input: &str) -> anyhow::Result<String> {
-        let api_key = self
-            .api_key
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Claude API key not configured"))?;
-        let payload = serde_json::json!({
-            "model": self.model,
-            "max_tokens": 1024,
-            "messages": [{"role": "user", "content": input}],
-        });
+    fn complete_request(&self, request: &CompletionRequest) -> anyhow::Result<Completion> {
+        let response: serde_json::Value = self
+            .post(&reqwest::blocking::Client::new())?
+            .json(&claude_payload(&self.model, request, false))
+            .send()?
+            .check_status(self.display_name())?
+            .json()?;
+        let (text, tool_calls) = claude_content(&response["content"]);
+        let (text, tool_calls) = claude_structured(request, text, tool_calls);
+        Ok(Completion::new(self.kind(), &self.model, text, claude_usage(&response))
+            .with_tool_calls(tool_calls))
+    }
+
+    fn complete_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<Completion> {
+        let response = self
+            .post(&stream_client()?)?
+            .json(&claude_payload(&self.model, &CompletionRequest::new(messages), true))
+            .send()?
+            .check_status(self.display_name())?;
+        let (text, usage) = read_sse(
+            response,
+            |event| {
+                if event["type"] == "content_block_delta" {
+                    event["delta"]["text"].as_str().map(str::to_string)
+                } else {
+                    None
+                }
+            },
+            claude_usage,
+            on_chunk,
+        )?;
+        Ok(Completion::new(self.kind(), &self.model, text, usage))
+    }
+}
+
+impl Provider for OpenAiCompatibleProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::OpenAiCompatible
+    }
+
+    fn display_name(&self) -> &'static str {
+        "OpenAI-compatible"
+    }
+
+    fn dry_run_prompt(&self, input: &str) -> String {
+        format!(
+            "[OpenAI-compatible dry-run] Would send prompt of {} chars to {}",
+            input.len(),
+            self.base_url
+        )
+    }
+
+    fn complete_request(&self, request: &CompletionRequest) -> anyhow::Result<Completion> {
+        let mut http = reqwest::blocking::Client::new()
+            .post(format!("{}/chat/completions", self.base_url.trim_end_matches('/')));
+        if let Some(api_key) = &self.api_key {
+            http = http.bearer_auth(api_key);
+        }
+        let response: serde_json::Value = http
+            .json(&openai_payload(&self.model, request, false))
+            .send()?
+            .check_status(self.display_name())?
+            .json()?;
+        let message = &response["choices"][0]["message"];
+        let text = message["content"].as_str().unwrap_or("").to_string();
+        Ok(Completion::new(self.kind(), &self.model, text, openai_usage(&response))
+            .with_tool_calls(openai_tool_calls(message)))
+    }
+
+    fn complete_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<Completion> {
+        let mut request = stream_client()?
+            .post(format!("{}/chat/completions", self.base_url.trim_end_matches('/')));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request
+            .json(&openai_payload(&self.model, &CompletionRequest::new(messages), true))
+            .send()?
+            .check_status(self.display_name())?;
+        let (text, usage) = read_sse(response, openai_delta, openai_usage, on_chunk)?;
+        Ok(Completion::new(self.kind(), &self.model, text, usage))
+    }
+
+    fn list_models(&self) -> anyhow::Result<Vec<String>> {
+        let mut request = reqwest::blocking::Client::new()
+            .get(format!("{}/models", self.base_url.trim_end_matches('/')));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response: serde_json::Value = request
+            .send()?
+            .check_status(self.display_name())?
+            .json()?;
+        Ok(response["data"]
+            .as_array()
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|model| model["id"].as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+impl Provider for OllamaProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Ollama
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Ollama"
+    }
+
+    fn dry_run_prompt(&self, input: &str) -> String {
+        format!(
+            "[Ollama dry-run] Would send prompt of {} chars to {} ({})",
+            input.len(),
+            self.base_url,
+            self.model
+        )
+    }
+
+    fn complete_request(&self, request: &CompletionRequest) -> anyhow::Result<Completion> {
+        let mut http = reqwest::blocking::Client::new()
+            .post(format!("{}/api/chat", self.base_url.trim_end_matches('/')));
+        if let Some(api_key) = &self.api_key {
+            http = http.bearer_auth(api_key);
+        }
+        let response: serde_json::Value = http
+            .json(&ollama_payload(&self.model, request, false))
+            .send()?
+            .check_status(self.display_name())?
+            .json()?;
+        let message = &response["message"];
+        let text = message["content"].as_str().unwrap_or("").to_string();
+        Ok(Completion::new(self.kind(), &self.model, text, ollama_usage(&response))
+            .with_tool_calls(openai_tool_calls(message)))
+    }
+
+    fn complete_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<Completion> {
+        let mut request = stream_client()?
+            .post(format!("{}/api/chat", self.base_url.trim_end_matches('/')));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request
+            .json(&ollama_payload(&self.model, &CompletionRequest::new(messages), true))
+            .send()?
+            .check_status(self.display_name())?;
+
+        let mut text = String::new();
+        let mut usage = None;
+        for line in std::io::BufReader::new(response).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event: serde_json::Value = serde_json::from_str(&line)?;
+            if let Some(error) = event.get("error") {
+                return Err(anyhow::anyhow!("Stream error: {}", error));
+            }
+            if let Some(chunk) = event["message"]["content"].as_str() {
+                if !chunk.is_empty() {
+                    on_chunk(chunk);
+                    text.push_str(chunk);
+                }
+            }
+            if event["done"].as_bool().unwrap_or(false) {
+                usage = ollama_usage(&event);
+                break;
+            }
+        }
+        Ok(Completion::new(self.kind(), &self.model, text, usage))
+    }
+
+    fn list_models(&self) -> anyhow::Result<Vec<String>> {
         let response: serde_json::Value = reqwest::blocking::Client::new()
-            .post(&self.base_url)
-            .header("x-api-key", api_key)
-            .header("anthropic-version", "2023-06-01")
-            .json(&payload)
+            .get(format!("{}/api/tags", self.base_url.trim_end_matches('/')))
             .send()?
-            .error_for_status()?
+            .check_status(self.display_name())?
             .json()?;
-        let text = response["content"][0]["text"]
-            .as_str()
-            .unwrap_or("")
-            .to_string();
-        Ok(text)
+        Ok(response["models"]
+            .as_array()
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|model| model["name"].as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+enum Credential {
+    ApiKey(String),
+    Bearer(String),
+}
+
+fn credential(
+    provider: &str,
+    api_key: &Option<String>,
+    oauth: &Option<OAuthSession>,
+) -> anyhow::Result<Credential> {
+    if let Some(session) = oauth {
+        if let Some(token) = session.access_token()? {
+            return Ok(Credential::Bearer(token));
+        }
+    }
+    api_key.clone().map(Credential::ApiKey).ok_or_else(|| {
+        anyhow::anyhow!(
+            "{} API key not configured (or run `nexus auth login`)",
+            provider
+        )
+    })
+}
+
+fn credential_status(api_key: &Option<String>, oauth: &Option<OAuthSession>) -> &'static str {
+    if oauth.is_some() {
+        "OAuth configured"
+    } else if api_key.is_some() {
+        "API key loaded"
+    } else {
+        "API key missing"
+    }
+}
+
+fn system_prompt(messages: &[ChatMessage]) -> Option<String> {
+    let system: Vec<&str> = messages
+        .iter()
+        .filter(|message| message.role == Role::System)
+        .map(|message| message.content.as_str())
+        .collect();
+    if system.is_empty() {
+        None
+    } else {
+        Some(system.join("\n\n"))
+    }
+}
+
+fn gemini_payload(request: &CompletionRequest) -> serde_json::Value {
+    let messages = &request.messages;
+    let mut names: HashMap<&str, &str> = HashMap::new();
+    let mut contents: Vec<serde_json::Value> = Vec::new();
+    for message in messages.iter().filter(|message| message.role != Role::System) {
+        if message.role == Role::Tool {
+            let id = message.tool_call_id.as_deref().unwrap_or("");
+            let part = serde_json::json!({
+                "functionResponse": {
+                    "name": names.get(id).copied().unwrap_or(id),
+                    "response": {"content": message.content}
+                }
+            });
+            match contents
+                .last_mut()
+                .filter(|turn| turn["parts"][0].get("functionResponse").is_some())
+                .and_then(|turn| turn["parts"].as_array_mut())
+            {
+                Some(parts) => parts.push(part),
+                None => contents.push(serde_json::json!({ "role": "user", "parts": [part] })),
+            }
+            continue;
+        }
+        let role = if message.role == Role::Assistant { "model" } else { "user" };
+        let mut parts = Vec::new();
+        if !message.content.is_empty() || message.tool_calls.is_empty() {
+            parts.push(serde_json::json!({"text": message.content}));
+        }
+        for call in &message.tool_calls {
+            names.insert(&call.id, &call.name);
+            parts.push(serde_json::json!({
+                "functionCall": {"name": call.name, "args": call.arguments}
+            }));
+        }
+        contents.push(serde_json::json!({ "role": role, "parts": parts }));
+    }
+    let mut payload = serde_json::json!({ "contents": contents });
+    if let Some(system) = system_prompt(messages) {
+        payload["systemInstruction"] = serde_json::json!({ "parts": [{"text": system}] });
+    }
+    if let Some(schema) = &request.response_schema {
+        payload["generationConfig"] = serde_json::json!({
+            "responseMimeType": "application/json",
+            "responseSchema": schema,
+        });
+    }
+    if !request.tools.is_empty() {
+        let declarations: Vec<serde_json::Value> = request
+            .tools
+            .iter()
+            .map(|tool| {
+                serde_json::json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": tool.parameters,
+                })
+            })
+            .collect();
+        payload["tools"] = serde_json::json!([{ "functionDeclarations": declarations }]);
+    }
+    payload
+}
+
+fn openai_messages(messages: &[ChatMessage], string_arguments: bool) -> Vec<serde_json::Value> {
+    messages
+        .iter()
+        .map(|message| {
+            let mut value = serde_json::json!({
+                "role": message.role,
+                "content": message.content,
+            });
+            if let Some(id) = &message.tool_call_id {
+                value["tool_call_id"] = serde_json::json!(id);
+            }
+            if !message.tool_calls.is_empty() {
+                let calls: Vec<serde_json::Value> = message
+                    .tool_calls
+                    .iter()
+                    .map(|call| {
+                        let arguments = if string_arguments {
+                            serde_json::json!(call.arguments.to_string())
+                        } else {
+                            call.arguments.clone()
+                        };
+                        serde_json::json!({
+                            "id": call.id,
+                            "type": "function",
+                            "function": {"name": call.name, "arguments": arguments}
+                        })
+                    })
+                    .collect();
+                value["tool_calls"] = serde_json::json!(calls);
+            }
+            value
+        })
+        .collect()
+}
+
+fn openai_tools(tools: &[ToolDefinition]) -> serde_json::Value {
+    tools
+        .iter()
+        .map(|tool| {
+            serde_json::json!({
+                "type": "function",
+                "function": {
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": tool.parameters,
+                }
+            })
+        })
+        .collect()
+}
+
+fn openai_payload(model: &str, request: &CompletionRequest, stream: bool) -> serde_json::Value {
+    let (messages, tools) = (&request.messages, &request.tools);
+    let mut payload = serde_json::json!({
+        "model": model,
+        "messages": openai_messages(messages, true),
+    });
+    if !tools.is_empty() {
+        payload["tools"] = openai_tools(tools);
+    }
+    if let Some(schema) = &request.response_schema {
+        payload["response_format"] = serde_json::json!({
+            "type": "json_schema",
+            "json_schema": {"name": "response", "schema": schema}
+        });
+    }
+    if stream {
+        payload["stream"] = serde_json::json!(true);
+        payload["stream_options"] = serde_json::json!({ "include_usage": true });
+    }
+    payload
+}
+
+fn ollama_payload(model: &str, request: &CompletionRequest, stream: bool) -> serde_json::Value {
+    let (messages, tools) = (&request.messages, &request.tools);
+    let mut payload = serde_json::json!({
+        "model": model,
+        "messages": openai_messages(messages, false),
+        "stream": stream,
+    });
+    if !tools.is_empty() {
+        payload["tools"] = openai_tools(tools);
+    }
+    if let Some(schema) = &request.response_schema {
+        payload["format"] = schema.clone();
+    }
+    payload
+}
+
+fn claude_payload(model: &str, request: &CompletionRequest, stream: bool) -> serde_json::Value {
+    let (messages, tools) = (&request.messages, &request.tools);
+    let mut turns: Vec<serde_json::Value> = Vec::new();
+    for message in messages.iter().filter(|message| message.role != Role::System) {
+        if message.role == Role::Tool {
+            let block = serde_json::json!({
+                "type": "tool_result",
+                "tool_use_id": message.tool_call_id,
+                "content": message.content,
+            });
+            match turns
+                .last_mut()
+                .filter(|turn| turn["content"][0]["type"] == "tool_result")
+                .and_then(|turn| turn["content"].as_array_mut())
+            {
+                Some(blocks) => blocks.push(block),
+                None => turns.push(serde_json::json!({ "role": "user", "content": [block] })),
+            }
+        } else if !message.tool_calls.is_empty() {
+            let mut blocks = Vec::new();
+            if !message.content.is_empty() {
+                blocks.push(serde_json::json!({"type": "text", "text": message.content}));
+            }
+            for call in &message.tool_calls {
+                blocks.push(serde_json::json!({
+                    "type": "tool_use",
+                    "id": call.id,
+                    "name": call.name,
+                    "input": call.arguments,
+                }));
+            }
+            turns.push(serde_json::json!({ "role": "assistant", "content": blocks }));
+        } else {
+            turns.push(serde_json::json!({
+                "role": message.role,
+                "content": message.content,
+            }));
+        }
+    }
+    let mut payload = serde_json::json!({
+        "model": model,
+        "max_tokens": 1024,
+        "messages": turns,
+    });
+    if let Some(system) = system_prompt(messages) {
+        payload["system"] = serde_json::json!(system);
+    }
+    let mut tools: Vec<serde_json::Value> = tools
+        .iter()
+        .map(|tool| {
+            serde_json::json!({
+                "name": tool.name,
+                "description": tool.description,
+                "input_schema": tool.parameters,
+            })
+        })
+        .collect();
+    if let Some(schema) = &request.response_schema {
+        tools.push(serde_json::json!({
+            "name": CLAUDE_RESPONSE_TOOL,
+            "description": "Return the response.",
+            "input_schema": claude_input_schema(schema),
+        }));
+        payload["tool_choice"] = serde_json::json!({"type": "tool", "name": CLAUDE_RESPONSE_TOOL});
+    }
+    if !tools.is_empty() {
+        payload["tools"] = serde_json::json!(tools);
+    }
+    if stream {
+        payload["stream"] = serde_json::json!(true);
+    }
+    payload
+}
+
+// Claude tool inputs must be objects, so other schemas are wrapped in a `value` property.
+fn claude_input_schema(schema: &serde_json::Value) -> serde_json::Value {
+    if schema["type"] == "object" {
+        return schema.clone();
+    }
+    serde_json::json!({
+        "type": "object",
+        "properties": {"value": schema},
+        "required": ["value"],
+    })
+}
+
+fn claude_structured(
+    request: &CompletionRequest,
+    text: String,
+    mut tool_calls: Vec<ToolCall>,
+) -> (String, Vec<ToolCall>) {
+    let Some(schema) = &request.response_schema else {
+        return (text, tool_calls);
+    };
+    let Some(index) = tool_calls
+        .iter()
+        .position(|call| call.name == CLAUDE_RESPONSE_TOOL)
+    else {
+        return (text, tool_calls);
+    };
+    let input = tool_calls.remove(index).arguments;
+    let value = if schema["type"] == "object" {
+        input
+    } else {
+        input["value"].clone()
+    };
+    (value.to_string(), tool_calls)
+}
+
+fn openai_tool_calls(message: &serde_json::Value) -> Vec<ToolCall> {
+    let calls = match message["tool_calls"].as_array() {
+        Some(calls) => calls,
+        None => return Vec::new(),
+    };
+    calls
+        .iter()
+        .enumerate()
+        .map(|(index, call)| {
+            let arguments = match &call["function"]["arguments"] {
+                serde_json::Value::String(raw) => serde_json::from_str(raw)
+                    .unwrap_or_else(|_| serde_json::Value::String(raw.clone())),
+                other => other.clone(),
+            };
+            ToolCall {
+                id: call["id"]
+                    .as_str()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("call_{}", index)),
+                name: call["function"]["name"].as_str().unwrap_or("").to_string(),
+                arguments,
+            }
+        })
+        .collect()
+}
+
+fn claude_content(content: &serde_json::Value) -> (String, Vec<ToolCall>) {
+    let mut text = String::new();
+    let mut tool_calls = Vec::new();
+    for block in content.as_array().into_iter().flatten() {
+        match block["type"].as_str() {
+            Some("text") => text.push_str(block["text"].as_str().unwrap_or("")),
+            Some("tool_use") => tool_calls.push(ToolCall {
+                id: block["id"].as_str().unwrap_or("").to_string(),
+                name: block["name"].as_str().unwrap_or("").to_string(),
+                arguments: block["input"].clone(),
+            }),
+            _ => {}
+        }
     }
+    (text, tool_calls)
+}
+
+fn gemini_parts(parts: &serde_json::Value) -> (String, Vec<ToolCall>) {
+    let mut text = String::new();
+    let mut tool_calls = Vec::new();
+    for (index, part) in parts.as_array().into_iter().flatten().enumerate() {
+        if let Some(chunk) = part["text"].as_str() {
+            text.push_str(chunk);
+        }
+        if let Some(call) = part.get("functionCall") {
+            tool_calls.push(ToolCall {
+                id: call["id"]
+                    .as_str()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("call_{}", index)),
+                name: call["name"].as_str().unwrap_or("").to_string(),
+                arguments: call["args"].clone(),
+            });
+        }
+    }
+    (text, tool_calls)
+}
+
+trait CheckStatus: Sized {
+    fn check_status(self, provider: &str) -> Result<Self, ProviderError>;
+}
+
+impl CheckStatus for reqwest::blocking::Response {
+    fn check_status(self, provider: &str) -> Result<Self, ProviderError> {
+        let status = self.status();
+        if status.is_success() {
+            return Ok(self);
+        }
+        let retry_after = self
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let body: String = self.text().unwrap_or_default().chars().take(500).collect();
+        Err(ProviderError::Http {
+            provider: provider.to_string(),
+            status: status.as_u16(),
+            retry_after,
+            body,
+        })
+    }
+}
+
+fn stream_client() -> anyhow::Result<reqwest::blocking::Client> {
+    Ok(reqwest::blocking::Client::builder().timeout(None).build()?)
+}
+
+fn openai_delta(event: &serde_json::Value) -> Option<String> {
+    event["choices"][0]["delta"]["content"]
+        .as_str()
+        .map(str::to_string)
+}
+
+fn openai_usage(response: &serde_json::Value) -> Option<Usage> {
+    let usage = response.get("usage")?;
+    Some(Usage {
+        prompt_tokens: usage["prompt_tokens"].as_u64()?,
+        completion_tokens: usage["completion_tokens"].as_u64().unwrap_or(0),
+    })
+}
+
+fn claude_usage(response: &serde_json::Value) -> Option<Usage> {
+    let usage = response
+        .get("usage")
+        .or_else(|| response["message"].get("usage"))?;
+    Some(Usage {
+        prompt_tokens: usage["input_tokens"].as_u64().unwrap_or(0),
+        completion_tokens: usage["output_tokens"].as_u64().unwrap_or(0),
+    })
+}
+
+fn gemini_usage(response: &serde_json::Value) -> Option<Usage> {
+    let usage = response.get("usageMetadata")?;
+    Some(Usage {
+        prompt_tokens: usage["promptTokenCount"].as_u64().unwrap_or(0),
+        completion_tokens: usage["candidatesTokenCount"].as_u64().unwrap_or(0),
+    })
+}
+
+fn ollama_usage(response: &serde_json::Value) -> Option<Usage> {
+    Some(Usage {
+        prompt_tokens: response["prompt_eval_count"].as_u64()?,
+        completion_tokens: response["eval_count"].as_u64().unwrap_or(0),
+    })
+}
+
+fn read_sse(
+    response: impl std::io::Read,
+    extract: impl Fn(&serde_json::Value) -> Option<String>,
+    usage_of: fn(&serde_json::Value) -> Option<Usage>,
+    on_chunk: &mut dyn FnMut(&str),
+) -> anyhow::Result<(String, Option<Usage>)> {
+    let mut text = String::new();
+    let mut usage: Option<Usage> = None;
+    for line in std::io::BufReader::new(response).lines() {
+        let line = line?;
+        let data = match line.strip_prefix("data:") {
+            Some(data) => data.trim(),
+            None => continue,
+        };
+        if data == "[DONE]" {
+            break;
+        }
+        let event: serde_json::Value = match serde_json::from_str(data) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        if let Some(error) = event.get("error") {
+            return Err(anyhow::anyhow!("Stream error: {}", error));
+        }
+        if let Some(chunk) = extract(&event) {
+            if !chunk.is_empty() {
+                on_chunk(&chunk);
+                text.push_str(&chunk);
+            }
+        }
+        if let Some(event_usage) = usage_of(&event) {
+            usage.get_or_insert_with(Usage::default).merge(event_usage);
+        }
+    }
+    Ok((text, usage))
 }
 
 pub fn build_provider(kind: &ProviderKind, settings: ProviderSettings) -> Box<dyn Provider> {
     match kind {
         ProviderKind::Gemini => Box::new(GeminiProvider {
             api_key: settings.api_key,
+            oauth: settings.oauth,
             model: settings.model.unwrap_or_else(|| "gemini-1.5-pro".to_string()),
             base_url: settings
                 .base_url
@@ -267,6 +1230,7 @@ pub fn build_provider(kind: &ProviderKind, settings: ProviderSettings) -> Box<dy
         }),
         ProviderKind::Claude => Box::new(ClaudeProvider {
             api_key: settings.api_key,
+            oauth: settings.oauth,
             model: settings
                 .model
                 .unwrap_or_else(|| "claude-3-5-sonnet-20240620".to_string()),
@@ -274,5 +1238,20 @@ pub fn build_provider(kind: &ProviderKind, settings: ProviderSettings) -> Box<dy
                 .base_url
                 .unwrap_or_else(|| "https://api.anthropic.com/v1/messages".to_string()),
         }),
+        ProviderKind::OpenAiCompatible => Box::new(OpenAiCompatibleProvider {
+            api_key: settings.api_key,
+            model: settings.model.unwrap_or_else(|| "local-model".to_string()),
+            base_url: settings
+                .base_url
+                .unwrap_or_else(|| "http://localhost:8080/v1".to_string()),
+        }),
+        ProviderKind::Ollama => Box::new(OllamaProvider {
+            api_key: settings.api_key,
+            model: settings.model.unwrap_or_else(|| "llama3.1".to_string()),
+            base_url: settings
+                .base_url
+                .unwrap_or_else(|| "http://localhost:11434".to_string()),
+        }),
+        ProviderKind::Mock => Box::new(MockProvider::from_fixture(settings.fixture.as_deref())),
     }
 }
@@ -0,0 +1,311 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+use crate::{cache::CacheState, usage::estimate_tokens};
+
+pub const DEFAULT_MAP_TOKENS: usize = 2000;
+const MAX_OUTLINE_BYTES: u64 = 512 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Symbol {
+    pub kind: String,
+    pub name: String,
+    pub line: usize,
+    pub public: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileOutline {
+    pub hash: String,
+    pub language: Option<String>,
+    pub symbols: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RepoMap {
+    pub root: String,
+    pub files: BTreeMap<String, FileOutline>,
+}
+
+impl RepoMap {
+    pub fn refresh(&mut self, cache: &CacheState) -> usize {
+        let extractor = Extractor::new();
+        let mut parsed = 0;
+        self.root = cache.root.display().to_string();
+        self.files.retain(|path, _| cache.files.contains_key(path));
+        for (path, meta) in &cache.files {
+            if self
+                .files
+                .get(path)
+                .is_some_and(|outline| outline.hash == meta.hash)
+            {
+                continue;
+            }
+            let language = language_for(path);
+            let symbols = match language {
+                Some(language) if meta.size <= MAX_OUTLINE_BYTES => {
+                    std::fs::read_to_string(cache.root.join(path))
+                        .map(|source| extractor.symbols(language, &source))
+                        .unwrap_or_default()
+                }
+                _ => Vec::new(),
+            };
+            self.files.insert(
+                path.clone(),
+                FileOutline {
+                    hash: meta.hash.clone(),
+                    language: language.map(str::to_string),
+                    symbols,
+                },
+            );
+            parsed += 1;
+        }
+        parsed
+    }
+
+    pub fn languages(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for outline in self.files.values() {
+            if let Some(language) = &outline.language {
+                *counts.entry(language.clone()).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    pub fn render(&self) -> String {
+        self.render_with(false, None)
+    }
+
+    pub fn render_within(&self, max_tokens: usize) -> String {
+        let full = self.render();
+        if estimate_tokens(&full) <= max_tokens {
+            return full;
+        }
+        let deepest = self
+            .files
+            .keys()
+            .map(|path| path.matches('/').count())
+            .max()
+            .unwrap_or_default();
+        let mut out = self.render_with(true, None);
+        let mut depth = deepest;
+        while estimate_tokens(&out) > max_tokens && depth > 0 {
+            depth -= 1;
+            out = self.render_with(true, Some(depth));
+        }
+        if estimate_tokens(&out) <= max_tokens {
+            return out;
+        }
+        let marker = "\n... (map truncated)";
+        let mut kept = String::new();
+        for line in out.split_inclusive('\n') {
+            if estimate_tokens(&kept) + estimate_tokens(line) + estimate_tokens(marker) > max_tokens {
+                break;
+            }
+            kept.push_str(line);
+        }
+        format!("{}{}", kept.trim_end_matches('\n'), marker)
+    }
+
+    fn render_with(&self, public_only: bool, max_depth: Option<usize>) -> String {
+        let languages: Vec<String> = self
+            .languages()
+            .iter()
+            .map(|(language, count)| format!("{} {}", language, count))
+            .collect();
+        let mut out = format!(
+            "{} files ({})",
+            self.files.len(),
+            if languages.is_empty() {
+                "no known languages".to_string()
+            } else {
+                languages.join(", ")
+            }
+        );
+        let collapsed = |path: &str| -> Option<String> {
+            let depth = max_depth?;
+            let parts: Vec<&str> = path.split('/').collect();
+            (parts.len() > depth + 1).then(|| parts[..=depth].join("/"))
+        };
+        let mut hidden: BTreeMap<String, usize> = BTreeMap::new();
+        for path in self.files.keys() {
+            if let Some(dir) = collapsed(path) {
+                *hidden.entry(dir).or_insert(0) += 1;
+            }
+        }
+        let mut open: Vec<String> = Vec::new();
+        let mut last_collapsed: Option<String> = None;
+        for (path, outline) in &self.files {
+            let folded = collapsed(path);
+            if folded.is_some() && folded == last_collapsed {
+                continue;
+            }
+            let entry = folded.clone().unwrap_or_else(|| path.clone());
+            let parts: Vec<&str> = entry.split('/').collect();
+            let (dirs, name) = parts.split_at(parts.len() - 1);
+            let shared = open
+                .iter()
+                .zip(dirs)
+                .take_while(|(open, dir)| open == dir)
+                .count();
+            open.truncate(shared);
+            for dir in &dirs[shared..] {
+                out.push_str(&format!("\n{}{}/", "  ".repeat(open.len()), dir));
+                open.push(dir.to_string());
+            }
+            let indent = "  ".repeat(open.len());
+            if let Some(dir) = &folded {
+                out.push_str(&format!("\n{}{}/ ({} files)", indent, name[0], hidden[dir]));
+                last_collapsed = folded;
+                continue;
+            }
+            out.push_str(&format!("\n{}{}", indent, name[0]));
+            if let Some(language) = &outline.language {
+                out.push_str(&format!(" [{}]", language));
+            }
+            let symbols: Vec<String> = outline
+                .symbols
+                .iter()
+                .filter(|symbol| symbol.public || !public_only)
+                .map(|symbol| format!("{} {}", symbol.kind, symbol.name))
+                .collect();
+            if !symbols.is_empty() {
+                out.push_str(&format!(": {}", symbols.join(", ")));
+            }
+        }
+        out
+    }
+}
+
+pub fn language_for(path: &str) -> Option<&'static str> {
+    let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "rs" => "rust",
+        "py" | "pyi" => "python",
+        "ts" | "tsx" | "mts" | "cts" => "typescript",
+        "js" | "jsx" | "mjs" | "cjs" => "javascript",
+        "go" => "go",
+        "md" => "markdown",
+        "toml" => "toml",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "sh" | "bash" => "shell",
+        "html" => "html",
+        "css" => "css",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "hpp" => "cpp",
+        "java" => "java",
+        _ => return None,
+    })
+}
+
+struct Extractor {
+    rust: Regex,
+    rust_impl: Regex,
+    python: Regex,
+    script: Regex,
+    go_func: Regex,
+    go_type: Regex,
+}
+
+impl Extractor {
+    fn new() -> Self {
+        Self {
+            rust: Regex::new(
+                r#"^(pub(?:\([^)]*\))?\s+)?(?:(?:async|unsafe|const|extern\s+"[^"]*")\s+)*(fn|struct|enum|trait|type|mod|const|static|union)\s+([A-Za-z_][A-Za-z0-9_]*)"#,
+            )
+            .expect("valid rust pattern"),
+            rust_impl: Regex::new(r"^(?:unsafe\s+)?impl(?:<[^{]*?>)?\s+([^{]+?)\s*(?:where\b.*)?\{?$")
+                .expect("valid impl pattern"),
+            python: Regex::new(r"^(?:async\s+)?(def|class)\s+([A-Za-z_][A-Za-z0-9_]*)")
+                .expect("valid python pattern"),
+            script: Regex::new(
+                r"^(export\s+)?(?:default\s+)?(?:declare\s+)?(?:abstract\s+)?(?:async\s+)?(function\*?|class|interface|type|enum|const|let|var)\s+([A-Za-z_$][A-Za-z0-9_$]*)",
+            )
+            .expect("valid script pattern"),
+            go_func: Regex::new(r"^func\s+(\([^)]*\)\s*)?([A-Za-z_][A-Za-z0-9_]*)")
+                .expect("valid go func pattern"),
+            go_type: Regex::new(r"^type\s+([A-Za-z_][A-Za-z0-9_]*)\s+(struct|interface)?")
+                .expect("valid go type pattern"),
+        }
+    }
+
+    fn symbols(&self, language: &str, source: &str) -> Vec<Symbol> {
+        let mut symbols = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let symbol = match language {
+                "rust" => self.rust_symbol(line),
+                "python" => self.python.captures(line).map(|captures| Symbol {
+                    kind: captures[1].to_string(),
+                    public: !captures[2].starts_with('_'),
+                    name: captures[2].to_string(),
+                    line: 0,
+                }),
+                "typescript" | "javascript" => self.script.captures(line).map(|captures| Symbol {
+                    kind: captures[2].trim_end_matches('*').to_string(),
+                    name: captures[3].to_string(),
+                    public: captures.get(1).is_some(),
+                    line: 0,
+                }),
+                "go" => self.go_symbol(line),
+                _ => None,
+            };
+            if let Some(symbol) = symbol {
+                symbols.push(Symbol {
+                    line: index + 1,
+                    ..symbol
+                });
+            }
+        }
+        symbols
+    }
+
+    fn rust_symbol(&self, line: &str) -> Option<Symbol> {
+        if let Some(captures) = self.rust.captures(line) {
+            return Some(Symbol {
+                kind: captures[2].to_string(),
+                name: captures[3].to_string(),
+                public: captures.get(1).is_some(),
+                line: 0,
+            });
+        }
+        self.rust_impl.captures(line).map(|captures| Symbol {
+            kind: "impl".to_string(),
+            name: captures[1].to_string(),
+            public: false,
+            line: 0,
+        })
+    }
+
+    fn go_symbol(&self, line: &str) -> Option<Symbol> {
+        if let Some(captures) = self.go_func.captures(line) {
+            let name = captures[2].to_string();
+            return Some(Symbol {
+                kind: if captures.get(1).is_some() {
+                    "method"
+                } else {
+                    "func"
+                }
+                .to_string(),
+                public: name.starts_with(|ch: char| ch.is_uppercase()),
+                name,
+                line: 0,
+            });
+        }
+        self.go_type.captures(line).map(|captures| {
+            let name = captures[1].to_string();
+            Symbol {
+                kind: captures
+                    .get(2)
+                    .map(|kind| kind.as_str())
+                    .unwrap_or("type")
+                    .to_string(),
+                public: name.starts_with(|ch: char| ch.is_uppercase()),
+                name,
+                line: 0,
+            }
+        })
+    }
+}
@@ -1,6 +1,8 @@
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+use crate::filter::{IgnoreConfig, RepoFilter};
+
 const DEFAULT_IMAGE: &str = "ubuntu:22.04";
 
 #[derive(Debug, Clone)]
@@ -16,6 +18,7 @@ pub struct ShadowOptions {
     pub image: String,
     pub allow_exec: bool,
     pub hydrate: bool,
+    pub ignore: IgnoreConfig,
 }
 
 impl Default for ShadowOptions {
@@ -25,6 +28,7 @@ impl Default for ShadowOptions {
             image: DEFAULT_IMAGE.to_string(),
             allow_exec: false,
             hydrate: false,
+            ignore: IgnoreConfig::default(),
         }
     }
 }
@@ -56,7 +60,7 @@ pub fn shadow_run_with_options(command: &str, options: ShadowOptions) -> anyhow:
         });
     }
 
-    let temp_root = stage_workspace(&options.root)?;
+    let temp_root = stage_workspace(&options.root, &options.ignore)?;
     let workdir = temp_root.to_string_lossy().to_string();
     let docker_output = Command::new("docker")
         .args([
@@ -78,7 +82,7 @@ pub fn shadow_run_with_options(command: &str, options: ShadowOptions) -> anyhow:
         + &String::from_utf8_lossy(&docker_output.stderr);
 
     if options.hydrate && status_code == Some(0) {
-        hydrate_workspace(&temp_root, &options.root)?;
+        hydrate_workspace(&temp_root, &options.root, &options.ignore)?;
     }
 
     Ok(ShadowResult {
@@ -96,7 +100,7 @@ fn docker_available() -> bool {
         .unwrap_or(false)
 }
 
-fn stage_workspace(root: &Path) -> anyhow::Result<PathBuf> {
+fn stage_workspace(root: &Path, rules: &IgnoreConfig) -> anyhow::Result<PathBuf> {
     let temp_root = std::env::temp_dir().join(format!(
         "nexus-shadow-{}-{}",
         std::process::id(),
@@ -106,48 +110,38 @@ fn stage_workspace(root: &Path) -> anyhow::Result<PathBuf> {
             .as_secs()
     ));
     std::fs::create_dir_all(&temp_root)?;
-    copy_dir_filtered(root, &temp_root)?;
+    copy_dir_filtered(root, &temp_root, rules)?;
     Ok(temp_root)
 }
 
-fn hydrate_workspace(staged: &Path, target: &Path) -> anyhow::Result<()> {
-    let backup = stage_workspace(target)?;
-    if let Err(err) = copy_dir_filtered(staged, target) {
-        let _ = copy_dir_filtered(&backup, target);
+fn hydrate_workspace(staged: &Path, target: &Path, rules: &IgnoreConfig) -> anyhow::Result<()> {
+    let backup = stage_workspace(target, rules)?;
+    if let Err(err) = copy_dir_filtered(staged, target, rules) {
+        let _ = copy_dir_filtered(&backup, target, rules);
         return Err(err);
     }
     Ok(())
 }
 
-fn copy_dir_filtered(src: &Path, dest: &Path) -> anyhow::Result<()> {
-    for entry in walkdir::WalkDir::new(src).into_iter().filter_map(Result::ok) {
+fn copy_dir_filtered(src: &Path, dest: &Path, rules: &IgnoreConfig) -> anyhow::Result<()> {
+    let filter = RepoFilter::new(src, rules)?;
+    for entry in filter.entries() {
         let path = entry.path();
         let rel = match path.strip_prefix(src) {
             Ok(rel) => rel,
             Err(_) => continue,
         };
-        if rel.as_os_str().is_empty() || should_skip(rel) {
-            continue;
-        }
         let target_path = dest.join(rel);
-        if entry.file_type().is_dir() {
-            std::fs::create_dir_all(&target_path)?;
-        } else if entry.file_type().is_file() {
-            if let Some(parent) = target_path.parent() {
-                std::fs::create_dir_all(parent)?;
+        match entry.file_type() {
+            Some(kind) if kind.is_dir() => std::fs::create_dir_all(&target_path)?,
+            Some(kind) if kind.is_file() => {
+                if let Some(parent) = target_path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::copy(path, &target_path)?;
             }
-            std::fs::copy(path, &target_path)?;
+            _ => {}
         }
     }
     Ok(())
 }
-
-fn should_skip(path: &Path) -> bool {
-    path.components().any(|component| {
-        let name = component.as_os_str().to_string_lossy();
-        matches!(
-            name.as_ref(),
-            ".git" | "target" | "node_modules" | ".venv"
-        )
-    })
-}
@@ -0,0 +1,368 @@
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, Key, KeyInit, Nonce};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use crate::{config::Config, mcp::IntegrationConfig, storage::secrets_path};
+
+const VAULT_VERSION: u32 = 1;
+const PASSPHRASE_ENV: &str = "NEXUS_VAULT_PASSPHRASE";
+const KEY_FILE_ENV: &str = "NEXUS_VAULT_KEY_FILE";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecretsConfig {
+    pub vault: Option<String>,
+    pub key_file: Option<String>,
+}
+
+impl SecretsConfig {
+    pub fn vault_path(&self) -> anyhow::Result<PathBuf> {
+        match &self.vault {
+            Some(path) => Ok(expand_home(path)),
+            None => secrets_path(),
+        }
+    }
+
+    pub fn key(&self) -> Option<VaultKey> {
+        VaultKey::from_env(self)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum VaultKey {
+    Passphrase(String),
+    KeyFile(PathBuf),
+}
+
+impl VaultKey {
+    pub fn from_env(config: &SecretsConfig) -> Option<Self> {
+        if let Ok(path) = std::env::var(KEY_FILE_ENV) {
+            return Some(Self::KeyFile(expand_home(&path)));
+        }
+        if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+            return Some(Self::Passphrase(passphrase));
+        }
+        config
+            .key_file
+            .as_deref()
+            .map(|path| Self::KeyFile(expand_home(path)))
+    }
+
+    pub fn prompt() -> anyhow::Result<Self> {
+        let passphrase = rpassword::prompt_password("Vault passphrase: ")?;
+        if passphrase.is_empty() {
+            return Err(anyhow::anyhow!("Empty vault passphrase"));
+        }
+        Ok(Self::Passphrase(passphrase))
+    }
+
+    fn kdf(&self) -> &'static str {
+        match self {
+            Self::Passphrase(_) => "passphrase",
+            Self::KeyFile(_) => "key-file",
+        }
+    }
+
+    fn derive(&self, salt: &[u8]) -> anyhow::Result<[u8; 32]> {
+        let material = match self {
+            Self::Passphrase(passphrase) => passphrase.as_bytes().to_vec(),
+            Self::KeyFile(path) => std::fs::read(path).map_err(|err| {
+                anyhow::anyhow!("Cannot read vault key file {}: {}", path.display(), err)
+            })?,
+        };
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(&material, salt, &mut key)
+            .map_err(|err| anyhow::anyhow!("Vault key derivation failed: {}", err))?;
+        Ok(key)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SealedVault {
+    version: u32,
+    kdf: String,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SecretVault {
+    pub entries: BTreeMap<String, String>,
+}
+
+impl SecretVault {
+    pub fn open(path: &Path, key: &VaultKey) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let sealed: SealedVault = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if sealed.version != VAULT_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported vault version {} in {}",
+                sealed.version,
+                path.display()
+            ));
+        }
+        let salt = STANDARD.decode(&sealed.salt)?;
+        let nonce = STANDARD.decode(&sealed.nonce)?;
+        let ciphertext = STANDARD.decode(&sealed.ciphertext)?;
+        if nonce.len() != 12 {
+            return Err(anyhow::anyhow!("Corrupt vault nonce in {}", path.display()));
+        }
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key.derive(&salt)?));
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "Cannot unlock {}: wrong {} or tampered vault",
+                    path.display(),
+                    sealed.kdf
+                )
+            })?;
+        Ok(Self {
+            entries: serde_json::from_slice(&plaintext)?,
+        })
+    }
+
+    pub fn save(&self, path: &Path, key: &VaultKey) -> anyhow::Result<()> {
+        let mut salt = [0u8; 16];
+        let mut nonce = [0u8; 12];
+        fill_random(&mut salt)?;
+        fill_random(&mut nonce)?;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key.derive(&salt)?));
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                serde_json::to_vec(&self.entries)?.as_slice(),
+            )
+            .map_err(|_| anyhow::anyhow!("Vault encryption failed"))?;
+        let sealed = SealedVault {
+            version: VAULT_VERSION,
+            kdf: key.kdf().to_string(),
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+        write_private(path, serde_json::to_string_pretty(&sealed)?.as_bytes())
+    }
+}
+
+type ResolverKey = (Option<PathBuf>, Option<String>);
+
+static SHARED_RESOLVERS: OnceLock<Mutex<BTreeMap<ResolverKey, Arc<SecretResolver>>>> =
+    OnceLock::new();
+
+pub struct SecretResolver {
+    vault_path: Option<PathBuf>,
+    key: Option<VaultKey>,
+    vault: OnceLock<Result<SecretVault, String>>,
+}
+
+impl SecretResolver {
+    pub fn new(vault_path: Option<PathBuf>, key: Option<VaultKey>) -> Self {
+        Self {
+            vault_path,
+            key,
+            vault: OnceLock::new(),
+        }
+    }
+
+    pub fn from_config(config: &SecretsConfig) -> Self {
+        Self::new(config.vault_path().ok(), config.key())
+    }
+
+    pub fn shared(config: &SecretsConfig) -> Arc<Self> {
+        let key = (config.vault_path().ok(), config.key_file.clone());
+        let mut resolvers = SHARED_RESOLVERS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        resolvers
+            .entry(key)
+            .or_insert_with(|| Arc::new(Self::from_config(config)))
+            .clone()
+    }
+
+    pub fn resolve(&self, value: &str) -> anyhow::Result<String> {
+        if let Some(name) = value.strip_prefix("env:") {
+            return std::env::var(name)
+                .map_err(|_| anyhow::anyhow!("Environment variable {} is not set", name));
+        }
+        if let Some(path) = value.strip_prefix("file:") {
+            let path = expand_home(path);
+            return std::fs::read_to_string(&path)
+                .map(|contents| contents.trim_end_matches(['\r', '\n']).to_string())
+                .map_err(|err| anyhow::anyhow!("Cannot read secret {}: {}", path.display(), err));
+        }
+        if let Some(name) = value.strip_prefix("vault:") {
+            return self
+                .vault()?
+                .entries
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("Secret `{}` is not in the vault", name));
+        }
+        Ok(value.to_string())
+    }
+
+    fn vault(&self) -> anyhow::Result<&SecretVault> {
+        self.vault
+            .get_or_init(|| self.unlock().map_err(|err| err.to_string()))
+            .as_ref()
+            .map_err(|err| anyhow::anyhow!("{}", err))
+    }
+
+    fn unlock(&self) -> anyhow::Result<SecretVault> {
+        let path = self
+            .vault_path
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("No vault path configured"))?;
+        let key = match &self.key {
+            Some(key) => key.clone(),
+            None if std::io::stdin().is_terminal() => VaultKey::prompt()?,
+            None => {
+                return Err(anyhow::anyhow!(
+                    "Vault is locked; set {} or {} or secrets.key_file",
+                    PASSPHRASE_ENV,
+                    KEY_FILE_ENV
+                ))
+            }
+        };
+        SecretVault::open(path, &key)
+    }
+}
+
+pub fn is_reference(value: &str) -> bool {
+    ["env:", "file:", "vault:"]
+        .iter()
+        .any(|prefix| value.starts_with(prefix))
+}
+
+pub fn is_sensitive_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    ["token", "secret", "password", "api_key", "apikey", "dsn"]
+        .iter()
+        .any(|needle| key.contains(needle))
+}
+
+pub fn migrate_plaintext(
+    document: &mut toml_edit::DocumentMut,
+    integrations: &mut [IntegrationConfig],
+    vault: &mut SecretVault,
+) -> Vec<String> {
+    let mut moved = Vec::new();
+    seal_config_table("", document.as_table_mut(), vault, &mut moved);
+    if let Some(profiles) = document
+        .get_mut("profiles")
+        .and_then(toml_edit::Item::as_table_like_mut)
+    {
+        for (name, profile) in profiles.iter_mut() {
+            if let Some(profile) = profile.as_table_like_mut() {
+                let prefix = format!("profiles.{}.", name.get());
+                seal_config_table(&prefix, profile, vault, &mut moved);
+            }
+        }
+    }
+    for integration in integrations.iter_mut() {
+        for (key, value) in integration.details.iter_mut() {
+            if !is_sensitive_key(key) || is_reference(value) {
+                continue;
+            }
+            let name = format!("integrations.{}.{}", integration.name.to_lowercase(), key);
+            let secret = std::mem::replace(value, format!("vault:{}", name));
+            vault.entries.insert(name.clone(), secret);
+            moved.push(name);
+        }
+    }
+    moved
+}
+
+fn seal_config_table(
+    prefix: &str,
+    table: &mut dyn toml_edit::TableLike,
+    vault: &mut SecretVault,
+    moved: &mut Vec<String>,
+) {
+    let mut paths = vec!["api_key".to_string()];
+    for (section, _) in Config::default().provider_configs() {
+        paths.push(format!("{}.api_key", section));
+        paths.push(format!("{}.oauth.client_secret", section));
+    }
+    for path in paths {
+        let Some(value) = value_at(table, &path) else {
+            continue;
+        };
+        let Some(secret) = value.as_str().filter(|secret| !is_reference(secret)) else {
+            continue;
+        };
+        let secret = secret.to_string();
+        let name = format!("{}{}", prefix, path);
+        let decor = value.decor().clone();
+        *value = toml_edit::Value::from(format!("vault:{}", name));
+        *value.decor_mut() = decor;
+        vault.entries.insert(name.clone(), secret);
+        moved.push(name);
+    }
+}
+
+fn value_at<'a>(
+    table: &'a mut dyn toml_edit::TableLike,
+    path: &str,
+) -> Option<&'a mut toml_edit::Value> {
+    match path.split_once('.') {
+        None => table.get_mut(path)?.as_value_mut(),
+        Some((head, rest)) => value_at(table.get_mut(head)?.as_table_like_mut()?, rest),
+    }
+}
+
+pub fn generate_key_file(path: &Path) -> anyhow::Result<()> {
+    if path.exists() {
+        return Err(anyhow::anyhow!("{} already exists", path.display()));
+    }
+    let mut key = [0u8; 32];
+    fill_random(&mut key)?;
+    write_private(path, STANDARD.encode(key).as_bytes())
+}
+
+fn fill_random(buf: &mut [u8]) -> anyhow::Result<()> {
+    getrandom::getrandom(buf).map_err(|err| anyhow::anyhow!("No system randomness: {}", err))
+}
+
+pub(crate) fn write_private(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    use std::io::Write;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(data)?;
+    Ok(())
+}
+
+pub(crate) fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
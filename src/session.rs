@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+
+use crate::provider::{ChatMessage, Provider, ProviderKind, Role};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub title: String,
+    pub provider: ProviderKind,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub messages: Vec<ChatMessage>,
+}
+
+impl Session {
+    pub fn new(provider: ProviderKind) -> Self {
+        let now = now_ts();
+        Self {
+            id: new_session_id(),
+            title: String::new(),
+            provider,
+            created_at: now,
+            updated_at: now,
+            messages: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, message: ChatMessage) {
+        if self.title.is_empty() && message.role == Role::User {
+            self.title = message
+                .content
+                .lines()
+                .next()
+                .unwrap_or("")
+                .chars()
+                .take(60)
+                .collect();
+        }
+        self.messages.push(message);
+        self.updated_at = now_ts();
+    }
+
+    pub fn transcript(&self) -> String {
+        self.messages
+            .iter()
+            .map(|message| format!("{:?}: {}", message.role, message.content))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn send(
+        &mut self,
+        provider: &dyn Provider,
+        input: &str,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<String> {
+        let mut messages = self.messages.clone();
+        messages.push(ChatMessage::user(input));
+        let reply = provider.stream_messages(&messages, on_chunk)?;
+        self.push(ChatMessage::user(input));
+        self.push(ChatMessage::assistant(&reply));
+        self.provider = provider.kind();
+        Ok(reply)
+    }
+}
+
+fn new_session_id() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(nanos.to_string().as_bytes());
+    hasher.update(std::process::id().to_string().as_bytes());
+    hasher.finalize().to_hex()[..12].to_string()
+}
+
+fn now_ts() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
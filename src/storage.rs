@@ -1,38 +1,140 @@
+use serde::{de::DeserializeOwned, Serialize};
 use std::path::{Path, PathBuf};
 
 use crate::{
-    cache::CacheState,
+    agent::AgentOutcome,
+    auth::AuthStore,
+    cache::{CacheState, RepoCacheEntry},
     context::{ContextPayload, Handshake},
     health::AuditReport,
     mcp::default_integrations,
     mcp::IntegrationConfig,
     memory::MemoryVault,
     notifications::Notification,
+    repomap::RepoMap,
+    secrets::write_private,
+    session::Session,
+    sync::SessionSync,
     swarm::SwarmEvent,
+    usage::UsageLedger,
     vector::VectorStoreSnapshot,
     watcher::Incident,
 };
 
-pub fn cache_path() -> anyhow::Result<PathBuf> {
-    let base = dirs::config_dir().ok_or_else(|| anyhow::anyhow!("No config dir"))?;
-    Ok(base.join("nexus").join("cache.json"))
+const STATE_MAGIC: &[u8; 4] = b"NXST";
+// Bincode is not self-describing: bump this whenever a field is added to, removed from or
+// reordered in any struct stored through `save_state` (the e2e layout fingerprint fails
+// until you do).
+pub const STATE_VERSION: u32 = 1;
+
+pub fn encode_state<T: Serialize>(value: &T) -> anyhow::Result<Vec<u8>> {
+    let mut data = STATE_MAGIC.to_vec();
+    data.extend_from_slice(&STATE_VERSION.to_le_bytes());
+    data.extend(bincode::serialize(value)?);
+    Ok(data)
+}
+
+pub fn decode_state<T: DeserializeOwned>(raw: &[u8], path: &Path) -> anyhow::Result<T> {
+    let Some(rest) = raw.strip_prefix(STATE_MAGIC) else {
+        return serde_json::from_slice(raw)
+            .map_err(|err| anyhow::anyhow!("Corrupt state file {}: {}", path.display(), err));
+    };
+    let (version, body) = rest.split_at(rest.len().min(4));
+    let version = u32::from_le_bytes(
+        version
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Truncated state file {}", path.display()))?,
+    );
+    if version != STATE_VERSION {
+        return Err(anyhow::anyhow!(
+            "Unsupported state format version {} in {} (expected {})",
+            version,
+            path.display(),
+            STATE_VERSION
+        ));
+    }
+    bincode::deserialize(body)
+        .map_err(|err| anyhow::anyhow!("Corrupt state file {}: {}", path.display(), err))
 }
 
-pub fn save_cache(cache: &CacheState, path: &Path) -> anyhow::Result<()> {
+fn save_state<T: Serialize>(value: &T, path: &Path) -> anyhow::Result<()> {
+    use std::io::Write;
+
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)?;
     }
-    let data = serde_json::to_string_pretty(cache)?;
-    std::fs::write(path, data)?;
+    let mut staged = path.as_os_str().to_owned();
+    staged.push(".tmp");
+    let staged = PathBuf::from(staged);
+    let mut file = std::fs::File::create(&staged)?;
+    file.write_all(&encode_state(value)?)?;
+    file.sync_all()?;
+    std::fs::rename(&staged, path)?;
     Ok(())
 }
 
-pub fn load_cache(path: &Path) -> anyhow::Result<CacheState> {
-    if !path.exists() {
-        return Ok(CacheState::default());
+fn load_state<T: Serialize + DeserializeOwned + Default>(path: &Path) -> anyhow::Result<T> {
+    if path.exists() {
+        return decode_state(&std::fs::read(path)?, path);
     }
-    let raw = std::fs::read_to_string(path)?;
-    Ok(serde_json::from_str(&raw).unwrap_or_default())
+    let legacy = path.with_extension("json");
+    if legacy == path || !legacy.exists() {
+        return Ok(T::default());
+    }
+    let value: T = serde_json::from_str(&std::fs::read_to_string(&legacy)?).map_err(|err| {
+        anyhow::anyhow!(
+            "Cannot migrate {}: {}; the file was left in place",
+            legacy.display(),
+            err
+        )
+    })?;
+    save_state(&value, path)?;
+    std::fs::remove_file(&legacy)?;
+    Ok(value)
+}
+
+pub fn repos_dir() -> anyhow::Result<PathBuf> {
+    let base = dirs::config_dir().ok_or_else(|| anyhow::anyhow!("No config dir"))?;
+    Ok(base.join("nexus").join("repos"))
+}
+
+pub fn canonical_root(root: &Path) -> PathBuf {
+    std::fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf())
+}
+
+pub fn repo_key(root: &Path) -> String {
+    let root = canonical_root(root);
+    let name: String = root
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "root".to_string())
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' || ch == '.' {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let digest = blake3::hash(root.to_string_lossy().as_bytes()).to_hex();
+    format!("{}-{}", name, &digest[..16])
+}
+
+pub fn repo_dir(dir: &Path, root: &Path) -> PathBuf {
+    dir.join(repo_key(root))
+}
+
+pub fn cache_path(root: &Path) -> anyhow::Result<PathBuf> {
+    Ok(repo_dir(&repos_dir()?, root).join("cache.bin"))
+}
+
+pub fn save_cache(cache: &CacheState, path: &Path) -> anyhow::Result<()> {
+    save_state(cache, path)
+}
+
+pub fn load_cache(path: &Path) -> anyhow::Result<CacheState> {
+    load_state(path)
 }
 
 pub fn memory_path() -> anyhow::Result<PathBuf> {
@@ -40,29 +142,16 @@ pub fn memory_path() -> anyhow::Result<PathBuf> {
     Ok(base.join("nexus").join("memory.json"))
 }
 
-pub fn handshake_path() -> anyhow::Result<PathBuf> {
-    let base = dirs::config_dir().ok_or_else(|| anyhow::anyhow!("No config dir"))?;
-    Ok(base.join("nexus").join("handshake.json"))
+pub fn handshake_path(root: &Path) -> anyhow::Result<PathBuf> {
+    Ok(repo_dir(&repos_dir()?, root).join("handshake.json"))
 }
 
 pub fn load_handshake(path: &Path) -> anyhow::Result<Handshake> {
     if !path.exists() {
-        return Ok(Handshake {
-            root: String::new(),
-            generated_at: 0,
-            file_count: 0,
-            total_bytes: 0,
-            digest: String::new(),
-        });
+        return Ok(Handshake::default());
     }
     let raw = std::fs::read_to_string(path)?;
-    Ok(serde_json::from_str(&raw).unwrap_or(Handshake {
-        root: String::new(),
-        generated_at: 0,
-        file_count: 0,
-        total_bytes: 0,
-        digest: String::new(),
-    }))
+    Ok(serde_json::from_str(&raw).unwrap_or_default())
 }
 
 pub fn save_handshake(handshake: &Handshake, path: &Path) -> anyhow::Result<()> {
@@ -74,42 +163,114 @@ pub fn save_handshake(handshake: &Handshake, path: &Path) -> anyhow::Result<()>
     Ok(())
 }
 
-pub fn context_payload_path() -> anyhow::Result<PathBuf> {
-    let base = dirs::config_dir().ok_or_else(|| anyhow::anyhow!("No config dir"))?;
-    Ok(base.join("nexus").join("context-payload.json"))
+pub fn repo_map_path(root: &Path) -> anyhow::Result<PathBuf> {
+    Ok(repo_dir(&repos_dir()?, root).join("repomap.json"))
 }
 
-pub fn save_context_payload(payload: &ContextPayload, path: &Path) -> anyhow::Result<()> {
+pub fn load_repo_map(path: &Path) -> anyhow::Result<RepoMap> {
+    if !path.exists() {
+        return Ok(RepoMap::default());
+    }
+    let raw = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&raw).unwrap_or_default())
+}
+
+pub fn save_repo_map(map: &RepoMap, path: &Path) -> anyhow::Result<()> {
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)?;
     }
-    let data = serde_json::to_string_pretty(payload)?;
+    let data = serde_json::to_string_pretty(map)?;
     std::fs::write(path, data)?;
     Ok(())
 }
 
-pub fn vector_store_path() -> anyhow::Result<PathBuf> {
-    let base = dirs::config_dir().ok_or_else(|| anyhow::anyhow!("No config dir"))?;
-    Ok(base.join("nexus").join("vector-store.json"))
+pub fn sync_dir(root: &Path) -> anyhow::Result<PathBuf> {
+    Ok(repo_dir(&repos_dir()?, root).join("sessions"))
 }
 
-pub fn load_vector_store(path: &Path) -> anyhow::Result<VectorStoreSnapshot> {
+fn check_name<'a>(kind: &str, name: &'a str) -> anyhow::Result<&'a str> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_');
+    if !valid {
+        return Err(anyhow::anyhow!("Invalid {}: {}", kind, name));
+    }
+    Ok(name)
+}
+
+pub fn sync_state_path(dir: &Path, session: &str) -> anyhow::Result<PathBuf> {
+    Ok(dir.join(format!("{}.json", check_name("session id", session)?)))
+}
+
+pub fn load_sync_state(path: &Path) -> anyhow::Result<Option<SessionSync>> {
     if !path.exists() {
-        return Ok(VectorStoreSnapshot::default());
+        return Ok(None);
     }
     let raw = std::fs::read_to_string(path)?;
-    Ok(serde_json::from_str(&raw).unwrap_or_default())
+    Ok(serde_json::from_str(&raw).ok())
 }
 
-pub fn save_vector_store(snapshot: &VectorStoreSnapshot, path: &Path) -> anyhow::Result<()> {
+pub fn save_sync_state(sync: &SessionSync, path: &Path) -> anyhow::Result<()> {
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)?;
     }
-    let data = serde_json::to_string_pretty(snapshot)?;
+    let data = serde_json::to_string_pretty(sync)?;
     std::fs::write(path, data)?;
     Ok(())
 }
 
+pub fn list_sync_states(dir: &Path) -> anyhow::Result<Vec<SessionSync>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut states = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        if let Some(sync) = load_sync_state(&entry?.path())? {
+            states.push(sync);
+        }
+    }
+    Ok(states)
+}
+
+pub fn blobs_dir(root: &Path) -> anyhow::Result<PathBuf> {
+    Ok(repo_dir(&repos_dir()?, root).join("blobs"))
+}
+
+pub fn context_payload_path(root: &Path) -> anyhow::Result<PathBuf> {
+    Ok(repo_dir(&repos_dir()?, root).join("context-payload.json"))
+}
+
+pub fn load_context_payload(path: &Path) -> anyhow::Result<Option<ContextPayload>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let raw = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&raw).ok())
+}
+
+pub fn save_context_payload(payload: &ContextPayload, path: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let data = serde_json::to_string_pretty(payload)?;
+    std::fs::write(path, data)?;
+    Ok(())
+}
+
+pub fn vector_store_path() -> anyhow::Result<PathBuf> {
+    let base = dirs::config_dir().ok_or_else(|| anyhow::anyhow!("No config dir"))?;
+    Ok(base.join("nexus").join("vector-store.bin"))
+}
+
+pub fn load_vector_store(path: &Path) -> anyhow::Result<VectorStoreSnapshot> {
+    load_state(path)
+}
+
+pub fn save_vector_store(snapshot: &VectorStoreSnapshot, path: &Path) -> anyhow::Result<()> {
+    save_state(snapshot, path)
+}
+
 pub fn load_memory(path: &Path) -> anyhow::Result<MemoryVault> {
     MemoryVault::load(path.to_path_buf())
 }
@@ -172,7 +333,8 @@ pub fn load_kill_switch(path: &Path) -> anyhow::Result<bool> {
         return Ok(false);
     }
     let raw = std::fs::read_to_string(path)?;
-    Ok(serde_json::from_str(&raw).unwrap_or(false))
+    serde_json::from_str(&raw)
+        .map_err(|err| anyhow::anyhow!("Corrupt kill switch {}: {}", path.display(), err))
 }
 
 pub fn save_kill_switch(enabled: bool, path: &Path) -> anyhow::Result<()> {
@@ -233,22 +395,258 @@ pub fn save_notifications(notifications: &[Notification], path: &Path) -> anyhow
 
 pub fn swarm_events_path() -> anyhow::Result<PathBuf> {
     let base = dirs::config_dir().ok_or_else(|| anyhow::anyhow!("No config dir"))?;
-    Ok(base.join("nexus").join("swarm-events.json"))
+    Ok(base.join("nexus").join("swarm-events.bin"))
 }
 
 pub fn load_swarm_events(path: &Path) -> anyhow::Result<Vec<SwarmEvent>> {
+    load_state(path)
+}
+
+pub fn save_swarm_events(events: &[SwarmEvent], path: &Path) -> anyhow::Result<()> {
+    save_state(&events, path)
+}
+
+pub fn list_repo_caches(dir: &Path) -> anyhow::Result<Vec<RepoCacheEntry>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let cache = load_cache(&path.join("cache.bin"))?;
+        let root = if cache.root.as_os_str().is_empty() {
+            recorded_root(&path)?
+        } else {
+            cache.root.clone()
+        };
+        let (disk_bytes, updated_at) = dir_usage(&path)?;
+        entries.push(RepoCacheEntry {
+            key: path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            root,
+            files: cache.files.len(),
+            disk_bytes,
+            updated_at,
+        });
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.updated_at));
+    Ok(entries)
+}
+
+fn recorded_root(dir: &Path) -> anyhow::Result<PathBuf> {
+    let handshake = load_handshake(&dir.join("handshake.json"))?;
+    if !handshake.root.is_empty() {
+        return Ok(PathBuf::from(handshake.root));
+    }
+    let sessions = list_sync_states(&dir.join("sessions"))?;
+    Ok(sessions
+        .into_iter()
+        .map(|sync| PathBuf::from(sync.root))
+        .find(|root| !root.as_os_str().is_empty())
+        .unwrap_or_default())
+}
+
+fn dir_usage(dir: &Path) -> anyhow::Result<(u64, u64)> {
+    let mut bytes = 0;
+    let mut updated_at = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            let (inner_bytes, inner_updated) = dir_usage(&entry.path())?;
+            bytes += inner_bytes;
+            updated_at = updated_at.max(inner_updated);
+            continue;
+        }
+        bytes += meta.len();
+        let modified = meta
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|dur| dur.as_secs())
+            .unwrap_or_default();
+        updated_at = updated_at.max(modified);
+    }
+    Ok((bytes, updated_at))
+}
+
+pub fn prune_repo_caches(
+    dir: &Path,
+    max_age_secs: Option<u64>,
+    now: u64,
+) -> anyhow::Result<Vec<RepoCacheEntry>> {
+    let mut pruned = Vec::new();
+    for entry in list_repo_caches(dir)? {
+        let stale = max_age_secs.is_some_and(|age| now.saturating_sub(entry.updated_at) > age);
+        if entry.root.as_os_str().is_empty() {
+            continue;
+        }
+        if entry.is_orphaned() || stale {
+            std::fs::remove_dir_all(dir.join(&entry.key))?;
+            pruned.push(entry);
+        }
+    }
+    Ok(pruned)
+}
+
+pub fn sessions_dir() -> anyhow::Result<PathBuf> {
+    let base = dirs::config_dir().ok_or_else(|| anyhow::anyhow!("No config dir"))?;
+    Ok(base.join("nexus").join("sessions"))
+}
+
+pub fn session_path(dir: &Path, id: &str) -> anyhow::Result<PathBuf> {
+    Ok(dir.join(format!("{}.json", check_name("session id", id)?)))
+}
+
+pub fn load_session(dir: &Path, id: &str) -> anyhow::Result<Session> {
+    let path = session_path(dir, id)?;
     if !path.exists() {
+        return Err(anyhow::anyhow!("Unknown session: {}", id));
+    }
+    let raw = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&raw)?)
+}
+
+pub fn save_session(session: &Session, dir: &Path) -> anyhow::Result<()> {
+    let path = session_path(dir, &session.id)?;
+    std::fs::create_dir_all(dir)?;
+    let data = serde_json::to_string_pretty(session)?;
+    std::fs::write(path, data)?;
+    Ok(())
+}
+
+pub fn list_sessions(dir: &Path) -> anyhow::Result<Vec<Session>> {
+    if !dir.exists() {
         return Ok(Vec::new());
     }
+    let mut sessions = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+        if let Ok(raw) = std::fs::read_to_string(&path) {
+            if let Ok(session) = serde_json::from_str::<Session>(&raw) {
+                sessions.push(session);
+            }
+        }
+    }
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.updated_at));
+    Ok(sessions)
+}
+
+pub fn delete_session(dir: &Path, id: &str) -> anyhow::Result<bool> {
+    let path = session_path(dir, id)?;
+    if !path.exists() {
+        return Ok(false);
+    }
+    std::fs::remove_file(path)?;
+    Ok(true)
+}
+
+pub fn usage_path() -> anyhow::Result<PathBuf> {
+    let base = dirs::config_dir().ok_or_else(|| anyhow::anyhow!("No config dir"))?;
+    Ok(base.join("nexus").join("usage.json"))
+}
+
+pub fn load_usage(path: &Path) -> anyhow::Result<UsageLedger> {
+    if !path.exists() {
+        return Ok(UsageLedger::default());
+    }
     let raw = std::fs::read_to_string(path)?;
-    Ok(serde_json::from_str(&raw).unwrap_or_default())
+    serde_json::from_str(&raw)
+        .map_err(|err| anyhow::anyhow!("Corrupt usage ledger {}: {}", path.display(), err))
 }
 
-pub fn save_swarm_events(events: &[SwarmEvent], path: &Path) -> anyhow::Result<()> {
+pub fn save_usage(ledger: &UsageLedger, path: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let data = serde_json::to_string_pretty(ledger)?;
+    std::fs::write(path, data)?;
+    Ok(())
+}
+
+pub fn agent_run_path() -> anyhow::Result<PathBuf> {
+    let base = dirs::config_dir().ok_or_else(|| anyhow::anyhow!("No config dir"))?;
+    Ok(base.join("nexus").join("agent_run.json"))
+}
+
+pub fn save_agent_run(outcome: &AgentOutcome, path: &Path) -> anyhow::Result<()> {
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)?;
     }
-    let data = serde_json::to_string_pretty(events)?;
+    let data = serde_json::to_string_pretty(outcome)?;
     std::fs::write(path, data)?;
     Ok(())
 }
+
+pub fn templates_dir() -> anyhow::Result<PathBuf> {
+    let base = dirs::config_dir().ok_or_else(|| anyhow::anyhow!("No config dir"))?;
+    Ok(base.join("nexus").join("templates"))
+}
+
+pub fn template_path(dir: &Path, name: &str) -> anyhow::Result<PathBuf> {
+    Ok(dir.join(format!("{}.txt", check_name("template name", name)?)))
+}
+
+pub fn load_template(dir: &Path, name: &str) -> anyhow::Result<String> {
+    let path = template_path(dir, name)?;
+    if !path.exists() {
+        return Err(anyhow::anyhow!("Template `{}` not found in {}", name, dir.display()));
+    }
+    Ok(std::fs::read_to_string(path)?)
+}
+
+pub fn save_template(dir: &Path, name: &str, body: &str) -> anyhow::Result<()> {
+    let path = template_path(dir, name)?;
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(path, body)?;
+    Ok(())
+}
+
+pub fn list_templates(dir: &Path) -> anyhow::Result<Vec<String>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) == Some("txt") {
+            if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+                names.push(stem.to_string());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+pub fn auth_path() -> anyhow::Result<PathBuf> {
+    let base = dirs::config_dir().ok_or_else(|| anyhow::anyhow!("No config dir"))?;
+    Ok(base.join("nexus").join("auth.json"))
+}
+
+pub fn secrets_path() -> anyhow::Result<PathBuf> {
+    let base = dirs::config_dir().ok_or_else(|| anyhow::anyhow!("No config dir"))?;
+    Ok(base.join("nexus").join("secrets.vault"))
+}
+
+pub fn load_auth(path: &Path) -> anyhow::Result<AuthStore> {
+    if !path.exists() {
+        return Ok(AuthStore::default());
+    }
+    let raw = std::fs::read_to_string(path)?;
+    serde_json::from_str(&raw)
+        .map_err(|err| anyhow::anyhow!("Corrupt auth store {}: {}", path.display(), err))
+}
+
+pub fn save_auth(store: &AuthStore, path: &Path) -> anyhow::Result<()> {
+    let data = serde_json::to_string_pretty(store)?;
+    write_private(path, data.as_bytes())
+}
@@ -0,0 +1,185 @@
+use serde_json::Value;
+
+use crate::provider::{ChatMessage, CompletionRequest, Provider, Role};
+
+pub const MAX_STRUCTURED_ATTEMPTS: usize = 3;
+
+pub fn send_structured<P: Provider + ?Sized>(
+    provider: &P,
+    messages: &[ChatMessage],
+    schema: &Value,
+) -> anyhow::Result<Value> {
+    let mut conversation = vec![ChatMessage::new(
+        Role::System,
+        &format!(
+            "Respond with a single JSON value and nothing else. It must match this JSON schema:\n{}",
+            schema
+        ),
+    )];
+    conversation.extend_from_slice(messages);
+
+    let mut last_error = String::new();
+    for _ in 0..MAX_STRUCTURED_ATTEMPTS {
+        let request = CompletionRequest::new(&conversation).with_schema(schema.clone());
+        let completion = provider.complete_request(&request)?;
+        let checked = extract_json(&completion.text)
+            .and_then(|value| validate(schema, &value).map(|_| value));
+        match checked {
+            Ok(value) => return Ok(value),
+            Err(err) => {
+                conversation.push(ChatMessage::assistant(&completion.text));
+                conversation.push(ChatMessage::user(&format!(
+                    "That response was invalid: {}. Reply again with only JSON matching the schema.",
+                    err
+                )));
+                last_error = err;
+            }
+        }
+    }
+    Err(anyhow::anyhow!(
+        "{} returned invalid JSON after {} attempt(s): {}",
+        provider.display_name(),
+        MAX_STRUCTURED_ATTEMPTS,
+        last_error
+    ))
+}
+
+pub fn extract_json(text: &str) -> Result<Value, String> {
+    let trimmed = text.trim();
+    let unfenced = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.trim_end().strip_suffix("```"))
+        .unwrap_or(trimmed)
+        .trim();
+    if let Ok(value) = serde_json::from_str(unfenced) {
+        return Ok(value);
+    }
+    let start = unfenced.find(['{', '[']);
+    let end = unfenced.rfind(['}', ']']);
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => serde_json::from_str(&unfenced[start..=end])
+            .map_err(|err| format!("response is not valid JSON ({})", err)),
+        _ => Err("response contains no JSON".to_string()),
+    }
+}
+
+pub fn validate(schema: &Value, value: &Value) -> Result<(), String> {
+    validate_at(schema, value, "$")
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|name| has_type(value, name)) {
+            return Err(format!(
+                "{} should be {} but is {}",
+                path,
+                types.join(" or "),
+                type_name(value)
+            ));
+        }
+    }
+    if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+        if !options.contains(value) {
+            return Err(format!(
+                "{} must be one of {}",
+                path,
+                Value::Array(options.clone())
+            ));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            return Err(format!("{} must equal {}", path, expected));
+        }
+    }
+
+    match value {
+        Value::Object(map) => {
+            if let Some(required) = schema.get("required").and_then(Value::as_array) {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !map.contains_key(key) {
+                        return Err(format!("{} is missing required field `{}`", path, key));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (key, item) in map {
+                let child = format!("{}.{}", path, key);
+                match properties.and_then(|properties| properties.get(key)) {
+                    Some(property) => validate_at(property, item, &child)?,
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => {
+                            return Err(format!("{} is not an allowed field", child))
+                        }
+                        Some(extra @ Value::Object(_)) => validate_at(extra, item, &child)?,
+                        _ => {}
+                    },
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                if (items.len() as u64) < min {
+                    return Err(format!("{} needs at least {} item(s)", path, min));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+                if items.len() as u64 > max {
+                    return Err(format!("{} allows at most {} item(s)", path, max));
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    validate_at(item_schema, item, &format!("{}[{}]", path, index))?;
+                }
+            }
+        }
+        Value::Number(number) => {
+            let number = number.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+                if number < min {
+                    return Err(format!("{} must be >= {}", path, min));
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+                if number > max {
+                    return Err(format!("{} must be <= {}", path, max));
+                }
+            }
+        }
+        Value::String(text) => {
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if (text.chars().count() as u64) < min {
+                    return Err(format!("{} must be at least {} character(s)", path, min));
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "integer" => value.as_i64().is_some() || value.as_u64().is_some(),
+        "number" => value.is_number(),
+        other => type_name(value) == other,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
@@ -3,6 +3,8 @@ use std::collections::{BTreeMap, BTreeSet};
 use std::thread;
 use std::time::Duration;
 
+use crate::provider::{ChatMessage, Provider};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SwarmEvent {
     pub timestamp: u64,
@@ -43,6 +45,44 @@ pub fn architect_plan(input: &str) -> Vec<Task> {
         .collect()
 }
 
+pub fn architect_plan_with_provider(
+    provider: &dyn Provider,
+    input: &str,
+) -> anyhow::Result<Vec<Task>> {
+    let schema = serde_json::json!({
+        "type": "object",
+        "required": ["tasks"],
+        "properties": {
+            "tasks": {
+                "type": "array",
+                "minItems": 1,
+                "items": {
+                    "type": "object",
+                    "required": ["id", "description", "dependencies"],
+                    "properties": {
+                        "id": {"type": "integer", "minimum": 1},
+                        "description": {"type": "string", "minLength": 1},
+                        "dependencies": {"type": "array", "items": {"type": "integer"}}
+                    }
+                }
+            }
+        }
+    });
+    let prompt = format!(
+        "Break this work into small tasks for a swarm of workers. Number tasks from 1 and \
+         list the ids each task depends on.\n\n{}",
+        input
+    );
+    let value = provider.send_structured(&[ChatMessage::user(&prompt)], &schema)?;
+    let mut tasks: Vec<Task> = serde_json::from_value(value["tasks"].clone())?;
+    let ids: BTreeSet<usize> = tasks.iter().map(|task| task.id).collect();
+    for task in &mut tasks {
+        let id = task.id;
+        task.dependencies.retain(|dep| *dep != id && ids.contains(dep));
+    }
+    Ok(tasks)
+}
+
 pub fn run_workers(tasks: &[Task]) -> Vec<TaskResult> {
     let mut results = Vec::new();
     for task in tasks {
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use crate::{
+    cache::{CacheState, FileMeta},
+    context::{build_handshake, build_payload_with, store_blobs, Baseline, ContextPayload},
+    packer::PackOptions,
+};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncPoint {
+    pub digest: String,
+    pub sequence: u64,
+    pub created_at: u64,
+    pub files: BTreeMap<String, FileMeta>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionSync {
+    pub session: String,
+    pub root: String,
+    pub sequence: u64,
+    pub acked: Option<SyncPoint>,
+    pub pending: Option<SyncPoint>,
+    #[serde(default)]
+    pub diverged: Option<String>,
+}
+
+impl SessionSync {
+    pub fn new(session: &str, root: &Path) -> Self {
+        Self {
+            session: session.to_string(),
+            root: root.display().to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn acked_digest(&self) -> Option<&str> {
+        self.acked.as_ref().map(|point| point.digest.as_str())
+    }
+
+    pub fn ack(&mut self, digest: &str) -> anyhow::Result<()> {
+        if self.acked_digest() == Some(digest) {
+            return Ok(());
+        }
+        match self.pending.take() {
+            Some(pending) if pending.digest == digest => {
+                self.acked = Some(pending);
+                self.diverged = None;
+                Ok(())
+            }
+            pending => {
+                let expected = pending
+                    .as_ref()
+                    .map(|point| point.digest.clone())
+                    .unwrap_or_else(|| "nothing pending".to_string());
+                let reason = format!(
+                    "acknowledged digest {} does not match pending {}",
+                    digest, expected
+                );
+                self.acked = None;
+                self.diverged = Some(reason.clone());
+                Err(anyhow::anyhow!(
+                    "Handshake diverged for session {}: {}; the next payload is a full resend",
+                    self.session,
+                    reason
+                ))
+            }
+        }
+    }
+
+    pub fn blob_hashes(&self) -> BTreeSet<String> {
+        self.acked
+            .iter()
+            .chain(self.pending.iter())
+            .flat_map(|point| point.files.values().map(|meta| meta.hash.clone()))
+            .collect()
+    }
+
+    fn base(&mut self, current: &CacheState, seen: Option<&str>) -> Result<SyncPoint, String> {
+        if let Some(seen) = seen {
+            let pending = self.pending.as_ref().map(|point| point.digest.as_str());
+            if pending == Some(seen) {
+                self.acked = self.pending.take();
+            } else if self.acked_digest() != Some(seen) {
+                return Err(format!(
+                    "client reports digest {} but the last acknowledged one is {}",
+                    seen,
+                    self.acked_digest().unwrap_or("none")
+                ));
+            }
+        }
+        if let Some(reason) = self.diverged.take() {
+            return Err(reason);
+        }
+        if self.root != current.root.display().to_string() {
+            return Err(format!("session belongs to {}", self.root));
+        }
+        self.acked
+            .clone()
+            .ok_or_else(|| "no acknowledged handshake for this session".to_string())
+    }
+}
+
+pub fn build_session_payload(
+    sync: &mut SessionSync,
+    current: &CacheState,
+    options: &PackOptions,
+    blobs: &Path,
+    seen: Option<&str>,
+) -> anyhow::Result<ContextPayload> {
+    let mut delivered = current.files.clone();
+    let mut base_files = BTreeMap::new();
+    let mut payload = match sync.base(current, seen) {
+        Ok(base) => {
+            let previous = CacheState {
+                root: current.root.clone(),
+                files: base.files.clone(),
+            };
+            let baseline = Baseline::from_files(
+                base.files
+                    .iter()
+                    .map(|(path, meta)| (path.clone(), meta.hash.clone()))
+                    .collect(),
+                blobs.to_path_buf(),
+            );
+            let mut payload =
+                build_payload_with(previous.diff(current), current, options, Some(&baseline))?;
+            payload.base_digest = Some(base.digest);
+            base_files = base.files;
+            payload
+        }
+        Err(reason) => {
+            let empty = CacheState::new(current.root.clone());
+            let mut payload = build_payload_with(empty.diff(current), current, options, None)?;
+            payload.resync = Some(reason);
+            payload
+        }
+    };
+    let undelivered = payload
+        .dropped
+        .iter()
+        .map(|file| &file.path)
+        .chain(payload.placeholders.iter().map(|placeholder| &placeholder.path))
+        .chain(payload.files.iter().filter(|file| file.truncated).map(|file| &file.path));
+    for path in undelivered {
+        match base_files.get(path) {
+            Some(meta) => delivered.insert(path.clone(), meta.clone()),
+            None => delivered.remove(path),
+        };
+    }
+    store_blobs(current, blobs)?;
+    let delivered = CacheState {
+        root: current.root.clone(),
+        files: delivered,
+    };
+    let handshake = build_handshake(&delivered);
+    sync.sequence += 1;
+    sync.pending = Some(SyncPoint {
+        digest: handshake.digest.clone(),
+        sequence: sync.sequence,
+        created_at: handshake.generated_at,
+        files: delivered.files,
+    });
+    payload.session = Some(sync.session.clone());
+    payload.sequence = sync.sequence;
+    payload.digest = handshake.digest;
+    Ok(payload)
+}
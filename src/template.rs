@@ -0,0 +1,200 @@
+use regex::Regex;
+use std::path::Path;
+
+use crate::{
+    context::{ContentFormat, ContextPayload, Handshake},
+    memory::MemoryVault,
+    storage::{
+        context_payload_path, handshake_path, load_context_payload, load_handshake,
+        load_vector_store, memory_path, vector_store_path,
+    },
+    usage::estimate_tokens,
+    vector::{LocalVectorStore, VectorStore},
+};
+
+pub const DEFAULT_VECTOR_HITS: usize = 3;
+
+pub struct TemplateContext {
+    pub input: String,
+    pub memory: MemoryVault,
+    pub handshake: Option<Handshake>,
+    pub payload: Option<ContextPayload>,
+    pub vectors: LocalVectorStore,
+}
+
+impl TemplateContext {
+    pub fn load(input: &str, root: &Path) -> anyhow::Result<Self> {
+        let handshake = load_handshake(&handshake_path(root)?)?;
+        Ok(Self {
+            input: input.to_string(),
+            memory: MemoryVault::load(memory_path()?)?,
+            handshake: Some(handshake).filter(|handshake| !handshake.digest.is_empty()),
+            payload: load_context_payload(&context_payload_path(root)?)?,
+            vectors: LocalVectorStore::from_snapshot(load_vector_store(&vector_store_path()?)?),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderedPrompt {
+    pub text: String,
+    pub placeholders: Vec<String>,
+}
+
+impl RenderedPrompt {
+    pub fn summary(&self) -> String {
+        format!(
+            "{} bytes, ~{} tokens, {} placeholder(s)",
+            self.text.len(),
+            estimate_tokens(&self.text),
+            self.placeholders.len()
+        )
+    }
+}
+
+pub fn render(template: &str, context: &TemplateContext) -> anyhow::Result<RenderedPrompt> {
+    let placeholder = Regex::new(r"\{\{\s*([^{}]+?)\s*\}\}")?;
+    let mut text = String::new();
+    let mut placeholders = Vec::new();
+    let mut last = 0;
+    for captures in placeholder.captures_iter(template) {
+        let whole = captures.get(0).expect("capture 0 always matches");
+        let name = &captures[1];
+        text.push_str(&template[last..whole.start()]);
+        text.push_str(&resolve(name, context)?);
+        placeholders.push(name.to_string());
+        last = whole.end();
+    }
+    text.push_str(&template[last..]);
+    if !placeholders.iter().any(|name| name == "input") && !context.input.is_empty() {
+        text.push_str("\n\n");
+        text.push_str(&context.input);
+    }
+    Ok(RenderedPrompt { text, placeholders })
+}
+
+fn resolve(name: &str, context: &TemplateContext) -> anyhow::Result<String> {
+    if name == "input" {
+        return Ok(context.input.clone());
+    }
+    if name == "memory" {
+        return Ok(memory_lines(context, |_| true));
+    }
+    if let Some(tag) = name.strip_prefix("memory.tag:") {
+        return Ok(memory_lines(context, |tags| {
+            tags.iter().any(|candidate| candidate == tag)
+        }));
+    }
+    if let Some(key) = name.strip_prefix("memory.") {
+        return context
+            .memory
+            .get(key)
+            .map(|entry| entry.value.clone())
+            .ok_or_else(|| anyhow::anyhow!("Memory entry `{}` not found", key));
+    }
+    if name == "handshake" {
+        return Ok(match &context.handshake {
+            Some(handshake) if !handshake.digest.is_empty() => {
+                let mut summary = format!(
+                    "Repository {}: {} files, {} bytes, digest {}",
+                    handshake.root, handshake.file_count, handshake.total_bytes, handshake.digest
+                );
+                if let Some(map) = &handshake.map {
+                    summary.push('\n');
+                    summary.push_str(map);
+                }
+                summary
+            }
+            _ => "No handshake recorded.".to_string(),
+        });
+    }
+    if name == "context" {
+        return Ok(match &context.payload {
+            Some(payload) => render_payload(payload),
+            None => "No context payload recorded.".to_string(),
+        });
+    }
+    if name == "vector" || name.starts_with("vector:") {
+        let top_k = match name.strip_prefix("vector:") {
+            Some(count) => count
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid vector hit count in `{{{{{}}}}}`", name))?,
+            None => DEFAULT_VECTOR_HITS,
+        };
+        return render_vector_hits(context, top_k);
+    }
+    Err(anyhow::anyhow!("Unknown placeholder `{{{{{}}}}}`", name))
+}
+
+fn memory_lines(context: &TemplateContext, keep: impl Fn(&[String]) -> bool) -> String {
+    context
+        .memory
+        .entries
+        .iter()
+        .filter(|(_, entry)| keep(&entry.tags))
+        .map(|(key, entry)| format!("{}: {}", key, entry.value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn render_payload(payload: &ContextPayload) -> String {
+    let mut out = format!(
+        "Changed: {}\nRemoved: {}",
+        payload.changed.join(", "),
+        payload.removed.join(", ")
+    );
+    if !payload.renamed.is_empty() {
+        let renamed: Vec<String> = payload
+            .renamed
+            .iter()
+            .map(|(from, to)| format!("{} -> {}", from, to))
+            .collect();
+        out.push_str(&format!("\nRenamed: {}", renamed.join(", ")));
+    }
+    match (&payload.resync, &payload.base_digest) {
+        (Some(reason), _) => out.push_str(&format!("\nFull resend ({})", reason)),
+        (None, Some(base)) => out.push_str(&format!("\nChanges since digest {}", base)),
+        (None, None) => {}
+    }
+    if !payload.dropped.is_empty() {
+        let dropped: Vec<&str> = payload.dropped.iter().map(|file| file.path.as_str()).collect();
+        out.push_str(&format!("\nOmitted: {}", dropped.join(", ")));
+    }
+    for placeholder in &payload.placeholders {
+        out.push_str(&format!("\n\n--- {} [content not sent]", placeholder.describe()));
+    }
+    for file in &payload.files {
+        match file.format {
+            ContentFormat::Full => {
+                out.push_str(&format!("\n\n--- {}\n{}", file.path, file.content))
+            }
+            ContentFormat::Diff => out.push_str(&format!(
+                "\n\n--- {} (diff since handshake)\n{}",
+                file.path, file.content
+            )),
+        }
+        if file.truncated {
+            out.push_str("\n[truncated]");
+        }
+    }
+    out
+}
+
+fn render_vector_hits(context: &TemplateContext, top_k: usize) -> anyhow::Result<String> {
+    if context.input.trim().is_empty() {
+        return Ok(String::new());
+    }
+    let hits = context.vectors.query(&context.input, top_k)?;
+    Ok(hits
+        .iter()
+        .filter_map(|hit| {
+            context
+                .vectors
+                .documents
+                .iter()
+                .find(|doc| doc.id == hit.id)
+                .map(|doc| format!("[{} {:.3}] {}", doc.id, hit.score, doc.content))
+        })
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
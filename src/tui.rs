@@ -1,4 +1,8 @@
 use std::io::{self, Stdout};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 use crossterm::{
     event::{self, Event, KeyCode},
@@ -10,13 +14,14 @@ use ratatui::{
     layout::{Constraint, Direction, Layout},
     style::{Color, Style},
     text::Line,
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
     Terminal,
 };
 
 use crate::{
     cache::CacheState,
-    storage::{cache_path, load_cache},
+    filter::IgnoreConfig,
+    storage::{cache_path, canonical_root, load_cache},
     Config,
 };
 
@@ -36,14 +41,14 @@ pub fn run(config: &Config) -> io::Result<()> {
     result
 }
 
-pub fn run_diff(root: &str) -> io::Result<()> {
+pub fn run_diff(root: &str, rules: &IgnoreConfig) -> io::Result<()> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let result = diff_loop(&mut terminal, root);
+    let result = diff_loop(&mut terminal, root, rules);
 
     disable_raw_mode()?;
     execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
@@ -53,13 +58,23 @@ pub fn run_diff(root: &str) -> io::Result<()> {
 }
 
 fn ui_loop(terminal: &mut Terminal<CrosstermBackend<Stdout>>, config: &Config) -> io::Result<()> {
+    let mut input = String::new();
+    let response = Arc::new(Mutex::new(String::new()));
+    let streaming = Arc::new(AtomicBool::new(false));
+
     loop {
+        let response_text = response.lock().map(|text| text.clone()).unwrap_or_default();
         terminal.draw(|frame| {
             let size = frame.size();
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
                 .margin(2)
-                .constraints([Constraint::Length(3), Constraint::Min(0)])
+                .constraints([
+                    Constraint::Length(3),
+                    Constraint::Length(6),
+                    Constraint::Length(3),
+                    Constraint::Min(0),
+                ])
                 .split(size);
 
             let header = Paragraph::new(Line::from("Nexus CLI - Phase 1"))
@@ -68,6 +83,10 @@ fn ui_loop(terminal: &mut Terminal<CrosstermBackend<Stdout>>, config: &Config) -
             frame.render_widget(header, chunks[0]);
 
             let body = Paragraph::new(vec![
+                Line::from(format!(
+                    "Profile: {}",
+                    config.profile.as_deref().unwrap_or("default")
+                )),
                 Line::from(format!("Provider: {:?}", config.provider)),
                 Line::from(format!("Dry run: {}", config.dry_run)),
                 Line::from(format!(
@@ -76,16 +95,40 @@ fn ui_loop(terminal: &mut Terminal<CrosstermBackend<Stdout>>, config: &Config) -
                         .map(|path| path.display().to_string())
                         .unwrap_or_else(|| "n/a".to_string())
                 )),
-                Line::from("Press q to exit."),
+                Line::from("Type a prompt and press Enter. Esc (or q on empty input) exits."),
             ])
             .block(Block::default().borders(Borders::ALL).title("Config"));
             frame.render_widget(body, chunks[1]);
+
+            let prompt = Paragraph::new(Line::from(format!("> {}", input)))
+                .block(Block::default().borders(Borders::ALL).title("Prompt"));
+            frame.render_widget(prompt, chunks[2]);
+
+            let title = if streaming.load(Ordering::SeqCst) {
+                "Response (streaming)"
+            } else {
+                "Response"
+            };
+            let output = Paragraph::new(response_text.clone())
+                .wrap(Wrap { trim: false })
+                .block(Block::default().borders(Borders::ALL).title(title));
+            frame.render_widget(output, chunks[3]);
         })?;
 
-        if event::poll(std::time::Duration::from_millis(200))? {
+        if event::poll(std::time::Duration::from_millis(100))? {
             if let Event::Key(key) = event::read()? {
-                if key.code == KeyCode::Char('q') {
-                    break;
+                match key.code {
+                    KeyCode::Esc => break,
+                    KeyCode::Char('q') if input.is_empty() => break,
+                    KeyCode::Char(ch) => input.push(ch),
+                    KeyCode::Backspace => {
+                        input.pop();
+                    }
+                    KeyCode::Enter if !input.is_empty() && !streaming.load(Ordering::SeqCst) => {
+                        let prompt = std::mem::take(&mut input);
+                        spawn_prompt(config.clone(), prompt, response.clone(), streaming.clone());
+                    }
+                    _ => {}
                 }
             }
         }
@@ -94,15 +137,60 @@ fn ui_loop(terminal: &mut Terminal<CrosstermBackend<Stdout>>, config: &Config) -
     Ok(())
 }
 
-fn diff_loop(terminal: &mut Terminal<CrosstermBackend<Stdout>>, root: &str) -> io::Result<()> {
+fn spawn_prompt(
+    config: Config,
+    prompt: String,
+    response: Arc<Mutex<String>>,
+    streaming: Arc<AtomicBool>,
+) {
+    if let Ok(mut text) = response.lock() {
+        text.clear();
+    }
+    streaming.store(true, Ordering::SeqCst);
+    thread::spawn(move || {
+        let provider = match config.build_provider("tui") {
+            Ok(provider) => provider,
+            Err(err) => {
+                if let Ok(mut text) = response.lock() {
+                    text.push_str(&format!("[error] {}", err));
+                }
+                streaming.store(false, Ordering::SeqCst);
+                return;
+            }
+        };
+        if config.dry_run {
+            if let Ok(mut text) = response.lock() {
+                text.push_str(&provider.dry_run_prompt(&prompt));
+            }
+        } else {
+            let result = provider.stream_prompt(&prompt, &mut |chunk| {
+                if let Ok(mut text) = response.lock() {
+                    text.push_str(chunk);
+                }
+            });
+            if let Err(err) = result {
+                if let Ok(mut text) = response.lock() {
+                    text.push_str(&format!("\n[error] {}", err));
+                }
+            }
+        }
+        streaming.store(false, Ordering::SeqCst);
+    });
+}
+
+fn diff_loop(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    root: &str,
+    rules: &IgnoreConfig,
+) -> io::Result<()> {
     let cached = load_cache(
-        cache_path()
-            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?
+        cache_path(Path::new(root))
+            .map_err(|err| io::Error::other(err.to_string()))?
             .as_path(),
     )
     .unwrap_or_default();
-    let mut current = CacheState::new(root.into());
-    let _ = current.warm();
+    let mut current = CacheState::new(canonical_root(Path::new(root)));
+    let _ = current.warm_from(&cached, rules);
     let diff = cached.diff(&current);
 
     let changed_items: Vec<ListItem> = diff
@@ -115,6 +203,11 @@ fn diff_loop(terminal: &mut Terminal<CrosstermBackend<Stdout>>, root: &str) -> i
         .iter()
         .map(|item| ListItem::new(item.clone()))
         .collect();
+    let renamed_items: Vec<ListItem> = diff
+        .renamed
+        .iter()
+        .map(|(from, to)| ListItem::new(format!("{} -> {}", from, to)))
+        .collect();
 
     loop {
         terminal.draw(|frame| {
@@ -135,7 +228,11 @@ fn diff_loop(terminal: &mut Terminal<CrosstermBackend<Stdout>>, root: &str) -> i
 
             let columns = Layout::default()
                 .direction(Direction::Horizontal)
-                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .constraints([
+                    Constraint::Percentage(34),
+                    Constraint::Percentage(33),
+                    Constraint::Percentage(33),
+                ])
                 .split(chunks[1]);
 
             let changed = List::new(changed_items.clone())
@@ -145,6 +242,10 @@ fn diff_loop(terminal: &mut Terminal<CrosstermBackend<Stdout>>, root: &str) -> i
             let removed = List::new(removed_items.clone())
                 .block(Block::default().borders(Borders::ALL).title("Removed"));
             frame.render_widget(removed, columns[1]);
+
+            let renamed = List::new(renamed_items.clone())
+                .block(Block::default().borders(Borders::ALL).title("Renamed"));
+            frame.render_widget(renamed, columns[2]);
         })?;
 
         if event::poll(std::time::Duration::from_millis(200))? {
@@ -0,0 +1,229 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::{
+    provider::{ChatMessage, Completion, CompletionRequest, Provider, ProviderKind, Usage},
+    storage::{load_usage, save_usage},
+};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelPrice {
+    pub prompt_per_mtok: f64,
+    pub completion_per_mtok: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BudgetConfig {
+    pub daily_usd: Option<f64>,
+    pub monthly_usd: Option<f64>,
+    pub daily_tokens: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub timestamp: u64,
+    pub provider: ProviderKind,
+    pub model: String,
+    pub command: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost_usd: f64,
+    pub estimated: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageTotals {
+    pub calls: usize,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost_usd: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageLedger {
+    pub records: Vec<UsageRecord>,
+}
+
+impl UsageLedger {
+    pub fn breakdown(&self, key: impl Fn(&UsageRecord) -> String) -> BTreeMap<String, UsageTotals> {
+        let mut totals: BTreeMap<String, UsageTotals> = BTreeMap::new();
+        for record in &self.records {
+            let entry = totals.entry(key(record)).or_default();
+            entry.calls += 1;
+            entry.prompt_tokens += record.prompt_tokens;
+            entry.completion_tokens += record.completion_tokens;
+            entry.cost_usd += record.cost_usd;
+        }
+        totals
+    }
+
+    pub fn since(&self, timestamp: u64) -> UsageLedger {
+        UsageLedger {
+            records: self
+                .records
+                .iter()
+                .filter(|record| record.timestamp >= timestamp)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn check_budget(&self, budget: &BudgetConfig, now: u64) -> anyhow::Result<()> {
+        let today = day_of(now);
+        let month = &today[..7];
+        let daily = self.breakdown(|record| day_of(record.timestamp));
+        let monthly = self.breakdown(|record| day_of(record.timestamp)[..7].to_string());
+        let day_totals = daily.get(&today).cloned().unwrap_or_default();
+        let month_totals = monthly.get(month).cloned().unwrap_or_default();
+
+        if let Some(cap) = budget.daily_usd {
+            if day_totals.cost_usd >= cap {
+                return Err(anyhow::anyhow!(
+                    "Budget exceeded: ${:.4} spent today (cap ${:.2})",
+                    day_totals.cost_usd,
+                    cap
+                ));
+            }
+        }
+        if let Some(cap) = budget.monthly_usd {
+            if month_totals.cost_usd >= cap {
+                return Err(anyhow::anyhow!(
+                    "Budget exceeded: ${:.4} spent in {} (cap ${:.2})",
+                    month_totals.cost_usd,
+                    month,
+                    cap
+                ));
+            }
+        }
+        if let Some(cap) = budget.daily_tokens {
+            let tokens = day_totals.prompt_tokens + day_totals.completion_tokens;
+            if tokens >= cap {
+                return Err(anyhow::anyhow!(
+                    "Budget exceeded: {} tokens used today (cap {})",
+                    tokens,
+                    cap
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct MeteredProvider {
+    pub inner: Box<dyn Provider>,
+    pub command: String,
+    pub ledger_path: PathBuf,
+    pub pricing: BTreeMap<String, ModelPrice>,
+    pub budget: BudgetConfig,
+}
+
+impl MeteredProvider {
+    fn metered(
+        &self,
+        messages: &[ChatMessage],
+        call: impl FnOnce() -> anyhow::Result<Completion>,
+    ) -> anyhow::Result<Completion> {
+        let mut ledger = load_usage(&self.ledger_path)?;
+        let now = now_ts();
+        ledger.check_budget(&self.budget, now)?;
+
+        let completion = call()?;
+        let (usage, estimated) = match completion.usage {
+            Some(usage) => (usage, false),
+            None => (
+                Usage {
+                    prompt_tokens: messages
+                        .iter()
+                        .map(|message| estimate_tokens(&message.content) as u64)
+                        .sum(),
+                    completion_tokens: estimate_tokens(&completion.text) as u64,
+                },
+                true,
+            ),
+        };
+        let cost_usd = self
+            .pricing
+            .get(&completion.model)
+            .map(|price| price_of(price, usage))
+            .unwrap_or(0.0);
+        ledger.records.push(UsageRecord {
+            timestamp: now,
+            provider: completion.provider.clone(),
+            model: completion.model.clone(),
+            command: self.command.clone(),
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            cost_usd,
+            estimated,
+        });
+        save_usage(&ledger, &self.ledger_path)?;
+        Ok(completion)
+    }
+}
+
+impl Provider for MeteredProvider {
+    fn kind(&self) -> ProviderKind {
+        self.inner.kind()
+    }
+
+    fn display_name(&self) -> &'static str {
+        self.inner.display_name()
+    }
+
+    fn dry_run_prompt(&self, input: &str) -> String {
+        format!(
+            "{} (~{} tokens)",
+            self.inner.dry_run_prompt(input),
+            estimate_tokens(input)
+        )
+    }
+
+    fn complete_request(&self, request: &CompletionRequest) -> anyhow::Result<Completion> {
+        self.metered(&request.messages, || self.inner.complete_request(request))
+    }
+
+    fn complete_stream(
+        &self,
+        messages: &[ChatMessage],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<Completion> {
+        self.metered(messages, || self.inner.complete_stream(messages, on_chunk))
+    }
+
+    fn list_models(&self) -> anyhow::Result<Vec<String>> {
+        self.inner.list_models()
+    }
+}
+
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+pub fn price_of(price: &ModelPrice, usage: Usage) -> f64 {
+    (usage.prompt_tokens as f64 * price.prompt_per_mtok
+        + usage.completion_tokens as f64 * price.completion_per_mtok)
+        / 1_000_000.0
+}
+
+pub fn day_of(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn now_ts() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
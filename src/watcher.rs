@@ -4,6 +4,9 @@ use serde::{Deserialize, Serialize};
 use std::path::Path;
 use std::sync::mpsc::Sender;
 
+use crate::filter::{IgnoreConfig, RepoFilter};
+use crate::provider::{ChatMessage, Provider};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Incident {
@@ -26,6 +29,39 @@ impl Default for Incident {
     }
 }
 
+pub fn suggest_fix(provider: &dyn Provider, incident: &Incident) -> anyhow::Result<String> {
+    let schema = serde_json::json!({
+        "type": "object",
+        "required": ["cause", "fix"],
+        "properties": {
+            "cause": {"type": "string", "minLength": 1},
+            "fix": {"type": "string", "minLength": 1},
+            "commands": {"type": "array", "items": {"type": "string"}}
+        }
+    });
+    let prompt = format!(
+        "Diagnose this {} from {} and suggest a fix.\n\n{}\n{}",
+        incident.kind,
+        incident.source,
+        incident.summary,
+        incident.detail.as_deref().unwrap_or("")
+    );
+    let value = provider.send_structured(&[ChatMessage::user(&prompt)], &schema)?;
+    let mut suggestion = format!(
+        "{} (cause: {})",
+        value["fix"].as_str().unwrap_or(""),
+        value["cause"].as_str().unwrap_or("")
+    );
+    let commands: Vec<&str> = value["commands"]
+        .as_array()
+        .map(|commands| commands.iter().filter_map(|command| command.as_str()).collect())
+        .unwrap_or_default();
+    if !commands.is_empty() {
+        suggestion.push_str(&format!(" Try: {}", commands.join("; ")));
+    }
+    Ok(suggestion)
+}
+
 pub fn analyze_log(contents: &str, source: &str) -> Vec<Incident> {
     let mut incidents = Vec::new();
     let error_re = Regex::new(r"(?i)(panic|exception|error|traceback|fatal)").unwrap();
@@ -131,11 +167,20 @@ pub fn monitor_log(
     Ok(Some(analyze_log(&contents, &path.display().to_string())))
 }
 
-pub fn watch_filesystem(root: &Path, tx: Sender<Incident>) -> notify::Result<RecommendedWatcher> {
+pub fn watch_filesystem(
+    root: &Path,
+    rules: &IgnoreConfig,
+    tx: Sender<Incident>,
+) -> anyhow::Result<RecommendedWatcher> {
+    let root = root.canonicalize()?;
+    let filter = RepoFilter::new(&root, rules)?;
     let root_display = root.display().to_string();
     let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
         if let Ok(event) = res {
             for path in event.paths {
+                if filter.is_ignored(&path, path.is_dir()) {
+                    continue;
+                }
                 let summary = format!("Filesystem change: {}", path.display());
                 let incident = Incident {
                     source: root_display.clone(),
@@ -148,7 +193,7 @@ pub fn watch_filesystem(root: &Path, tx: Sender<Incident>) -> notify::Result<Rec
             }
         }
     })?;
-    watcher.watch(root, RecursiveMode::Recursive)?;
+    watcher.watch(&root, RecursiveMode::Recursive)?;
     Ok(watcher)
 }
 
@@ -1,9 +1,45 @@
 use std::fs;
 use std::path::PathBuf;
+use std::sync::mpsc;
 
-use nexus::cache::CacheState;
-use nexus::context::build_handshake;
-use nexus::vector::{embed, LocalVectorStore, VectorDocument, VectorStore};
+use nexus::agent::{Agent, AgentConfig, AgentStop};
+use nexus::auth::{device_login, OAuthConfig, OAuthSession};
+use nexus::cache::{CacheState, FileMeta};
+use nexus::cassette::{Cassette, CassetteMode, CassetteProvider};
+use nexus::classify::{ClassPolicies, ClassPolicy, FileClass};
+use nexus::context::{
+    build_handshake, build_payload_with, store_blobs, Baseline, ContentFormat, PayloadMode,
+};
+use nexus::fallback::FallbackEntry;
+use nexus::filter::{IgnoreConfig, RepoFilter};
+use nexus::packer::{truncate_to_budget, PackOptions};
+use nexus::repomap::RepoMap;
+use nexus::sync::{build_session_payload, SessionSync};
+use nexus::template::{render as render_template, TemplateContext};
+use nexus::config::{
+    config_schema, get_value as get_config_value, read_document as read_config_document,
+    set_file_value as set_config_value, validate_table as validate_config_table, ConfigLayer,
+    LayeredConfig, Severity,
+};
+use nexus::Config;
+use nexus::provider::{
+    build_provider, ChatMessage, CompletionRequest, Provider, ProviderKind, ProviderSettings,
+    ToolCall, ToolDefinition,
+};
+use nexus::mcp::{default_integrations, set_detail};
+use nexus::secrets::{
+    generate_key_file, migrate_plaintext, SecretResolver, SecretVault, SecretsConfig, VaultKey,
+};
+use nexus::session::Session;
+use nexus::storage::{
+    canonical_root, delete_session, list_repo_caches, list_sessions, load_auth, load_cache,
+    load_session, load_swarm_events, load_template, load_usage, load_vector_store,
+    prune_repo_caches, repo_dir, repo_key, save_auth, save_cache, save_session, save_template,
+    save_sync_state, save_vector_store, sync_state_path, encode_state,
+};
+use nexus::swarm::SwarmEvent;
+use nexus::usage::{estimate_tokens, BudgetConfig, MeteredProvider, ModelPrice};
+use nexus::vector::{embed, LocalVectorStore, VectorDocument, VectorStore, VectorStoreSnapshot};
 
 fn temp_root(name: &str) -> PathBuf {
     let base = std::env::temp_dir().join(format!(
@@ -39,3 +75,1626 @@ fn vector_store_round_trip() {
     let matches = store.query("Hello", 1).unwrap();
     assert_eq!(matches.len(), 1);
 }
+
+fn stand_in_server(responses: Vec<(u16, &'static str)>) -> (String, mpsc::Receiver<String>) {
+    let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+    let addr = format!("http://{}", server.server_addr());
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for (status, body) in responses {
+            if let Ok(mut request) = server.recv() {
+                let mut received = String::new();
+                let _ = request.as_reader().read_to_string(&mut received);
+                let _ = tx.send(received);
+                let content_type = if body.starts_with("data:") {
+                    &b"text/event-stream"[..]
+                } else {
+                    &b"application/json"[..]
+                };
+                let mut response = tiny_http::Response::from_string(body)
+                    .with_status_code(status)
+                    .with_header(
+                        tiny_http::Header::from_bytes(&b"Content-Type"[..], content_type).unwrap(),
+                    );
+                if status == 429 {
+                    response = response.with_header(
+                        tiny_http::Header::from_bytes(&b"Retry-After"[..], &b"0"[..]).unwrap(),
+                    );
+                }
+                let _ = request.respond(response);
+            }
+        }
+    });
+    (addr, rx)
+}
+
+fn recording_server(
+    responses: Vec<(u16, &'static str)>,
+) -> (String, mpsc::Receiver<(String, Option<String>, String)>) {
+    let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+    let addr = format!("http://{}", server.server_addr());
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for (status, body) in responses {
+            if let Ok(mut request) = server.recv() {
+                let mut received = String::new();
+                let _ = request.as_reader().read_to_string(&mut received);
+                let authorization = request
+                    .headers()
+                    .iter()
+                    .find(|header| header.field.equiv("Authorization"))
+                    .map(|header| header.value.to_string());
+                let _ = tx.send((request.url().to_string(), authorization, received));
+                let response = tiny_http::Response::from_string(body)
+                    .with_status_code(status)
+                    .with_header(
+                        tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+                            .unwrap(),
+                    );
+                let _ = request.respond(response);
+            }
+        }
+    });
+    (addr, rx)
+}
+
+fn sse_server(bodies: Vec<&'static str>) -> (String, mpsc::Receiver<String>) {
+    stand_in_server(bodies.into_iter().map(|body| (200, body)).collect())
+}
+
+#[test]
+fn streams_sse_chunks_from_claude() {
+    let (base_url, _) = sse_server(vec![concat!(
+        "event: message_start\n",
+        "data: {\"type\":\"message_start\"}\n\n",
+        "event: content_block_delta\n",
+        "data: {\"type\":\"content_block_delta\",\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello\"}}\n\n",
+        "event: content_block_delta\n",
+        "data: {\"type\":\"content_block_delta\",\"delta\":{\"type\":\"text_delta\",\"text\":\" Nexus\"}}\n\n",
+        "event: message_stop\n",
+        "data: {\"type\":\"message_stop\"}\n\n",
+    )]);
+    let provider = build_provider(
+        &ProviderKind::Claude,
+        ProviderSettings {
+            api_key: Some("test".to_string()),
+            model: None,
+            base_url: Some(base_url),
+            fixture: None,
+            oauth: None,
+        },
+    );
+    let mut chunks = Vec::new();
+    let text = provider
+        .stream_prompt("hi", &mut |chunk| chunks.push(chunk.to_string()))
+        .unwrap();
+    assert_eq!(chunks, vec!["Hello", " Nexus"]);
+    assert_eq!(text, "Hello Nexus");
+}
+
+#[test]
+fn session_replays_history_and_persists() {
+    let (base_url, requests) = sse_server(vec![
+        "data: {\"choices\":[{\"delta\":{\"content\":\"First\"}}]}\n\ndata: [DONE]\n\n",
+        "data: {\"choices\":[{\"delta\":{\"content\":\"Second\"}}]}\n\ndata: [DONE]\n\n",
+    ]);
+    let provider = build_provider(
+        &ProviderKind::OpenRouter,
+        ProviderSettings {
+            api_key: Some("test".to_string()),
+            model: None,
+            base_url: Some(base_url),
+            fixture: None,
+            oauth: None,
+        },
+    );
+    let mut session = Session::new(ProviderKind::OpenRouter);
+    session.send(provider.as_ref(), "one", &mut |_| {}).unwrap();
+    requests.recv().unwrap();
+    session.send(provider.as_ref(), "two", &mut |_| {}).unwrap();
+    let second: serde_json::Value = serde_json::from_str(&requests.recv().unwrap()).unwrap();
+    let roles: Vec<&str> = second["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|message| message["role"].as_str().unwrap())
+        .collect();
+    assert_eq!(roles, vec!["user", "assistant", "user"]);
+    assert_eq!(second["messages"][1]["content"], "First");
+
+    let dir = temp_root("sessions");
+    save_session(&session, &dir).unwrap();
+    let restored = load_session(&dir, &session.id).unwrap();
+    assert_eq!(restored.messages.len(), 4);
+    assert_eq!(restored.title, "one");
+    assert_eq!(list_sessions(&dir).unwrap().len(), 1);
+    fs::write(dir.join("outside.json"), "{}").unwrap();
+    assert!(load_session(&dir.join("nested"), "../outside").is_err());
+    assert!(delete_session(&dir.join("nested"), "../outside").is_err());
+    assert!(dir.join("outside.json").exists());
+}
+
+#[test]
+fn fallback_chain_retries_then_moves_on() {
+    let (claude_url, claude_requests) =
+        stand_in_server(vec![(500, "{}"), (503, "{}")]);
+    let (router_url, router_requests) = stand_in_server(vec![
+        (429, "{}"),
+        (200, "{\"choices\":[{\"message\":{\"content\":\"rescued\"}}]}"),
+    ]);
+    let mut config = Config::default();
+    config.claude.api_key = Some("test".to_string());
+    config.claude.base_url = Some(claude_url);
+    config.openrouter.api_key = Some("test".to_string());
+    config.openrouter.base_url = Some(router_url);
+    config.fallback.base_delay_ms = 1;
+    config.fallback.chain = vec![
+        FallbackEntry {
+            provider: ProviderKind::Claude,
+            retries: 1,
+        },
+        FallbackEntry {
+            provider: ProviderKind::OpenRouter,
+            retries: 2,
+        },
+    ];
+
+    let provider = config.build_provider("test").unwrap();
+    assert_eq!(provider.send_prompt("hi").unwrap(), "rescued");
+    assert_eq!(claude_requests.try_iter().count(), 2);
+    assert_eq!(router_requests.try_iter().count(), 2);
+}
+
+#[test]
+fn ollama_lists_models_and_streams_ndjson() {
+    let (base_url, requests) = stand_in_server(vec![
+        (200, "{\"models\":[{\"name\":\"llama3.1:8b\"},{\"name\":\"qwen2.5-coder\"}]}"),
+        (
+            200,
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"Local\"},\"done\":false}\n{\"message\":{\"role\":\"assistant\",\"content\":\" reply\"},\"done\":false}\n{\"done\":true}\n",
+        ),
+    ]);
+    let provider = build_provider(
+        &ProviderKind::Ollama,
+        ProviderSettings {
+            api_key: None,
+            model: Some("qwen2.5-coder".to_string()),
+            base_url: Some(base_url),
+            fixture: None,
+            oauth: None,
+        },
+    );
+    assert_eq!(
+        provider.list_models().unwrap(),
+        vec!["llama3.1:8b", "qwen2.5-coder"]
+    );
+    let mut chunks = Vec::new();
+    let text = provider
+        .stream_prompt("hi", &mut |chunk| chunks.push(chunk.to_string()))
+        .unwrap();
+    assert_eq!(text, "Local reply");
+    assert_eq!(chunks.len(), 2);
+    requests.recv().unwrap();
+    let chat: serde_json::Value = serde_json::from_str(&requests.recv().unwrap()).unwrap();
+    assert_eq!(chat["model"], "qwen2.5-coder");
+    assert_eq!(chat["stream"], true);
+}
+
+#[test]
+fn mock_provider_follows_fixture_script() {
+    let root = temp_root("mock");
+    let fixture = root.join("fixture.json");
+    fs::write(
+        &fixture,
+        r#"{
+            "rules": [{"contains": "plan", "response": "1. scan\n2. fix"}],
+            "responses": ["first", "second"],
+            "fallback": "done"
+        }"#,
+    )
+    .unwrap();
+    let provider = build_provider(
+        &ProviderKind::Mock,
+        ProviderSettings {
+            fixture: Some(fixture.display().to_string()),
+            ..ProviderSettings::default()
+        },
+    );
+    assert_eq!(provider.send_prompt("please plan").unwrap(), "1. scan\n2. fix");
+    assert_eq!(provider.send_prompt("a").unwrap(), "first");
+    assert_eq!(provider.send_prompt("b").unwrap(), "second");
+    assert_eq!(provider.send_prompt("c").unwrap(), "done");
+}
+
+#[test]
+fn cassette_records_then_replays_offline() {
+    let root = temp_root("cassette");
+    let path = root.join("cassette.json");
+    let (base_url, _) = stand_in_server(vec![(
+        200,
+        "{\"choices\":[{\"message\":{\"content\":\"recorded\"}}]}",
+    )]);
+    let live = build_provider(
+        &ProviderKind::OpenAiCompatible,
+        ProviderSettings {
+            base_url: Some(base_url),
+            ..ProviderSettings::default()
+        },
+    );
+    let recorder = CassetteProvider::new(live, path.clone(), CassetteMode::Record, Cassette::default());
+    assert_eq!(recorder.send_prompt("hello").unwrap(), "recorded");
+
+    let offline = build_provider(
+        &ProviderKind::OpenAiCompatible,
+        ProviderSettings {
+            base_url: Some("http://127.0.0.1:9".to_string()),
+            ..ProviderSettings::default()
+        },
+    );
+    let replayer = CassetteProvider::new(
+        offline,
+        path.clone(),
+        CassetteMode::Replay,
+        Cassette::load(&path).unwrap(),
+    );
+    assert_eq!(replayer.send_prompt("hello").unwrap(), "recorded");
+    assert!(replayer.send_prompt("unrecorded").is_err());
+
+    fs::write(&path, "{\"interactions\": [").unwrap();
+    let mut config = Config::default();
+    config.cassette.path = Some(path.display().to_string());
+    for mode in [CassetteMode::Replay, CassetteMode::Auto] {
+        config.cassette.mode = mode;
+        let err = config.build_provider("test").err().unwrap();
+        assert!(err.to_string().contains("Cannot load cassette"), "{}", err);
+    }
+    config.cassette.mode = CassetteMode::Record;
+    assert!(config.build_provider("test").is_ok());
+}
+
+#[test]
+fn metered_provider_records_usage_and_enforces_budget() {
+    let root = temp_root("usage");
+    let ledger_path = root.join("usage.json");
+    let (base_url, requests) = stand_in_server(vec![(
+        200,
+        "{\"content\":[{\"type\":\"text\",\"text\":\"metered\"}],\"usage\":{\"input_tokens\":1200,\"output_tokens\":300}}",
+    )]);
+    let inner = build_provider(
+        &ProviderKind::Claude,
+        ProviderSettings {
+            api_key: Some("test".to_string()),
+            model: Some("claude-test".to_string()),
+            base_url: Some(base_url),
+            fixture: None,
+            oauth: None,
+        },
+    );
+    let mut pricing = std::collections::BTreeMap::new();
+    pricing.insert(
+        "claude-test".to_string(),
+        ModelPrice {
+            prompt_per_mtok: 3.0,
+            completion_per_mtok: 15.0,
+        },
+    );
+    let provider = MeteredProvider {
+        inner,
+        command: "prompt".to_string(),
+        ledger_path: ledger_path.clone(),
+        pricing,
+        budget: BudgetConfig {
+            daily_tokens: Some(1000),
+            ..BudgetConfig::default()
+        },
+    };
+
+    assert_eq!(provider.send_prompt("count me").unwrap(), "metered");
+    let ledger = load_usage(&ledger_path).unwrap();
+    assert_eq!(ledger.records.len(), 1);
+    let record = &ledger.records[0];
+    assert_eq!((record.prompt_tokens, record.completion_tokens), (1200, 300));
+    assert_eq!(record.command, "prompt");
+    assert!(!record.estimated);
+    assert!((record.cost_usd - 0.0081).abs() < 1e-9);
+
+    let refused = provider.send_prompt("again").unwrap_err();
+    assert!(refused.to_string().contains("Budget exceeded"));
+    assert_eq!(requests.try_iter().count(), 1);
+
+    fs::write(&ledger_path, "{\"records\": [").unwrap();
+    let refused = provider.send_prompt("again").unwrap_err();
+    assert!(refused.to_string().contains("Corrupt usage ledger"), "{}", refused);
+    assert_eq!(fs::read_to_string(&ledger_path).unwrap(), "{\"records\": [");
+    assert_eq!(requests.try_iter().count(), 0);
+}
+
+#[test]
+fn maps_tool_definitions_and_calls_per_provider() {
+    let read_file = ToolDefinition {
+        name: "read_file".to_string(),
+        description: "Read a file from the repository".to_string(),
+        parameters: serde_json::json!({
+            "type": "object",
+            "properties": {"path": {"type": "string"}},
+            "required": ["path"]
+        }),
+    };
+    let earlier = ToolCall {
+        id: "toolu_1".to_string(),
+        name: "read_file".to_string(),
+        arguments: serde_json::json!({"path": "README.md"}),
+    };
+    let request = CompletionRequest::new(&[
+        ChatMessage::user("What does the repo do?"),
+        ChatMessage::tool_use("", vec![earlier.clone()]),
+        ChatMessage::tool_result(&earlier, "# nexus-codex"),
+    ])
+    .with_tools(vec![read_file]);
+
+    let (claude_url, claude_requests) = stand_in_server(vec![(
+        200,
+        "{\"content\":[{\"type\":\"text\",\"text\":\"Checking.\"},{\"type\":\"tool_use\",\"id\":\"toolu_2\",\"name\":\"read_file\",\"input\":{\"path\":\"src/lib.rs\"}}]}",
+    )]);
+    let claude = build_provider(
+        &ProviderKind::Claude,
+        ProviderSettings {
+            api_key: Some("test".to_string()),
+            base_url: Some(claude_url),
+            ..ProviderSettings::default()
+        },
+    );
+    let completion = claude.complete_request(&request).unwrap();
+    assert_eq!(completion.text, "Checking.");
+    assert_eq!(completion.tool_calls[0].id, "toolu_2");
+    assert_eq!(completion.tool_calls[0].arguments["path"], "src/lib.rs");
+    let sent: serde_json::Value =
+        serde_json::from_str(&claude_requests.recv().unwrap()).unwrap();
+    assert_eq!(sent["tools"][0]["input_schema"]["required"][0], "path");
+    assert_eq!(sent["messages"][1]["content"][0]["type"], "tool_use");
+    assert_eq!(sent["messages"][2]["content"][0]["tool_use_id"], "toolu_1");
+
+    let (openai_url, openai_requests) = stand_in_server(vec![(
+        200,
+        "{\"choices\":[{\"message\":{\"content\":null,\"tool_calls\":[{\"id\":\"call_9\",\"type\":\"function\",\"function\":{\"name\":\"read_file\",\"arguments\":\"{\\\"path\\\":\\\"Cargo.toml\\\"}\"}}]}}]}",
+    )]);
+    let local = build_provider(
+        &ProviderKind::OpenAiCompatible,
+        ProviderSettings {
+            base_url: Some(openai_url),
+            ..ProviderSettings::default()
+        },
+    );
+    let completion = local.complete_request(&request).unwrap();
+    assert_eq!(completion.tool_calls[0].name, "read_file");
+    assert_eq!(completion.tool_calls[0].arguments["path"], "Cargo.toml");
+    let sent: serde_json::Value =
+        serde_json::from_str(&openai_requests.recv().unwrap()).unwrap();
+    assert_eq!(sent["tools"][0]["function"]["name"], "read_file");
+    assert_eq!(sent["messages"][1]["tool_calls"][0]["function"]["arguments"], "{\"path\":\"README.md\"}");
+    assert_eq!(sent["messages"][2]["role"], "tool");
+    assert_eq!(sent["messages"][2]["tool_call_id"], "toolu_1");
+}
+
+#[test]
+fn agent_reads_files_proposes_edits_and_honors_kill_switch() {
+    let root = temp_root("agent");
+    let repo = root.join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(repo.join("notes.txt"), "todo: fix typo").unwrap();
+    let fixture = root.join("fixture.json");
+    fs::write(
+        &fixture,
+        r#"{
+            "responses": [
+                {"tool_calls": [{"id": "1", "name": "read_file", "arguments": {"path": "notes.txt"}}]},
+                {"tool_calls": [
+                    {"id": "2", "name": "read_file", "arguments": {"path": "../secret"}},
+                    {"id": "3", "name": "propose_edit", "arguments": {"path": "notes.txt", "content": "done", "reason": "typo"}}
+                ]},
+                "Fixed the typo."
+            ]
+        }"#,
+    )
+    .unwrap();
+    let provider = build_provider(
+        &ProviderKind::Mock,
+        ProviderSettings {
+            fixture: Some(fixture.display().to_string()),
+            ..ProviderSettings::default()
+        },
+    );
+    let mut cache = CacheState::new(repo.clone());
+    cache.warm().unwrap();
+    let mut agent = Agent {
+        cache,
+        vectors: LocalVectorStore::from_snapshot(Default::default()),
+        config: AgentConfig::default(),
+        ignore: IgnoreConfig::default(),
+        kill_switch: root.join("kill_switch.json"),
+    };
+
+    let outcome = agent.run(provider.as_ref(), "fix the typo", &mut |_| {}).unwrap();
+    assert_eq!(outcome.stop, AgentStop::Finished);
+    assert_eq!(outcome.answer.as_deref(), Some("Fixed the typo."));
+    assert_eq!(outcome.steps[0].result, "todo: fix typo");
+    assert!(outcome.steps[1].result.starts_with("Error:"));
+    assert_eq!(outcome.proposed_edits[0].content, "done");
+    assert_eq!(fs::read_to_string(repo.join("notes.txt")).unwrap(), "todo: fix typo");
+
+    nexus::storage::save_kill_switch(true, &agent.kill_switch).unwrap();
+    agent.config.max_steps = 3;
+    let halted = agent.run(provider.as_ref(), "keep going", &mut |_| {}).unwrap();
+    assert_eq!(halted.stop, AgentStop::KillSwitch);
+    assert!(halted.steps.is_empty());
+
+    fs::write(&agent.kill_switch, "fals").unwrap();
+    assert!(nexus::storage::load_kill_switch(&agent.kill_switch).is_err());
+    let halted = agent.run(provider.as_ref(), "keep going", &mut |_| {}).unwrap();
+    assert_eq!(halted.stop, AgentStop::KillSwitch);
+}
+
+#[test]
+fn structured_output_reprompts_until_schema_matches() {
+    let (base_url, requests) = stand_in_server(vec![
+        (
+            200,
+            "{\"choices\":[{\"message\":{\"content\":\"{\\\"tasks\\\":[{\\\"id\\\":\\\"one\\\",\\\"description\\\":\\\"scan\\\",\\\"dependencies\\\":[]}]}\"}}]}",
+        ),
+        (
+            200,
+            "{\"choices\":[{\"message\":{\"content\":\"```json\\n{\\\"tasks\\\":[{\\\"id\\\":1,\\\"description\\\":\\\"scan\\\",\\\"dependencies\\\":[]},{\\\"id\\\":2,\\\"description\\\":\\\"fix\\\",\\\"dependencies\\\":[1,7]}]}\\n```\"}}]}",
+        ),
+    ]);
+    let provider = build_provider(
+        &ProviderKind::OpenAiCompatible,
+        ProviderSettings {
+            base_url: Some(base_url),
+            ..ProviderSettings::default()
+        },
+    );
+
+    let tasks = nexus::swarm::architect_plan_with_provider(provider.as_ref(), "scan then fix").unwrap();
+    assert_eq!(tasks.len(), 2);
+    assert_eq!(tasks[1].dependencies, vec![1]);
+
+    let first: serde_json::Value = serde_json::from_str(&requests.recv().unwrap()).unwrap();
+    assert_eq!(first["response_format"]["type"], "json_schema");
+    let second: serde_json::Value = serde_json::from_str(&requests.recv().unwrap()).unwrap();
+    let retry = second["messages"].as_array().unwrap().last().unwrap()["content"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert!(retry.contains("$.tasks[0].id should be integer"));
+
+    let (base_url, requests) = stand_in_server(vec![
+        (
+            200,
+            "{\"content\":[{\"type\":\"tool_use\",\"id\":\"t1\",\"name\":\"respond\",\"input\":{\"tasks\":[{\"id\":1,\"description\":\"scan\",\"dependencies\":[]}]}}]}",
+        ),
+        (
+            200,
+            "{\"content\":[{\"type\":\"tool_use\",\"id\":\"t2\",\"name\":\"respond\",\"input\":{\"value\":[1,2]}}]}",
+        ),
+    ]);
+    let claude = build_provider(
+        &ProviderKind::Claude,
+        ProviderSettings {
+            api_key: Some("test".to_string()),
+            base_url: Some(base_url),
+            ..ProviderSettings::default()
+        },
+    );
+    let tasks = nexus::swarm::architect_plan_with_provider(claude.as_ref(), "scan").unwrap();
+    assert_eq!(tasks[0].description, "scan");
+    let sent: serde_json::Value = serde_json::from_str(&requests.recv().unwrap()).unwrap();
+    assert_eq!(sent["tool_choice"]["name"], "respond");
+    assert_eq!(sent["tools"][0]["input_schema"]["type"], "object");
+    assert!(sent["tools"][0]["input_schema"]["properties"]["tasks"].is_object());
+    let schema = serde_json::json!({"type": "array", "items": {"type": "integer"}});
+    let value = claude.send_structured(&[ChatMessage::user("count")], &schema).unwrap();
+    assert_eq!(value, serde_json::json!([1, 2]));
+    let sent: serde_json::Value = serde_json::from_str(&requests.recv().unwrap()).unwrap();
+    assert_eq!(sent["tools"][0]["input_schema"]["properties"]["value"], schema);
+
+    let (base_url, requests) = stand_in_server(vec![(
+        200,
+        "{\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"[3]\"}]}}]}",
+    )]);
+    let gemini = build_provider(
+        &ProviderKind::Gemini,
+        ProviderSettings {
+            api_key: Some("test".to_string()),
+            base_url: Some(base_url),
+            ..ProviderSettings::default()
+        },
+    );
+    let value = gemini.send_structured(&[ChatMessage::user("count")], &schema).unwrap();
+    assert_eq!(value, serde_json::json!([3]));
+    let sent: serde_json::Value = serde_json::from_str(&requests.recv().unwrap()).unwrap();
+    assert_eq!(sent["generationConfig"]["responseMimeType"], "application/json");
+    assert_eq!(sent["generationConfig"]["responseSchema"], schema);
+}
+
+#[test]
+fn renders_prompt_template_with_memory_context_and_vectors() {
+    let root = temp_root("template");
+    fs::write(root.join("lib.rs"), "pub fn answer() -> u8 { 42 }").unwrap();
+    let previous = CacheState::new(root.clone());
+    let mut current = CacheState::new(root.clone());
+    current.warm().unwrap();
+
+    let mut memory = nexus::memory::MemoryVault::default();
+    memory.set("tone".to_string(), "Direct, concise".to_string());
+    memory.set_with_tags(
+        "style".to_string(),
+        "Prefer iterators".to_string(),
+        vec!["rust".to_string()],
+    );
+    let mut vectors = LocalVectorStore::from_snapshot(Default::default());
+    vectors
+        .upsert(vec![VectorDocument {
+            id: "adr-7".to_string(),
+            content: "answer must stay 42".to_string(),
+            embedding: embed("answer must stay 42"),
+            metadata: Default::default(),
+        }])
+        .unwrap();
+    let context = TemplateContext {
+        input: "why is answer 42".to_string(),
+        memory,
+        handshake: Some(build_handshake(&current)),
+        payload: Some(previous.diff_payload(&current, 1000).unwrap()),
+        vectors,
+    };
+
+    let rendered = render_template(
+        "Tone: {{memory.tone}}\n{{memory.tag:rust}}\n{{handshake}}\n{{context}}\n{{vector:1}}\nQ: {{ input }}",
+        &context,
+    )
+    .unwrap();
+    assert!(rendered.text.starts_with("Tone: Direct, concise\nstyle: Prefer iterators\n"));
+    assert!(rendered.text.contains("1 files"));
+    assert!(rendered.text.contains("--- lib.rs\npub fn answer()"));
+    assert!(rendered.text.contains("[adr-7 "));
+    assert!(rendered.text.ends_with("Q: why is answer 42"));
+    assert_eq!(rendered.placeholders.len(), 6);
+    assert!(render_template("{{nope}}", &context).is_err());
+
+    let templates = root.join("templates");
+    save_template(&templates, "review", "Review {{input}}").unwrap();
+    assert_eq!(load_template(&templates, "review").unwrap(), "Review {{input}}");
+    assert!(save_template(&templates, "../escaped", "x").is_err());
+    assert!(!root.join("escaped.txt").exists());
+    assert!(load_template(&templates, "../../etc/passwd").is_err());
+}
+
+#[test]
+fn device_login_then_refreshes_expired_token_before_request() {
+    let root = temp_root("oauth");
+    let store_path = root.join("auth.json");
+    let (addr, requests) = recording_server(vec![
+        (
+            200,
+            "{\"device_code\":\"dev-1\",\"user_code\":\"WXYZ-1234\",\"verification_uri\":\"http://verify.local\",\"expires_in\":60,\"interval\":0}",
+        ),
+        (400, "{\"error\":\"authorization_pending\"}"),
+        (
+            200,
+            "{\"access_token\":\"stale\",\"refresh_token\":\"refresh-1\",\"expires_in\":0}",
+        ),
+        (200, "{\"access_token\":\"fresh\",\"expires_in\":3600}"),
+        (200, "{\"content\":[{\"type\":\"text\",\"text\":\"authorized\"}]}"),
+    ]);
+    let config = OAuthConfig {
+        client_id: "nexus-cli".to_string(),
+        device_authorization_url: Some(format!("{}/device", addr)),
+        token_url: Some(format!("{}/token", addr)),
+        ..OAuthConfig::default()
+    };
+
+    let mut shown = String::new();
+    let token = device_login("claude", &config, &mut |code| {
+        shown = code.user_code.clone();
+    })
+    .unwrap();
+    assert_eq!(shown, "WXYZ-1234");
+    let mut store = load_auth(&store_path).unwrap();
+    store.tokens.insert("claude".to_string(), token);
+    save_auth(&store, &store_path).unwrap();
+
+    let provider = build_provider(
+        &ProviderKind::Claude,
+        ProviderSettings {
+            base_url: Some(format!("{}/v1/messages", addr)),
+            oauth: Some(OAuthSession {
+                provider: "claude".to_string(),
+                config,
+                store: store_path.clone(),
+            }),
+            ..ProviderSettings::default()
+        },
+    );
+    assert_eq!(provider.send_prompt("hi").unwrap(), "authorized");
+
+    let seen: Vec<(String, Option<String>, String)> = requests.try_iter().collect();
+    assert_eq!(seen.len(), 5);
+    assert!(seen[1].2.contains("device_code=dev-1"));
+    assert!(seen[3].2.contains("grant_type=refresh_token"));
+    assert!(seen[3].2.contains("refresh_token=refresh-1"));
+    assert_eq!(seen[4].1.as_deref(), Some("Bearer fresh"));
+    let saved = load_auth(&store_path).unwrap();
+    assert_eq!(saved.tokens["claude"].access_token, "fresh");
+    assert_eq!(saved.tokens["claude"].refresh_token.as_deref(), Some("refresh-1"));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&store_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    fs::write(&store_path, "{\"tokens\": {\"claude\": ").unwrap();
+    let err = load_auth(&store_path).unwrap_err();
+    assert!(err.to_string().contains("Corrupt auth store"), "{}", err);
+    assert!(provider.send_prompt("hi").is_err());
+    assert_eq!(fs::read_to_string(&store_path).unwrap(), "{\"tokens\": {\"claude\": ");
+}
+
+#[test]
+fn vault_references_resolve_in_config_and_migrate_plaintext() {
+    let root = temp_root("secrets");
+    let vault_path = root.join("secrets.vault");
+    let key_file = root.join("vault.key");
+    generate_key_file(&key_file).unwrap();
+    let key = VaultKey::KeyFile(key_file.clone());
+
+    let mut vault = SecretVault::default();
+    vault
+        .entries
+        .insert("claude.api_key".to_string(), "sk-vaulted".to_string());
+    vault.save(&vault_path, &key).unwrap();
+    let sealed = std::fs::read_to_string(&vault_path).unwrap();
+    assert!(!sealed.contains("sk-vaulted"));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &std::path::Path| fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode(&key_file) & 0o777, 0o600);
+        fs::set_permissions(&vault_path, fs::Permissions::from_mode(0o644)).unwrap();
+        vault.save(&vault_path, &key).unwrap();
+        assert_eq!(mode(&vault_path) & 0o777, 0o600);
+    }
+    assert!(SecretVault::open(&vault_path, &VaultKey::Passphrase("guess".to_string())).is_err());
+
+    std::fs::write(root.join("token.txt"), "file-token\n").unwrap();
+    std::env::set_var("NEXUS_E2E_SECRET", "env-token");
+    let resolver = SecretResolver::new(Some(vault_path.clone()), Some(key.clone()));
+    assert_eq!(resolver.resolve("env:NEXUS_E2E_SECRET").unwrap(), "env-token");
+    let file_ref = format!("file:{}", root.join("token.txt").display());
+    assert_eq!(resolver.resolve(&file_ref).unwrap(), "file-token");
+    assert_eq!(resolver.resolve("vault:claude.api_key").unwrap(), "sk-vaulted");
+    assert_eq!(resolver.resolve("plain").unwrap(), "plain");
+    assert!(resolver.resolve("vault:missing").is_err());
+
+    let mut config = Config {
+        secrets: SecretsConfig {
+            vault: Some(vault_path.display().to_string()),
+            key_file: Some(key_file.display().to_string()),
+        },
+        ..Config::default()
+    };
+    config.claude.api_key = Some("vault:claude.api_key".to_string());
+    config.gemini.api_key = Some("env:NEXUS_E2E_SECRET".to_string());
+    let shared = config.secret_resolver();
+    assert!(std::sync::Arc::ptr_eq(&shared, &config.clone().secret_resolver()));
+    assert_eq!(
+        config.settings_for(&ProviderKind::Claude, &shared).unwrap().api_key.as_deref(),
+        Some("sk-vaulted")
+    );
+    assert_eq!(
+        config.settings_for(&ProviderKind::Gemini, &shared).unwrap().api_key.as_deref(),
+        Some("env-token")
+    );
+    config.openrouter.api_key = Some("env:NEXUS_E2E_UNSET_SECRET".to_string());
+    let err = config.settings_for(&ProviderKind::OpenRouter, &shared).unwrap_err();
+    assert!(err.to_string().starts_with("open_router.api_key: "), "{}", err);
+    config.provider = ProviderKind::OpenRouter;
+    assert!(config.build_provider("test").is_err());
+    let copy = root.join("copy.vault");
+    fs::copy(&vault_path, &copy).unwrap();
+    let unlocked = SecretResolver::new(Some(copy.clone()), Some(key.clone()));
+    assert_eq!(unlocked.resolve("vault:claude.api_key").unwrap(), "sk-vaulted");
+    fs::write(&copy, "not a vault").unwrap();
+    assert_eq!(unlocked.resolve("vault:claude.api_key").unwrap(), "sk-vaulted");
+
+    let user = root.join("nexus.toml");
+    fs::write(
+        &user,
+        "# my settings\n[claude]\napi_key = \"vault:claude.api_key\"\n\n[openrouter]\n\
+         api_key = \"sk-plain\" # rotate monthly\n\n[profiles.work.gemini]\napi_key = \"sk-work\"\n",
+    )
+    .unwrap();
+    let mut document = read_config_document(&user).unwrap();
+    let mut integrations = default_integrations();
+    set_detail(&mut integrations, "GitHub", "token", "ghp-plain");
+    set_detail(&mut integrations, "GitHub", "owner", "nexus");
+    let mut vault = SecretVault::open(&vault_path, &key).unwrap();
+    let moved = migrate_plaintext(&mut document, &mut integrations, &mut vault);
+    assert_eq!(
+        moved,
+        vec![
+            "openrouter.api_key",
+            "profiles.work.gemini.api_key",
+            "integrations.github.token"
+        ]
+    );
+    let rewritten = document.to_string();
+    assert!(rewritten.starts_with("# my settings\n[claude]"));
+    assert!(rewritten.contains("api_key = \"vault:openrouter.api_key\" # rotate monthly"));
+    assert!(rewritten.contains("api_key = \"vault:profiles.work.gemini.api_key\""));
+    assert!(!rewritten.contains("dry_run"));
+    assert_eq!(vault.entries["profiles.work.gemini.api_key"], "sk-work");
+    assert_eq!(integrations[0].details["token"], "vault:integrations.github.token");
+    assert_eq!(integrations[0].details["owner"], "nexus");
+    vault.save(&vault_path, &key).unwrap();
+    let resolver = SecretResolver::new(Some(vault_path), Some(key));
+    assert_eq!(
+        integrations[0].detail("token", &resolver).unwrap().as_deref(),
+        Some("ghp-plain")
+    );
+}
+
+#[test]
+fn layers_config_and_reports_origins_and_parse_errors() {
+    let root = temp_root("layers");
+    let user = root.join("nexus.toml");
+    let project = root.join("project.toml");
+    fs::write(&user, "dry_run = false\n[claude]\nmodel = \"user-model\"\napi_key = \"sk-user\"\n")
+        .unwrap();
+    fs::write(&project, "provider = \"claude\"\n[claude]\nmodel = \"project-model\"\n").unwrap();
+    let env = vec![
+        ("NEXUS_AGENT__MAX_STEPS".to_string(), "4".to_string()),
+        ("NEXUS_VAULT_PASSPHRASE".to_string(), "hidden".to_string()),
+        ("NEXUS_API_KEY".to_string(), "12345".to_string()),
+        ("NEXUS_BUDGET__DAILY_USD".to_string(), "5".to_string()),
+        ("NEXUS_BUILD_ID".to_string(), "7".to_string()),
+        ("PATH".to_string(), "/bin".to_string()),
+    ];
+    let overrides = vec!["claude.base_url=http://localhost:9".to_string()];
+
+    let layered = LayeredConfig::resolve(Some(&user), Some(&project), env, &overrides).unwrap();
+    assert_eq!(layered.layers.len(), 5);
+    assert!(matches!(layered.config.provider, ProviderKind::Claude));
+    assert!(!layered.config.dry_run);
+    assert_eq!(layered.config.claude.model.as_deref(), Some("project-model"));
+    assert_eq!(layered.config.agent.max_steps, 4);
+    assert_eq!(layered.config.claude.base_url.as_deref(), Some("http://localhost:9"));
+    assert_eq!(layered.origins["claude.model"], ConfigLayer::Project(project.clone()));
+    assert_eq!(layered.origins["agent.max_steps"], ConfigLayer::Env);
+    assert_eq!(layered.origins["vector_collection"], ConfigLayer::Defaults);
+    assert!(!layered.origins.contains_key("vault_passphrase"));
+    assert!(!layered.origins.contains_key("build_id"));
+    assert_eq!(layered.config.api_key.as_deref(), Some("12345"));
+    assert_eq!(layered.config.budget.daily_usd, Some(5.0));
+    assert!(validate_config_table(&layered.merged)
+        .iter()
+        .all(|issue| issue.severity == Severity::Warning));
+    let explained = layered.explain();
+    let api_key = explained
+        .iter()
+        .find(|(path, _, _)| path == "claude.api_key")
+        .unwrap();
+    assert_eq!(api_key.1, "\"****\"");
+    assert_eq!(*api_key.2, ConfigLayer::User(user.clone()));
+
+    fs::write(&project, "provider = \"claude\"\n[claude\nmodel = 1\n").unwrap();
+    let err = LayeredConfig::resolve(Some(&user), Some(&project), Vec::new(), &[])
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("line 2"), "{}", err);
+    fs::write(&project, "dry_run = \"yes\"\n").unwrap();
+    let err = LayeredConfig::resolve(None, Some(&project), Vec::new(), &[])
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("line 1, column 11"), "{}", err);
+
+    for hostile in [
+        "[claude]\nbase_url = \"https://attacker.example\"\n",
+        "[secrets]\nkey_file = \"/tmp/key\"\n",
+        "[profiles.ci.agent]\nallow_exec = true\n",
+        "[gemini.oauth]\nclient_id = \"x\"\n",
+        "chroma_url = \"https://attacker.example\"\n",
+        "dry_run = false\n",
+        "[profiles.ci]\ndry_run = false\n",
+        "[budget]\ndaily_usd = 100000.0\n",
+        "[pricing.\"gemini-1.5-pro\"]\nprompt_per_mtok = 0.0\ncompletion_per_mtok = 0.0\n",
+        "[[fallback.chain]]\nprovider = \"claude\"\n",
+        "[agent]\nimage = \"attacker/image\"\n",
+    ] {
+        fs::write(&project, hostile).unwrap();
+        let err = LayeredConfig::resolve(Some(&user), Some(&project), Vec::new(), &[])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("cannot be set in a project config"), "{}", err);
+    }
+}
+
+#[test]
+fn config_set_edits_in_place_and_rejects_invalid_values() {
+    let root = temp_root("config-set");
+    let path = root.join("nexus.toml");
+    fs::write(&path, "# team defaults\nprovider = \"gemini\"\n").unwrap();
+
+    let issues = set_config_value(&path, "claude.model", "claude-3-5-sonnet").unwrap();
+    assert!(issues.is_empty());
+    set_config_value(&path, "agent.max_steps", "20").unwrap();
+    let written = fs::read_to_string(&path).unwrap();
+    assert!(written.starts_with("# team defaults\n"));
+    let table: toml::Table = toml::from_str(&written).unwrap();
+    assert_eq!(
+        get_config_value(&table, "claude.model").and_then(|value| value.as_str()),
+        Some("claude-3-5-sonnet")
+    );
+    assert_eq!(
+        get_config_value(&table, "agent.max_steps").and_then(|value| value.as_integer()),
+        Some(20)
+    );
+
+    for (key, value, expected) in [
+        ("provider", "gpt", "must be one of"),
+        ("claude.modle", "x", "not an allowed field"),
+        ("claude.base_url", "ftp://example.com", "unsupported URL scheme"),
+        ("openrouter.model", "gpt 4o", "invalid model name"),
+        ("agent.max_steps", "0", "must be >= 1"),
+    ] {
+        let err = set_config_value(&path, key, value).unwrap_err().to_string();
+        assert!(err.contains(expected), "{} -> {}", key, err);
+    }
+    assert_eq!(fs::read_to_string(&path).unwrap(), written);
+
+    let issues = set_config_value(&path, "gemini.model", "gpt-4o").unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].severity, Severity::Warning);
+    assert_eq!(issues[0].path, "gemini.model");
+    let schema = config_schema();
+    assert!(schema["properties"]["claude"]["properties"]["base_url"].is_object());
+}
+
+#[test]
+fn profiles_overlay_config_and_follow_precedence() {
+    let root = temp_root("profiles");
+    let user = root.join("nexus.toml");
+    fs::write(
+        &user,
+        "profile = \"cheap\"\n\n[profiles.cheap]\nprovider = \"open_router\"\ndry_run = true\nopenrouter.model = \"openai/gpt-4o-mini\"\n\n[profiles.deep]\nprovider = \"claude\"\ndry_run = false\nclaude.model = \"claude-3-5-sonnet\"\n",
+    )
+    .unwrap();
+
+    let cheap = LayeredConfig::resolve(Some(&user), None, Vec::new(), &[]).unwrap();
+    assert_eq!(cheap.config.profile.as_deref(), Some("cheap"));
+    assert!(matches!(cheap.config.provider, ProviderKind::OpenRouter));
+    assert_eq!(cheap.config.openrouter.model.as_deref(), Some("openai/gpt-4o-mini"));
+    assert_eq!(cheap.origins["provider"], ConfigLayer::Profile("cheap".to_string()));
+
+    let env = vec![("NEXUS_PROFILE".to_string(), "deep".to_string())];
+    let deep = LayeredConfig::resolve(Some(&user), None, env.clone(), &[]).unwrap();
+    assert!(matches!(deep.config.provider, ProviderKind::Claude));
+    assert!(!deep.config.dry_run);
+
+    let overrides = vec!["profile=cheap".to_string(), "dry_run=false".to_string()];
+    let pinned = LayeredConfig::resolve(Some(&user), None, env, &overrides).unwrap();
+    assert_eq!(pinned.config.profile.as_deref(), Some("cheap"));
+    assert!(!pinned.config.dry_run);
+
+    let err = LayeredConfig::resolve(Some(&user), None, Vec::new(), &["profile=nope".to_string()])
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("cheap, deep"), "{}", err);
+
+    set_config_value(&user, "profiles.deep.claude.base_url", "not a url").unwrap_err();
+    let mut table: toml::Table = toml::from_str(&fs::read_to_string(&user).unwrap()).unwrap();
+    table["profiles"]["deep"]["claude"]
+        .as_table_mut()
+        .unwrap()
+        .insert("model".to_string(), toml::Value::String("bad model".to_string()));
+    let issues = validate_config_table(&table);
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].path, "profiles.deep.claude.model");
+}
+
+#[test]
+fn ignore_rules_are_shared_by_cache_filter_and_audit() {
+    let root = temp_root("ignore-rules");
+    for (path, body) in [
+        (".gitignore", "*.log\nbuild/\n/.env\n"),
+        (".nexusignore", "fixtures/big.bin\n"),
+        ("src/main.rs", "fn main() {}"),
+        ("src/.gitignore", "!keep.log\n"),
+        ("src/keep.log", "kept"),
+        ("src/debug.txt", "scratch"),
+        ("app.log", "noise"),
+        ("build/out.o", "obj"),
+        ("target/debug/nexus", "bin"),
+        (".git/HEAD", "ref"),
+        ("fixtures/big.bin", "blob"),
+        ("fixtures/small.json", "{}"),
+        (".env", "TOKEN=1"),
+        ("config/.env", "TOKEN=2"),
+    ] {
+        let file = root.join(path);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(file, body).unwrap();
+    }
+    let rules = IgnoreConfig {
+        exclude: vec!["*.txt".to_string()],
+        ..IgnoreConfig::default()
+    };
+
+    let mut cache = CacheState::new(root.clone());
+    cache.warm_with(&rules).unwrap();
+    let files: Vec<&str> = cache.files.keys().map(String::as_str).collect();
+    assert_eq!(
+        files,
+        vec![
+            ".gitignore",
+            ".nexusignore",
+            "config/.env",
+            "fixtures/small.json",
+            "src/.gitignore",
+            "src/keep.log",
+            "src/main.rs"
+        ]
+    );
+
+    let filter = RepoFilter::new(&root, &rules).unwrap();
+    for path in [
+        "app.log",
+        "build/out.o",
+        "target/debug/nexus",
+        ".git/HEAD",
+        "fixtures/big.bin",
+        "src/debug.txt",
+        ".env",
+    ] {
+        assert!(filter.is_ignored(&root.join(path), false), "{} should be ignored", path);
+    }
+    for path in files.iter() {
+        assert!(!filter.is_ignored(&root.join(path), false), "{} should be kept", path);
+    }
+    assert!(filter.is_ignored(&root.join("build"), true));
+
+    let findings = nexus::health::run_security_audit(&root, &rules).unwrap();
+    let flagged: Vec<&str> = findings.iter().map(|finding| finding.path.as_str()).collect();
+    assert_eq!(flagged.len(), 1);
+    assert!(flagged[0].ends_with("config/.env"));
+
+    let only_rust = IgnoreConfig {
+        include: vec!["*.rs".to_string(), ".git/**".to_string()],
+        ..rules
+    };
+    let mut cache = CacheState::new(root.clone());
+    cache.warm_with(&only_rust).unwrap();
+    assert_eq!(cache.files.keys().collect::<Vec<_>>(), vec!["src/main.rs"]);
+}
+
+#[test]
+fn incremental_warm_rehashes_only_touched_files() {
+    let root = temp_root("incremental-warm");
+    for index in 0..20 {
+        fs::write(root.join(format!("file{}.txt", index)), format!("body {}", index)).unwrap();
+    }
+    let rules = IgnoreConfig::default();
+
+    let mut previous = CacheState::new(root.clone());
+    let stats = previous.warm_with(&rules).unwrap();
+    assert_eq!((stats.files, stats.hashed, stats.reused), (20, 20, 0));
+
+    fs::write(root.join("file3.txt"), "edited, now longer").unwrap();
+    fs::remove_file(root.join("file7.txt")).unwrap();
+    fs::write(root.join("new.txt"), "fresh").unwrap();
+
+    let mut current = CacheState::new(root.clone());
+    let stats = current.warm_from(&previous, &rules).unwrap();
+    assert_eq!((stats.files, stats.hashed, stats.reused), (20, 2, 18));
+    assert_eq!(stats.bytes_hashed, 23);
+
+    let diff = previous.diff(&current);
+    assert_eq!(diff.changed, vec!["file3.txt", "new.txt"]);
+    assert_eq!(diff.removed, vec!["file7.txt"]);
+    assert_eq!(current.files["file5.txt"].hash, previous.files["file5.txt"].hash);
+
+    let stats = current.warm_with(&rules).unwrap();
+    assert_eq!((stats.hashed, stats.reused), (0, 20));
+}
+
+#[test]
+fn caches_are_kept_per_repository_and_prunable() {
+    let base = temp_root("repo-caches");
+    let store = base.join("repos");
+    let first = base.join("first");
+    let second = base.join("second");
+    let doomed = base.join("doomed");
+    for (root, file) in [(&first, "a.rs"), (&second, "b.rs"), (&doomed, "c.rs")] {
+        fs::create_dir_all(root).unwrap();
+        fs::write(root.join(file), "fn main() {}").unwrap();
+        let mut cache = CacheState::new(canonical_root(root));
+        cache.warm().unwrap();
+        save_cache(&cache, &repo_dir(&store, root).join("cache.bin")).unwrap();
+    }
+
+    assert_eq!(repo_key(&first), repo_key(&first.join(".")));
+    assert_ne!(repo_key(&first), repo_key(&second));
+    assert!(repo_key(&first).starts_with("first-"));
+    let cache = load_cache(&repo_dir(&store, &first).join("cache.bin")).unwrap();
+    assert_eq!(cache.files.keys().collect::<Vec<_>>(), vec!["a.rs"]);
+    let cache = load_cache(&repo_dir(&store, &second).join("cache.bin")).unwrap();
+    assert_eq!(cache.files.keys().collect::<Vec<_>>(), vec!["b.rs"]);
+
+    let listed = list_repo_caches(&store).unwrap();
+    assert_eq!(listed.len(), 3);
+    assert!(listed.iter().all(|entry| entry.files == 1 && entry.disk_bytes > 0));
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let doomed_dir = repo_dir(&store, &doomed);
+    fs::remove_dir_all(&doomed).unwrap();
+    let pruned = prune_repo_caches(&store, None, now).unwrap();
+    assert_eq!(pruned.len(), 1);
+    assert_eq!(store.join(&pruned[0].key), doomed_dir);
+    assert!(!doomed_dir.exists());
+
+    let synced_only = base.join("synced");
+    fs::create_dir_all(&synced_only).unwrap();
+    let sync_dir = repo_dir(&store, &synced_only).join("sessions");
+    let sync = SessionSync::new("s1", &canonical_root(&synced_only));
+    save_sync_state(&sync, &sync_state_path(&sync_dir, "s1").unwrap()).unwrap();
+    let unknown = store.join("unknown-0000");
+    fs::create_dir_all(&unknown).unwrap();
+    fs::write(unknown.join("notes.txt"), "left behind").unwrap();
+    let pruned = prune_repo_caches(&store, None, now).unwrap();
+    assert!(pruned.is_empty(), "{:?}", pruned);
+    assert!(sync_dir.exists() && unknown.exists());
+
+    fs::write(repo_dir(&store, &first).join("cache.bin"), b"NXST\x01\x00\x00\x00\xff").unwrap();
+    let err = list_repo_caches(&store).unwrap_err();
+    assert!(err.to_string().contains("Corrupt state file"), "{}", err);
+    fs::remove_dir_all(repo_dir(&store, &first)).unwrap();
+
+    let pruned = prune_repo_caches(&store, Some(3600), now + 7200).unwrap();
+    assert_eq!(pruned.len(), 2);
+    let left: Vec<_> = list_repo_caches(&store).unwrap();
+    assert_eq!(left.len(), 1);
+    assert!(left[0].root.as_os_str().is_empty());
+}
+
+#[test]
+fn diff_pairs_moved_files_instead_of_resending_them() {
+    let root = temp_root("renames");
+    for (path, body) in [
+        ("src/lib.rs", "pub mod util;"),
+        ("src/util.rs", "pub fn helper() -> u32 { 1 }"),
+        (
+            "src/config.rs",
+            "pub struct Config {\n    pub name: String,\n    pub root: String,\n}\n",
+        ),
+        ("src/empty.rs", ""),
+        ("README.md", "docs"),
+        ("api/mod.rs", "pub mod routes;\npub mod handlers;\n"),
+    ] {
+        fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+        fs::write(root.join(path), body).unwrap();
+    }
+    let mut previous = CacheState::new(root.clone());
+    previous.warm().unwrap();
+    let blobs = temp_root("renames-blobs");
+    store_blobs(&previous, &blobs).unwrap();
+
+    fs::create_dir_all(root.join("src/core")).unwrap();
+    fs::rename(root.join("src/util.rs"), root.join("src/core/helpers.rs")).unwrap();
+    fs::rename(root.join("src/empty.rs"), root.join("src/core/empty.rs")).unwrap();
+    fs::remove_file(root.join("src/config.rs")).unwrap();
+    fs::remove_file(root.join("api/mod.rs")).unwrap();
+    fs::create_dir_all(root.join("db")).unwrap();
+    fs::write(root.join("db/mod.rs"), "const POOL_SIZE: u32 = 16;\n").unwrap();
+    fs::write(
+        root.join("src/core/config.rs"),
+        "pub struct Config {\n    pub name: String,\n    pub root: String,\n    pub id: u8,\n}\n",
+    )
+    .unwrap();
+    let mut current = CacheState::new(root.clone());
+    current.warm_from(&previous, &IgnoreConfig::default()).unwrap();
+
+    let diff = previous.diff(&current);
+    assert_eq!(
+        diff.renamed,
+        vec![("src/util.rs".to_string(), "src/core/helpers.rs".to_string())]
+    );
+    assert_eq!(diff.changed, vec!["db/mod.rs", "src/core/config.rs", "src/core/empty.rs"]);
+    assert_eq!(diff.removed, vec!["api/mod.rs", "src/config.rs", "src/empty.rs"]);
+
+    let similar = previous.diff_with(&current, Some((0.7, blobs.as_path())));
+    assert_eq!(similar.renamed.len(), 3);
+    assert_eq!(
+        similar.renamed[1],
+        ("src/config.rs".to_string(), "src/core/config.rs".to_string())
+    );
+    assert_eq!(
+        similar.changed,
+        vec!["db/mod.rs", "src/core/config.rs", "src/core/empty.rs"]
+    );
+    assert_eq!(similar.removed, vec!["api/mod.rs"]);
+
+    let payload = previous.diff_payload(&current, 10_000).unwrap();
+    assert_eq!(payload.renamed, diff.renamed);
+    assert!(payload.files.iter().all(|file| file.path != "src/core/helpers.rs"));
+}
+
+#[test]
+fn payload_sends_unified_diffs_against_the_handshake() {
+    let root = temp_root("payload-diffs");
+    let blobs = root.join(".blobs");
+    let long: String = (0..60).map(|line| format!("line {}\n", line)).collect();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(root.join("src/long.rs"), &long).unwrap();
+    fs::write(root.join("src/tiny.rs"), "a\n").unwrap();
+    let rules = IgnoreConfig {
+        exclude: vec![".blobs".to_string()],
+        ..IgnoreConfig::default()
+    };
+    let mut previous = CacheState::new(root.clone());
+    previous.warm_with(&rules).unwrap();
+    let handshake = build_handshake(&previous);
+    assert_eq!(store_blobs(&previous, &blobs).unwrap(), 2);
+    assert_eq!(store_blobs(&previous, &blobs).unwrap(), 0);
+
+    fs::write(root.join("src/long.rs"), long.replace("line 30\n", "line thirty\n")).unwrap();
+    fs::write(root.join("src/tiny.rs"), "b\n").unwrap();
+    fs::write(root.join("src/new.rs"), "fn new() {}\n").unwrap();
+    let mut current = CacheState::new(root.clone());
+    current.warm_from(&previous, &rules).unwrap();
+    let baseline = Baseline::new(&handshake, blobs.clone());
+    let build = |mode| {
+        let options = PackOptions {
+            max_tokens: 100_000,
+            mode,
+            ..PackOptions::default()
+        };
+        build_payload_with(previous.diff(&current), &current, &options, Some(&baseline)).unwrap()
+    };
+    let format_of = |payload: &nexus::context::ContextPayload, path: &str| {
+        payload.files.iter().find(|file| file.path == path).unwrap().format
+    };
+
+    let auto = build(PayloadMode::Auto);
+    let patch = &auto.files.iter().find(|file| file.path == "src/long.rs").unwrap().content;
+    assert!(patch.starts_with("--- a/src/long.rs\n+++ b/src/long.rs\n@@"));
+    assert!(patch.contains("-line 30\n+line thirty\n"));
+    assert!(patch.len() < long.len());
+    assert_eq!(format_of(&auto, "src/long.rs"), ContentFormat::Diff);
+    assert_eq!(format_of(&auto, "src/tiny.rs"), ContentFormat::Full);
+    assert_eq!(format_of(&auto, "src/new.rs"), ContentFormat::Full);
+
+    let diff = build(PayloadMode::Diff);
+    assert_eq!(format_of(&diff, "src/tiny.rs"), ContentFormat::Diff);
+    assert_eq!(format_of(&diff, "src/new.rs"), ContentFormat::Full);
+
+    let full = build(PayloadMode::Full);
+    assert!(full.files.iter().all(|file| file.format == ContentFormat::Full));
+    assert!(full.total_bytes > auto.total_bytes);
+    assert_eq!("diff".parse::<PayloadMode>().unwrap(), PayloadMode::Diff);
+
+    let edited = long
+        .replace("line 5\n", "line five\n")
+        .replace("line 50\n", "line fifty\n");
+    fs::write(root.join("src/long.rs"), edited).unwrap();
+    let mut current = CacheState::new(root.clone());
+    current.warm_from(&previous, &rules).unwrap();
+    let build = |max_bytes| {
+        let options = PackOptions {
+            max_bytes: Some(max_bytes),
+            pins: vec!["src/long.rs".to_string()],
+            mode: PayloadMode::Diff,
+            ..PackOptions::default()
+        };
+        build_payload_with(previous.diff(&current), &current, &options, Some(&baseline)).unwrap()
+    };
+    let whole = build(usize::MAX);
+    let patch = whole.files[0].content.clone();
+    let second = patch.rfind("\n@@").unwrap() + 1;
+    let cut = build(second + 10);
+    assert_eq!(cut.files[0].path, "src/long.rs");
+    assert!(cut.files[0].truncated);
+    assert_eq!(cut.files[0].content, patch[..second]);
+    let squeezed = build(second / 2);
+    assert!(squeezed.files.iter().all(|file| file.path != "src/long.rs"));
+    assert_eq!(squeezed.dropped[0].path, "src/long.rs");
+}
+
+#[test]
+fn packer_ranks_by_pins_and_relevance_within_token_budget() {
+    let root = temp_root("packer");
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::create_dir_all(root.join("docs")).unwrap();
+    let mut previous = CacheState::new(root.clone());
+    previous.warm().unwrap();
+
+    fs::write(root.join("src/auth.rs"), "fn refresh_token() {}\n// token refresh logic\n").unwrap();
+    fs::write(root.join("docs/notes.md"), "Remember the migration.\n").unwrap();
+    let filler: String = (0..200).map(|line| format!("// filler line {}\n", line)).collect();
+    fs::write(root.join("src/aaa_generated.rs"), &filler).unwrap();
+    fs::write(root.join("src/unicode.rs"), "é".repeat(400)).unwrap();
+    let mut current = CacheState::new(root.clone());
+    current.warm().unwrap();
+
+    let options = PackOptions {
+        max_tokens: 120,
+        query: Some("token refresh?".to_string()),
+        pins: vec!["docs/".to_string()],
+        ..PackOptions::default()
+    };
+    let payload = build_payload_with(previous.diff(&current), &current, &options, None).unwrap();
+    let order: Vec<&str> = payload.files.iter().map(|file| file.path.as_str()).collect();
+    assert_eq!(order[..2], ["docs/notes.md", "src/auth.rs"]);
+    assert!(payload.files[0].reason.contains("pinned"));
+    assert!(payload.files[1].reason.contains("prompt relevance 1.00"));
+    assert!(payload.estimated_tokens <= 120);
+    assert!(payload.truncated);
+    assert_eq!(payload.files.len() + payload.dropped.len(), 4);
+    assert!(payload.dropped.iter().all(|file| file.reason.starts_with("over budget")));
+    for file in payload.files.iter().filter(|file| file.truncated) {
+        assert!(file.reason.ends_with("truncated to fit budget"));
+        assert!(file.content.ends_with('\n') || file.path == "src/unicode.rs");
+    }
+
+    assert_eq!(truncate_to_budget("one\ntwo\nthree\n", 2, 100), "one\ntwo\n");
+    let cut = truncate_to_budget(&"é".repeat(10), 1, 7);
+    assert_eq!(cut, "ééé");
+}
+
+#[test]
+fn repo_map_outlines_symbols_and_reparses_only_changed_files() {
+    let root = temp_root("repomap");
+    for (path, body) in [
+        (
+            "src/lib.rs",
+            "pub mod cache;\npub struct Cache {\n    inner: u8,\n}\nimpl Cache {\n    pub fn new() -> Self { todo!() }\n}\npub(crate) async fn warm() {}\nfn helper() {}\n",
+        ),
+        ("tools/build.py", "import os\n\nclass Builder:\n    def run(self):\n        pass\n\ndef _private():\n    pass\n"),
+        ("web/app.ts", "export interface Props {}\nexport default class App {}\nconst local = 1;\nexport async function mount() {}\n"),
+        ("cmd/main.go", "package main\n\ntype Server struct {}\nfunc (s *Server) Serve() {}\nfunc main() {}\n"),
+        ("README.md", "# Title\n"),
+    ] {
+        fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+        fs::write(root.join(path), body).unwrap();
+    }
+    let mut cache = CacheState::new(root.clone());
+    cache.warm().unwrap();
+    let mut map = RepoMap::default();
+    assert_eq!(map.refresh(&cache), 5);
+
+    let names = |map: &RepoMap, path: &str| -> Vec<String> {
+        map.files[path]
+            .symbols
+            .iter()
+            .map(|symbol| format!("{} {}{}", symbol.kind, symbol.name, if symbol.public { "" } else { " (private)" }))
+            .collect()
+    };
+    assert_eq!(
+        names(&map, "src/lib.rs"),
+        vec!["mod cache", "struct Cache", "impl Cache (private)", "fn warm", "fn helper (private)"]
+    );
+    assert_eq!(map.files["src/lib.rs"].symbols[1].line, 2);
+    assert_eq!(names(&map, "tools/build.py"), vec!["class Builder", "def _private (private)"]);
+    assert_eq!(
+        names(&map, "web/app.ts"),
+        vec!["interface Props", "class App", "const local (private)", "function mount"]
+    );
+    assert_eq!(
+        names(&map, "cmd/main.go"),
+        vec!["struct Server", "method Serve", "func main (private)"]
+    );
+    assert_eq!(map.files["README.md"].language.as_deref(), Some("markdown"));
+    assert_eq!(map.languages()["rust"], 1);
+
+    let rendered = map.render();
+    assert!(rendered.starts_with("5 files (go 1, markdown 1, python 1, rust 1, typescript 1)"));
+    assert!(rendered.contains("\nsrc/\n  lib.rs [rust]: mod cache, struct Cache"));
+    assert_eq!(map.render_within(10_000), rendered);
+
+    let mut large = map.clone();
+    for index in 0..400 {
+        let mut outline = map.files["src/lib.rs"].clone();
+        outline.hash = index.to_string();
+        large
+            .files
+            .insert(format!("src/deep/nested/module_{}.rs", index), outline);
+    }
+    let capped = large.render_within(300);
+    assert!(estimate_tokens(&capped) <= 300, "{}", capped);
+    assert!(capped.contains("\n  deep/\n    nested/ (400 files)"), "{}", capped);
+    assert!(capped.contains("lib.rs [rust]: mod cache, struct Cache, fn warm\n"));
+    assert!(!capped.contains("fn helper"));
+    assert!(large.render_within(5).ends_with("... (map truncated)"));
+
+    fs::write(root.join("tools/build.py"), "def build():\n    pass\n").unwrap();
+    fs::remove_file(root.join("README.md")).unwrap();
+    cache.warm().unwrap();
+    assert_eq!(map.refresh(&cache), 1);
+    assert_eq!(names(&map, "tools/build.py"), vec!["def build"]);
+    assert!(!map.files.contains_key("README.md"));
+
+    let mut handshake = build_handshake(&cache);
+    handshake.map = Some(map.render());
+    let encoded = serde_json::to_string(&handshake).unwrap();
+    let decoded: nexus::context::Handshake = serde_json::from_str(&encoded).unwrap();
+    assert!(decoded.map.unwrap().contains("def build"));
+}
+
+#[test]
+fn handshake_sessions_ack_chain_and_resync_on_divergence() {
+    let root = temp_root("session-sync");
+    let blobs = root.join(".blobs");
+    fs::write(root.join("a.rs"), "fn a() {}\n").unwrap();
+    fs::write(root.join("b.rs"), "fn b() {}\n").unwrap();
+    let rules = IgnoreConfig {
+        exclude: vec![".blobs".to_string()],
+        ..IgnoreConfig::default()
+    };
+    let warm = |previous: Option<&CacheState>| {
+        let mut cache = CacheState::new(root.clone());
+        match previous {
+            Some(previous) => cache.warm_from(previous, &rules).unwrap(),
+            None => cache.warm_with(&rules).unwrap(),
+        };
+        cache
+    };
+    let options = PackOptions {
+        max_tokens: 100_000,
+        ..PackOptions::default()
+    };
+    let mut sync = SessionSync::new("chat-1", &root);
+
+    let first_cache = warm(None);
+    let first = build_session_payload(&mut sync, &first_cache, &options, &blobs, None).unwrap();
+    assert_eq!(first.resync.as_deref(), Some("no acknowledged handshake for this session"));
+    assert_eq!(first.files.len(), 2);
+    assert_eq!(first.sequence, 1);
+    sync.ack(&first.digest).unwrap();
+    assert_eq!(sync.acked_digest(), Some(first.digest.as_str()));
+
+    fs::write(root.join("a.rs"), "fn a() { todo!() }\n").unwrap();
+    let second_cache = warm(Some(&first_cache));
+    let second = build_session_payload(&mut sync, &second_cache, &options, &blobs, None).unwrap();
+    assert!(second.resync.is_none());
+    assert_eq!(second.base_digest.as_deref(), Some(first.digest.as_str()));
+    assert_eq!(second.changed, vec!["a.rs".to_string()]);
+    assert_ne!(second.digest, first.digest);
+
+    assert!(sync.ack("bogus").is_err());
+    let third = build_session_payload(&mut sync, &second_cache, &options, &blobs, None).unwrap();
+    assert!(third.resync.unwrap().contains("bogus"));
+    assert_eq!(third.files.len(), 2);
+
+    fs::write(root.join("b.rs"), "fn b() { todo!() }\n").unwrap();
+    let fourth_cache = warm(Some(&second_cache));
+    let fourth =
+        build_session_payload(&mut sync, &fourth_cache, &options, &blobs, Some(&third.digest))
+            .unwrap();
+    assert_eq!(fourth.base_digest.as_deref(), Some(third.digest.as_str()));
+    assert_eq!(fourth.changed, vec!["b.rs".to_string()]);
+    assert_eq!(fourth.sequence, 4);
+
+    let stale = build_session_payload(&mut sync, &fourth_cache, &options, &blobs, Some("old"));
+    assert!(stale.unwrap().resync.unwrap().contains("old"));
+    fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn session_payload_resends_files_left_out_by_the_budget() {
+    let root = temp_root("session-budget");
+    let blobs = temp_root("session-budget-blobs");
+    fs::write(root.join("a.rs"), "fn a() {}\n").unwrap();
+    let filler: String = (0..200).map(|line| format!("// line {}\n", line)).collect();
+    fs::write(root.join("big.rs"), &filler).unwrap();
+    fs::write(root.join("huge.rs"), filler.repeat(2)).unwrap();
+    let mut cache = CacheState::new(root.clone());
+    cache.warm().unwrap();
+    let mut sync = SessionSync::new("budget", &root);
+
+    let tight = PackOptions {
+        max_tokens: 60,
+        ..PackOptions::default()
+    };
+    let first = build_session_payload(&mut sync, &cache, &tight, &blobs, None).unwrap();
+    let mut missing: Vec<String> = first
+        .dropped
+        .iter()
+        .map(|file| file.path.clone())
+        .chain(first.files.iter().filter(|file| file.truncated).map(|file| file.path.clone()))
+        .collect();
+    missing.sort();
+    assert_eq!(missing, vec!["big.rs", "huge.rs"]);
+    sync.ack(&first.digest).unwrap();
+
+    let roomy = PackOptions {
+        max_tokens: 100_000,
+        ..PackOptions::default()
+    };
+    let second = build_session_payload(&mut sync, &cache, &roomy, &blobs, None).unwrap();
+    assert!(second.resync.is_none());
+    assert_ne!(second.digest, first.digest);
+    assert_eq!(second.changed, missing);
+    assert!(second.files.iter().all(|file| !file.truncated));
+    sync.ack(&second.digest).unwrap();
+
+    let third = build_session_payload(&mut sync, &cache, &roomy, &blobs, None).unwrap();
+    assert!(third.changed.is_empty());
+    fs::remove_dir_all(&root).ok();
+    fs::remove_dir_all(&blobs).ok();
+}
+
+#[test]
+fn payload_classifies_files_and_sends_placeholders_by_policy() {
+    let root = temp_root("classify");
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::create_dir_all(root.join("vendor/lib")).unwrap();
+    fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+    fs::write(root.join("src/api.rs"), "// @generated by protoc\npub struct Api;\n").unwrap();
+    fs::write(root.join("logo.png"), [0x89, b'P', b'N', b'G', 0, 0, 0, 13]).unwrap();
+    fs::write(root.join("Cargo.lock"), "version = 3\n").unwrap();
+    fs::write(root.join("app.js"), "var a=1;".repeat(400)).unwrap();
+    fs::write(root.join("vendor/lib/dep.rs"), "fn dep() {}\n").unwrap();
+    fs::write(root.join("big.txt"), "x\n".repeat(600 * 1024)).unwrap();
+    let mut cache = CacheState::new(root.clone());
+    cache.warm().unwrap();
+    let class_of = |path: &str| cache.files[path].class();
+    assert_eq!(class_of("src/main.rs"), FileClass::Text);
+    assert_eq!(class_of("src/api.rs"), FileClass::Generated);
+    assert_eq!(class_of("logo.png"), FileClass::Binary);
+    assert_eq!(class_of("Cargo.lock"), FileClass::Lockfile);
+    assert_eq!(class_of("app.js"), FileClass::Minified);
+    assert_eq!(class_of("vendor/lib/dep.rs"), FileClass::Vendored);
+    assert_eq!(class_of("big.txt"), FileClass::TooLarge);
+    assert_eq!("too_large".parse::<FileClass>().unwrap(), FileClass::TooLarge);
+
+    let build = |policies: ClassPolicies| {
+        let options = PackOptions {
+            max_tokens: usize::MAX,
+            policies,
+            ..PackOptions::default()
+        };
+        build_payload_with(CacheState::new(root.clone()).diff(&cache), &cache, &options, None)
+            .unwrap()
+    };
+    let payload = build(ClassPolicies::default());
+    let sent: Vec<&str> = payload.files.iter().map(|file| file.path.as_str()).collect();
+    assert_eq!(sent, vec!["src/main.rs"]);
+    let placeholder = payload
+        .placeholders
+        .iter()
+        .find(|placeholder| placeholder.path == "big.txt")
+        .unwrap();
+    assert_eq!(placeholder.class, FileClass::TooLarge);
+    assert_eq!(placeholder.size, 2 * 600 * 1024);
+    assert_eq!(placeholder.hash, cache.files["big.txt"].hash);
+    assert_eq!(payload.placeholders.len(), 5);
+    assert_eq!(payload.dropped.len(), 1);
+    assert!(payload.dropped[0].reason.contains("vendored"));
+
+    let relaxed = build(ClassPolicies {
+        generated: ClassPolicy::Include,
+        binary: ClassPolicy::Include,
+        lockfile: ClassPolicy::Skip,
+        ..ClassPolicies::default()
+    });
+    assert!(relaxed.files.iter().any(|file| file.path == "src/api.rs"));
+    assert!(relaxed
+        .placeholders
+        .iter()
+        .any(|placeholder| placeholder.path == "logo.png" && placeholder.class == FileClass::Binary));
+    assert!(relaxed.dropped.iter().any(|file| file.path == "Cargo.lock"));
+    fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn state_files_migrate_from_json_to_versioned_binary() {
+    let root = temp_root("binary-state");
+    fs::write(root.join("a.rs"), "fn a() {}\n").unwrap();
+    let mut cache = CacheState::new(root.clone());
+    cache.warm().unwrap();
+    let events = vec![SwarmEvent {
+        timestamp: 7,
+        event: "plan".to_string(),
+        detail: "split work".to_string(),
+    }];
+    fs::write(root.join("cache.json"), serde_json::to_string_pretty(&cache).unwrap()).unwrap();
+    fs::write(root.join("swarm-events.json"), serde_json::to_string(&events).unwrap()).unwrap();
+
+    let migrated = load_cache(&root.join("cache.bin")).unwrap();
+    assert_eq!(migrated.files["a.rs"].hash, cache.files["a.rs"].hash);
+    assert!(!root.join("cache.json").exists());
+    let raw = fs::read(root.join("cache.bin")).unwrap();
+    assert!(raw.starts_with(b"NXST"));
+    assert_eq!(load_cache(&root.join("cache.bin")).unwrap().files.len(), 1);
+    let loaded = load_swarm_events(&root.join("swarm-events.bin")).unwrap();
+    assert_eq!(loaded[0].detail, "split work");
+    assert!(root.join("swarm-events.bin").exists());
+
+    let snapshot = VectorStoreSnapshot {
+        documents: vec![VectorDocument {
+            id: "doc".to_string(),
+            content: "Document".to_string(),
+            embedding: embed("Document"),
+            metadata: [("path".to_string(), "a.rs".to_string())].into(),
+        }],
+    };
+    let vector_path = root.join("vector-store.bin");
+    fs::write(root.join("vector-store.bin.tmp"), b"NXST").unwrap();
+    save_vector_store(&snapshot, &vector_path).unwrap();
+    assert!(!root.join("vector-store.bin.tmp").exists());
+    let loaded = load_vector_store(&vector_path).unwrap();
+    assert_eq!(loaded.documents[0].embedding, snapshot.documents[0].embedding);
+    assert_eq!(loaded.documents[0].metadata["path"], "a.rs");
+
+    let mut future = raw.clone();
+    future[4..8].copy_from_slice(&99u32.to_le_bytes());
+    fs::write(root.join("cache.bin"), future).unwrap();
+    let err = load_cache(&root.join("cache.bin")).unwrap_err();
+    assert!(err.to_string().contains("version 99"));
+
+    let mut corrupt = raw.clone();
+    corrupt.truncate(raw.len() - 3);
+    fs::write(root.join("cache.bin"), corrupt).unwrap();
+    let err = load_cache(&root.join("cache.bin")).unwrap_err();
+    assert!(err.to_string().contains("Corrupt state file"));
+    assert!(err.to_string().contains("cache.bin"));
+
+    fs::write(root.join("events.json"), "[{\"timestamp\": \"soon\"}]").unwrap();
+    let err = load_swarm_events(&root.join("events.bin")).unwrap_err();
+    assert!(err.to_string().contains("events.json"));
+    assert!(root.join("events.json").exists());
+    assert!(!root.join("events.bin").exists());
+    fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn state_layout_matches_state_version() {
+    let cache = CacheState {
+        root: PathBuf::from("/repo"),
+        files: [(
+            "a.rs".to_string(),
+            FileMeta {
+                modified: Some(1),
+                size: 2,
+                hash: "h".to_string(),
+                modified_ns: Some(3),
+                inode: Some(4),
+                class: Some(FileClass::Generated),
+            },
+        )]
+        .into(),
+    };
+    let events = vec![SwarmEvent {
+        timestamp: 5,
+        event: "plan".to_string(),
+        detail: "d".to_string(),
+    }];
+    let snapshot = VectorStoreSnapshot {
+        documents: vec![VectorDocument {
+            id: "doc".to_string(),
+            content: "c".to_string(),
+            embedding: vec![0.5],
+            metadata: [("path".to_string(), "a.rs".to_string())].into(),
+        }],
+    };
+    let mut encoded = encode_state(&cache).unwrap();
+    encoded.extend(encode_state(&events).unwrap());
+    encoded.extend(encode_state(&snapshot).unwrap());
+    // A persisted struct changed shape: bump STATE_VERSION, then update this fingerprint.
+    assert_eq!(
+        blake3::hash(&encoded).to_hex().as_str(),
+        "db655a2ad41d9bfa21566d97adc224636eb6e907e739b08751c618d8905bf452"
+    );
+}